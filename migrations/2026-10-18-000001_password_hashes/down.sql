@@ -0,0 +1,3 @@
+ALTER TABLE users MODIFY password CHAR(128) NOT NULL;
+ALTER TABLE doctors MODIFY password CHAR(128) NOT NULL;
+ALTER TABLE administrators MODIFY password CHAR(128) NOT NULL;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE users MODIFY password VARCHAR(255) NOT NULL;
+ALTER TABLE doctors MODIFY password VARCHAR(255) NOT NULL;
+ALTER TABLE administrators MODIFY password VARCHAR(255) NOT NULL;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE user_logins DROP last_active, DROP refresh_token;
+ALTER TABLE doctor_logins DROP last_active, DROP refresh_token;
+ALTER TABLE admin_logins DROP last_active, DROP refresh_token;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE user_logins ADD last_active DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, ADD refresh_token CHAR(128) NOT NULL DEFAULT '';
+ALTER TABLE doctor_logins ADD last_active DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, ADD refresh_token CHAR(128) NOT NULL DEFAULT '';
+ALTER TABLE admin_logins ADD last_active DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, ADD refresh_token CHAR(128) NOT NULL DEFAULT '';
\ No newline at end of file
@@ -0,0 +1,3 @@
+DELETE FROM user_logins;
+DELETE FROM doctor_logins;
+DELETE FROM admin_logins;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE administrators DROP role, DROP department;
\ No newline at end of file
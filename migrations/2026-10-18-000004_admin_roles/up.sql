@@ -0,0 +1,2 @@
+ALTER TABLE administrators ADD role CHAR(16) NOT NULL DEFAULT 'super_admin', ADD department CHAR(32);
+ALTER TABLE administrators ALTER role DROP DEFAULT;
\ No newline at end of file
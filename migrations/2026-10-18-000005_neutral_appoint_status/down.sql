@@ -0,0 +1 @@
+UPDATE appointments SET status = CASE status WHEN 'unfinished' THEN '未完成' WHEN 'finished' THEN '已完成' WHEN 'canceled' THEN '已取消' ELSE status END;
\ No newline at end of file
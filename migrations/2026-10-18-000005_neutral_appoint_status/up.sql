@@ -0,0 +1 @@
+UPDATE appointments SET status = CASE status WHEN '未完成' THEN 'unfinished' WHEN '已完成' THEN 'finished' WHEN '已取消' THEN 'canceled' ELSE status END;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE times DROP sid;
+DROP TABLE schedule_exceptions;
+DROP TABLE schedules;
\ No newline at end of file
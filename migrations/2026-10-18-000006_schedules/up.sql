@@ -0,0 +1,17 @@
+CREATE TABLE schedules (
+    sid SERIAL,
+    did CHAR(32) NOT NULL,
+    weekday INT NOT NULL,
+    start_time TIME NOT NULL,
+    end_time TIME NOT NULL,
+    capacity INT NOT NULL,
+    start_date DATE NOT NULL,
+    end_date DATE,
+    PRIMARY KEY (sid)
+);
+CREATE TABLE schedule_exceptions (
+    sid BIGINT UNSIGNED NOT NULL,
+    date DATE NOT NULL,
+    PRIMARY KEY (sid, date)
+);
+ALTER TABLE times ADD sid BIGINT UNSIGNED;
\ No newline at end of file
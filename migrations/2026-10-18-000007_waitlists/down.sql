@@ -0,0 +1 @@
+DROP TABLE waitlists;
\ No newline at end of file
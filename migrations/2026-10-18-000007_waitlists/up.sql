@@ -0,0 +1,8 @@
+CREATE TABLE waitlists (
+    wid SERIAL,
+    username CHAR(32) NOT NULL,
+    tid BIGINT UNSIGNED NOT NULL,
+    time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (wid),
+    UNIQUE (username, tid)
+);
\ No newline at end of file
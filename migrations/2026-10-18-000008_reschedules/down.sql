@@ -0,0 +1 @@
+DROP TABLE reschedules;
\ No newline at end of file
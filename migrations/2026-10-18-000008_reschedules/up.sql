@@ -0,0 +1,8 @@
+CREATE TABLE reschedules (
+    rid SERIAL,
+    username CHAR(32) NOT NULL,
+    from_tid BIGINT UNSIGNED NOT NULL,
+    to_tid BIGINT UNSIGNED NOT NULL,
+    time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (rid)
+);
\ No newline at end of file
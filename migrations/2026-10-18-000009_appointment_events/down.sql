@@ -0,0 +1 @@
+DROP TABLE appointment_events;
\ No newline at end of file
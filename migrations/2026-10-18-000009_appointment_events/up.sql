@@ -0,0 +1,11 @@
+CREATE TABLE appointment_events (
+    eid SERIAL,
+    username CHAR(32) NOT NULL,
+    tid BIGINT UNSIGNED NOT NULL,
+    from_status CHAR(16),
+    to_status CHAR(16) NOT NULL,
+    actor_kind CHAR(16) NOT NULL,
+    actor_id CHAR(32),
+    time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (eid)
+);
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE users DROP penalty_cleared_at;
\ No newline at end of file
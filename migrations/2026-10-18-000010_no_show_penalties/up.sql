@@ -0,0 +1 @@
+ALTER TABLE users ADD penalty_cleared_at DATETIME;
\ No newline at end of file
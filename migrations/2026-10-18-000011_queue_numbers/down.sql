@@ -0,0 +1,2 @@
+ALTER TABLE times DROP issued_number, DROP serving_number, DROP called_at;
+ALTER TABLE appointments DROP queue_number, DROP called_at, DROP skipped;
\ No newline at end of file
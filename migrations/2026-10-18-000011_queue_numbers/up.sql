@@ -0,0 +1,4 @@
+ALTER TABLE appointments ADD queue_number INT NOT NULL DEFAULT 0, ADD called_at DATETIME, ADD skipped BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE times ADD issued_number INT NOT NULL DEFAULT 0, ADD serving_number INT, ADD called_at DATETIME;
+UPDATE appointments a JOIN (SELECT username, tid, ROW_NUMBER() OVER (PARTITION BY tid ORDER BY time, username) AS n FROM appointments) q ON a.username = q.username AND a.tid = q.tid SET a.queue_number = q.n;
+UPDATE times t SET issued_number = (SELECT COUNT(*) FROM appointments a WHERE a.tid = t.tid);
\ No newline at end of file
@@ -0,0 +1,2 @@
+DROP TABLE notification_outbox;
+DROP TABLE notification_preferences;
\ No newline at end of file
@@ -0,0 +1,25 @@
+CREATE TABLE notification_preferences (
+    username CHAR(32) NOT NULL,
+    email VARCHAR(255),
+    email_enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    sms_enabled BOOLEAN NOT NULL DEFAULT FALSE,
+    reminders_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    locale CHAR(8) NOT NULL DEFAULT 'zh-CN',
+    PRIMARY KEY (username)
+);
+CREATE TABLE notification_outbox (
+    nid SERIAL,
+    username CHAR(32) NOT NULL,
+    tid BIGINT UNSIGNED,
+    kind CHAR(16) NOT NULL,
+    channel CHAR(16) NOT NULL,
+    recipient VARCHAR(255) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    body TEXT NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sent_at DATETIME,
+    last_error TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (nid)
+);
\ No newline at end of file
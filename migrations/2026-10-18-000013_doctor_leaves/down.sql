@@ -0,0 +1,3 @@
+ALTER TABLE times DROP COLUMN available;
+
+DROP TABLE doctor_leaves;
\ No newline at end of file
@@ -0,0 +1,13 @@
+CREATE TABLE doctor_leaves (
+    lid SERIAL,
+    did CHAR(32) NOT NULL,
+    start_time DATETIME NOT NULL,
+    end_time DATETIME NOT NULL,
+    reason VARCHAR(255) NOT NULL DEFAULT '',
+    actor_kind CHAR(16) NOT NULL,
+    actor_id CHAR(32),
+    time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (lid)
+);
+
+ALTER TABLE times ADD COLUMN available BOOL NOT NULL DEFAULT TRUE;
\ No newline at end of file
@@ -0,0 +1,5 @@
+DROP TABLE doctor_ratings;
+
+DROP INDEX comments_appointment ON comments;
+ALTER TABLE comments DROP COLUMN rating;
+ALTER TABLE comments DROP COLUMN tid;
\ No newline at end of file
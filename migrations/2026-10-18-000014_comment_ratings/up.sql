@@ -0,0 +1,10 @@
+ALTER TABLE comments ADD COLUMN tid BIGINT UNSIGNED;
+ALTER TABLE comments ADD COLUMN rating INT;
+CREATE UNIQUE INDEX comments_appointment ON comments (username, tid);
+
+CREATE TABLE doctor_ratings (
+    did CHAR(32) NOT NULL,
+    rating_total INT NOT NULL,
+    rating_count INT NOT NULL,
+    PRIMARY KEY (did)
+);
\ No newline at end of file
@@ -0,0 +1,6 @@
+DROP TABLE comment_reports;
+
+ALTER TABLE comments DROP COLUMN status_time;
+ALTER TABLE comments DROP COLUMN status_actor_id;
+ALTER TABLE comments DROP COLUMN status_actor_kind;
+ALTER TABLE comments DROP COLUMN status;
\ No newline at end of file
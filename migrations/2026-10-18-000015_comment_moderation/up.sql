@@ -0,0 +1,16 @@
+ALTER TABLE comments ADD COLUMN status CHAR(16) NOT NULL DEFAULT 'visible';
+ALTER TABLE comments ADD COLUMN status_actor_kind CHAR(16);
+ALTER TABLE comments ADD COLUMN status_actor_id CHAR(32);
+ALTER TABLE comments ADD COLUMN status_time DATETIME;
+
+CREATE TABLE comment_reports (
+    rpid SERIAL,
+    cid BIGINT UNSIGNED NOT NULL,
+    reporter_kind CHAR(16) NOT NULL,
+    reporter_id CHAR(32) NOT NULL,
+    reason VARCHAR(256) NOT NULL,
+    time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    resolved BOOL NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (rpid),
+    UNIQUE (cid, reporter_kind, reporter_id)
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE comment_replies;
\ No newline at end of file
@@ -0,0 +1,8 @@
+CREATE TABLE comment_replies (
+    cid BIGINT UNSIGNED NOT NULL,
+    did CHAR(32) NOT NULL,
+    reply VARCHAR(256) NOT NULL,
+    time DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    modified_time DATETIME,
+    PRIMARY KEY (cid)
+);
\ No newline at end of file
@@ -0,0 +1,4 @@
+ALTER TABLE departments DROP COLUMN retired;
+ALTER TABLE departments DROP COLUMN parent;
+DROP INDEX departments_name ON departments;
+ALTER TABLE departments DROP PRIMARY KEY, DROP COLUMN dpid, ADD PRIMARY KEY (depart_name);
\ No newline at end of file
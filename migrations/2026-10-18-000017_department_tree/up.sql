@@ -0,0 +1,4 @@
+ALTER TABLE departments DROP PRIMARY KEY, ADD COLUMN dpid SERIAL FIRST, ADD PRIMARY KEY (dpid);
+CREATE UNIQUE INDEX departments_name ON departments (depart_name);
+ALTER TABLE departments ADD COLUMN parent BIGINT UNSIGNED;
+ALTER TABLE departments ADD COLUMN retired BOOL NOT NULL DEFAULT FALSE;
\ No newline at end of file
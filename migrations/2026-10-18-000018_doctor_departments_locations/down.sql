@@ -0,0 +1,7 @@
+ALTER TABLE schedules DROP COLUMN lcid;
+ALTER TABLE schedules DROP COLUMN dpid;
+ALTER TABLE times DROP COLUMN lcid;
+ALTER TABLE times DROP COLUMN dpid;
+
+DROP TABLE locations;
+DROP TABLE doctor_departments;
\ No newline at end of file
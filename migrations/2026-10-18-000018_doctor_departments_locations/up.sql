@@ -0,0 +1,31 @@
+CREATE TABLE doctor_departments (
+    did CHAR(32) NOT NULL,
+    dpid BIGINT UNSIGNED NOT NULL,
+    PRIMARY KEY (did, dpid)
+);
+
+INSERT INTO doctor_departments (did, dpid)
+    SELECT doctors.did, departments.dpid FROM doctors
+    INNER JOIN departments ON departments.depart_name = doctors.department;
+
+CREATE TABLE locations (
+    lcid SERIAL,
+    name CHAR(32) NOT NULL,
+    address VARCHAR(256) NOT NULL DEFAULT '',
+    PRIMARY KEY (lcid),
+    UNIQUE (name)
+);
+
+ALTER TABLE times ADD COLUMN dpid BIGINT UNSIGNED;
+ALTER TABLE times ADD COLUMN lcid BIGINT UNSIGNED;
+UPDATE times
+    INNER JOIN doctors ON doctors.did = times.did
+    INNER JOIN departments ON departments.depart_name = doctors.department
+    SET times.dpid = departments.dpid;
+
+ALTER TABLE schedules ADD COLUMN dpid BIGINT UNSIGNED;
+ALTER TABLE schedules ADD COLUMN lcid BIGINT UNSIGNED;
+UPDATE schedules
+    INNER JOIN doctors ON doctors.did = schedules.did
+    INNER JOIN departments ON departments.depart_name = doctors.department
+    SET schedules.dpid = departments.dpid;
\ No newline at end of file
@@ -0,0 +1,13 @@
+ALTER TABLE administrators DROP FOREIGN KEY administrators_dpid;
+ALTER TABLE administrators ADD COLUMN department CHAR(32) AFTER dpid;
+UPDATE administrators
+    INNER JOIN departments ON departments.dpid = administrators.dpid
+    SET administrators.department = departments.depart_name;
+ALTER TABLE administrators DROP COLUMN dpid;
+
+ALTER TABLE doctors DROP FOREIGN KEY doctors_dpid;
+ALTER TABLE doctors ADD COLUMN department CHAR(32) NOT NULL DEFAULT '' AFTER dpid;
+UPDATE doctors
+    INNER JOIN departments ON departments.dpid = doctors.dpid
+    SET doctors.department = departments.depart_name;
+ALTER TABLE doctors ALTER department DROP DEFAULT, DROP COLUMN dpid;
\ No newline at end of file
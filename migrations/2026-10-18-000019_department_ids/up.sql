@@ -0,0 +1,23 @@
+INSERT INTO departments (depart_name, information)
+    SELECT DISTINCT department, '' FROM doctors
+    WHERE department NOT IN (SELECT depart_name FROM departments);
+INSERT INTO departments (depart_name, information)
+    SELECT DISTINCT department, '' FROM administrators
+    WHERE department IS NOT NULL AND department NOT IN (SELECT depart_name FROM departments);
+INSERT IGNORE INTO doctor_departments (did, dpid)
+    SELECT doctors.did, departments.dpid FROM doctors
+    INNER JOIN departments ON departments.depart_name = doctors.department;
+
+ALTER TABLE doctors ADD COLUMN dpid BIGINT UNSIGNED AFTER department;
+UPDATE doctors
+    INNER JOIN departments ON departments.depart_name = doctors.department
+    SET doctors.dpid = departments.dpid;
+ALTER TABLE doctors MODIFY dpid BIGINT UNSIGNED NOT NULL, DROP COLUMN department,
+    ADD CONSTRAINT doctors_dpid FOREIGN KEY (dpid) REFERENCES departments (dpid);
+
+ALTER TABLE administrators ADD COLUMN dpid BIGINT UNSIGNED AFTER department;
+UPDATE administrators
+    INNER JOIN departments ON departments.depart_name = administrators.department
+    SET administrators.dpid = departments.dpid;
+ALTER TABLE administrators DROP COLUMN department,
+    ADD CONSTRAINT administrators_dpid FOREIGN KEY (dpid) REFERENCES departments (dpid);
\ No newline at end of file
@@ -1,32 +1,52 @@
+mod permission;
 mod requests;
 mod responses;
-mod utils;
 
 use crate::{
+    auth::AuthedAdmin,
+    booking::{self, Actor},
+    config::Config,
     database::{assert, get_db_conn},
+    depart,
+    error::ApiError,
+    events::Broker,
+    leave, location,
     models::{
-        admin_logins::AdminLoginData,
-        administrators::AdminData,
-        comments::Comment,
-        departments::DepartData,
+        administrators::{AdminData, AdminRole},
+        appointment_events::AppointEvent,
+        appointments::AppointStatus,
+        comments::{Comment, CommentStatus},
         doctors::{DoctorData, UpdateDoctor},
+        locations::UpdateLocation,
         users::{UpdateUser, UserData},
     },
-    protocol::SimpleResponse,
+    notify, penalty,
+    protocol::{
+        AddLeaveResponse, AddScheduleResponse, ModifyScheduleResponse, SearchDepartResponse,
+        SearchLeaveResponse, SearchLocationResponse, SearchScheduleResponse, SimpleResponse,
+    },
+    review::{self, DoctorSort},
+    schedule::{self, ScheduleInput},
     DbPool,
 };
-use actix_web::{post, web, HttpResponse, Responder};
+use actix::Addr;
+use actix_web::{post, web, HttpRequest, HttpResponse};
 use anyhow::{bail, Context};
-use blake2::{Blake2b, Digest};
 use chrono::{Datelike, NaiveDate, Utc};
 use diesel::prelude::*;
 
-use self::{requests::*, responses::*, utils::get_aid_from_token};
+use self::{requests::*, responses::*};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(register)
         .service(login)
         .service(logout)
+        .service(logout_all)
+        .route(
+            "/refresh",
+            web::post().to(crate::auth::refresh::<AuthedAdmin>),
+        )
+        .service(modify_password)
         .service(add_doctor)
         .service(search_doctor)
         .service(view_doctor)
@@ -34,41 +54,137 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(add_depart)
         .service(search_depart)
         .service(modify_depart)
+        .service(rename_depart)
+        .service(move_depart)
+        .service(merge_depart)
+        .service(retire_depart)
+        .service(add_doctor_depart)
+        .service(remove_doctor_depart)
+        .service(add_location)
+        .service(modify_location)
+        .service(search_location)
         .service(search_comment)
         .service(delete_comment)
+        .service(search_moderation)
+        .service(moderate_comment)
         .service(search_user)
         .service(view_user)
         .service(ban_user)
-        .service(modify_user);
+        .service(clear_penalty)
+        .service(modify_user)
+        .service(add_schedule)
+        .service(modify_schedule)
+        .service(delete_schedule)
+        .service(search_schedule)
+        .service(update_appoint_status)
+        .service(search_appoint_event)
+        .service(add_leave)
+        .service(delete_leave)
+        .service(search_leave);
+}
+
+/// Creates a super-admin from `ADMIN_BOOTSTRAP_ID` / `ADMIN_BOOTSTRAP_PASSWORD`
+/// when there is no administrator yet, since `/register` needs a logged-in super-admin.
+pub fn bootstrap(pool: &DbPool) -> anyhow::Result<()> {
+    use crate::schema::administrators;
+
+    let (aid, password) = match (
+        std::env::var("ADMIN_BOOTSTRAP_ID"),
+        std::env::var("ADMIN_BOOTSTRAP_PASSWORD"),
+    ) {
+        (Ok(aid), Ok(password)) => (aid, password),
+        _ => return Ok(()),
+    };
+
+    let conn = pool.get().context(ApiError::Database)?;
+    conn.transaction(|| {
+        let res = administrators::table
+            .count()
+            .get_result::<i64>(&conn)
+            .context(ApiError::Database)?;
+        if res > 0 {
+            return Ok(());
+        }
+
+        let data = AdminData {
+            aid,
+            password: crate::password::hash_password(&password)?,
+            role: AdminRole::SuperAdmin.as_str().to_string(),
+            dpid: None,
+        };
+        diesel::insert_into(administrators::table)
+            .values(data)
+            .execute(&conn)
+            .context(ApiError::Database)?;
+
+        Ok(())
+    })
 }
 
 crate::post_funcs! {
-    (register, "/register", RegisterRequest, SimpleResponse),
+    (register, "/register", RegisterRequest, SimpleResponse, AuthedAdmin),
     (login, "/login", LoginRequest, LoginResponse),
-    (logout, "/logout", LogoutRequest, SimpleResponse),
-    (modify_password, "/modify_password", ModifyPasswordRequest, SimpleResponse),
-    (add_doctor, "/add_doctor", AddDoctorRequest, SimpleResponse),
-    (search_doctor, "/search_doctor", SearchDoctorRequest, SearchDoctorResponse),
-    (view_doctor, "/view_doctor", ViewDoctorRequest, ViewDoctorResponse),
-    (modify_doctor, "/modify_doctor", ModifyDoctorRequest, SimpleResponse),
-    (add_depart, "/add_depart", AddDepartRequst, SimpleResponse),
-    (search_depart, "/search_depart", SearchDepartRequest, SearchDepartResponse),
-    (modify_depart, "/modify_depart", ModifyDepartRequest, SimpleResponse),
-    (search_comment, "/search_comment", SearchCommentRequest, SearchCommentResponse),
-    (delete_comment, "/delete_comment", DeleteCommentRequest, SimpleResponse),
-    (search_user, "/search_user", SearchUserRequest, SearchUserResponse),
-    (view_user, "/view_user", ViewUserRequest, ViewUserResponse),
-    (ban_user, "/ban_user", BanUserRequest, SimpleResponse),
-    (modify_user, "/modify_user", ModifyUserRequest, SimpleResponse),
+    (logout, "/logout", LogoutRequest, SimpleResponse, AuthedAdmin),
+    (logout_all, "/logout_all", LogoutAllRequest, SimpleResponse, AuthedAdmin),
+    (modify_password, "/modify_password", ModifyPasswordRequest, SimpleResponse, AuthedAdmin),
+    (add_doctor, "/add_doctor", AddDoctorRequest, SimpleResponse, AuthedAdmin),
+    (search_doctor, "/search_doctor", SearchDoctorRequest, SearchDoctorResponse, AuthedAdmin),
+    (view_doctor, "/view_doctor", ViewDoctorRequest, ViewDoctorResponse, AuthedAdmin),
+    (modify_doctor, "/modify_doctor", ModifyDoctorRequest, SimpleResponse, AuthedAdmin),
+    (add_depart, "/add_depart", AddDepartRequst, AddDepartResponse, AuthedAdmin),
+    (search_depart, "/search_depart", SearchDepartRequest, SearchDepartResponse, AuthedAdmin),
+    (modify_depart, "/modify_depart", ModifyDepartRequest, SimpleResponse, AuthedAdmin),
+    (rename_depart, "/rename_depart", RenameDepartRequest, SimpleResponse, AuthedAdmin),
+    (move_depart, "/move_depart", MoveDepartRequest, SimpleResponse, AuthedAdmin),
+    (merge_depart, "/merge_depart", MergeDepartRequest, MergeDepartResponse, AuthedAdmin),
+    (retire_depart, "/retire_depart", RetireDepartRequest, SimpleResponse, AuthedAdmin),
+    (add_doctor_depart, "/add_doctor_depart", DoctorDepartRequest, SimpleResponse, AuthedAdmin),
+    (remove_doctor_depart, "/remove_doctor_depart", DoctorDepartRequest, SimpleResponse, AuthedAdmin),
+    (add_location, "/add_location", AddLocationRequest, AddLocationResponse, AuthedAdmin),
+    (modify_location, "/modify_location", ModifyLocationRequest, SimpleResponse, AuthedAdmin),
+    (search_location, "/search_location", SearchLocationRequest, SearchLocationResponse, AuthedAdmin),
+    (search_comment, "/search_comment", SearchCommentRequest, SearchCommentResponse, AuthedAdmin),
+    (delete_comment, "/delete_comment", DeleteCommentRequest, SimpleResponse, AuthedAdmin),
+    (search_moderation, "/search_moderation", SearchModerationRequest, SearchModerationResponse, AuthedAdmin),
+    (moderate_comment, "/moderate_comment", ModerateCommentRequest, SimpleResponse, AuthedAdmin),
+    (search_user, "/search_user", SearchUserRequest, SearchUserResponse, AuthedAdmin),
+    (view_user, "/view_user", ViewUserRequest, ViewUserResponse, AuthedAdmin),
+    (ban_user, "/ban_user", BanUserRequest, SimpleResponse, AuthedAdmin),
+    (clear_penalty, "/clear_penalty", ClearPenaltyRequest, SimpleResponse, AuthedAdmin),
+    (modify_user, "/modify_user", ModifyUserRequest, SimpleResponse, AuthedAdmin),
+    (add_schedule, "/add_schedule", AddScheduleRequest, AddScheduleResponse, AuthedAdmin),
+    (modify_schedule, "/modify_schedule", ModifyScheduleRequest, ModifyScheduleResponse, AuthedAdmin),
+    (delete_schedule, "/delete_schedule", DeleteScheduleRequest, SimpleResponse, AuthedAdmin),
+    (search_schedule, "/search_schedule", SearchScheduleRequest, SearchScheduleResponse, AuthedAdmin),
+    (update_appoint_status, "/update_appoint_status", UpdateAppointStatusRequest, SimpleResponse, AuthedAdmin, events),
+    (search_appoint_event, "/search_appoint_event", SearchAppointEventRequest, SearchAppointEventResponse, AuthedAdmin),
+    (add_leave, "/add_leave", AddLeaveRequest, AddLeaveResponse, AuthedAdmin, events),
+    (delete_leave, "/delete_leave", DeleteLeaveRequest, SimpleResponse, AuthedAdmin, events),
+    (search_leave, "/search_leave", SearchLeaveRequest, SearchLeaveResponse, AuthedAdmin),
 }
 
 async fn register_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<RegisterRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::administrators;
 
-    let info = info.into_inner();
+    admin.assert_super()?;
+
+    let mut info = info.into_inner();
+    let role = AdminRole::parse(&info.role)?;
+    let department = match role {
+        AdminRole::DepartAdmin => match info.depart.take() {
+            Some(depart) => {
+                assert::assert_depart(&pool, depart.clone()).await?;
+                Some(depart)
+            }
+            None => bail!(ApiError::DepartRequired),
+        },
+        _ => None,
+    };
 
     let conn = get_db_conn(&pool)?;
     web::block(move || {
@@ -77,20 +193,26 @@ async fn register_impl(
                 .filter(administrators::aid.eq(&info.aid))
                 .count()
                 .get_result::<i64>(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
             if res > 0 {
-                bail!("ID 重复");
+                bail!(ApiError::DuplicateId);
             }
 
-            let hashed_password = format!("{:x}", Blake2b::digest(info.password.as_bytes()));
+            let hashed_password = crate::password::hash_password(&info.password)?;
+            let dpid = match &department {
+                Some(depart) => Some(depart::id_of(&conn, depart)?),
+                None => None,
+            };
             let data = AdminData {
                 aid: info.aid,
                 password: hashed_password,
+                role: role.as_str().to_string(),
+                dpid,
             };
             diesel::insert_into(administrators::table)
                 .values(data)
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
 
             Ok(())
         })
@@ -102,40 +224,31 @@ async fn register_impl(
 
 async fn login_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
     info: web::Json<LoginRequest>,
 ) -> anyhow::Result<LoginResponse> {
-    use crate::schema::{admin_logins, administrators};
+    use crate::schema::administrators;
 
     let info = info.into_inner();
     assert::assert_admin(&pool, info.aid.clone()).await?;
 
     let conn = get_db_conn(&pool)?;
-    let login_token = web::block(move || {
-        conn.transaction(|| {
-            let hashed_password = format!("{:x}", Blake2b::digest(info.password.as_bytes()));
-            let res = administrators::table
+    let (login_token, refresh_token) = web::block(move || {
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let stored_password = administrators::table
                 .filter(administrators::aid.eq(&info.aid))
-                .filter(administrators::password.eq(&hashed_password))
-                .count()
-                .get_result::<i64>(&conn)
-                .context("数据库错误")?;
-
-            if res != 1 {
-                bail!("密码错误");
+                .select(administrators::password)
+                .get_result::<String>(&conn)
+                .context(ApiError::Database)?;
+            if crate::password::verify_password(&info.password, &stored_password)? {
+                let rehashed_password = crate::password::hash_password(&info.password)?;
+                diesel::update(administrators::table.filter(administrators::aid.eq(&info.aid)))
+                    .set(administrators::password.eq(&rehashed_password))
+                    .execute(&conn)
+                    .context(ApiError::Database)?;
             }
 
-            let login_token = crate::utils::generate_login_token(&info.aid, "admin");
-            let token_data = AdminLoginData {
-                token: login_token.clone(),
-                aid: info.aid,
-                login_time: Utc::now().naive_utc(),
-            };
-            diesel::insert_into(admin_logins::table)
-                .values(token_data)
-                .execute(&conn)
-                .context("数据库错误")?;
-
-            Ok(login_token)
+            crate::auth::create_session::<AuthedAdmin>(&conn, &info.aid)
         })
     })
     .await?;
@@ -144,57 +257,77 @@ async fn login_impl(
         success: true,
         err: "".to_string(),
         login_token,
+        refresh_token,
     })
 }
 
 async fn logout_impl(
     pool: web::Data<DbPool>,
-    info: web::Json<LogoutRequest>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    _info: web::Json<LogoutRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::admin_logins;
 
-    let info = info.into_inner();
     let conn = get_db_conn(&pool)?;
     web::block(move || {
-        diesel::delete(admin_logins::table.filter(admin_logins::token.eq(info.login_token)))
+        diesel::delete(admin_logins::table.filter(admin_logins::token.eq(admin.token_hash)))
             .execute(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn logout_all_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    _info: web::Json<LogoutAllRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    use crate::schema::admin_logins;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || {
+        diesel::delete(admin_logins::table.filter(admin_logins::aid.eq(admin.aid))).execute(&conn)
+    })
+    .await
+    .context(ApiError::Database)?;
 
     Ok(SimpleResponse::ok())
 }
 
 async fn modify_password_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<ModifyPasswordRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::administrators;
+    use crate::schema::{admin_logins, administrators};
 
     let info = info.into_inner();
-    let aid = get_aid_from_token(info.login_token.clone(), &pool).await?;
+    let aid = admin.aid;
 
     let conn = get_db_conn(&pool)?;
     web::block(move || {
-        conn.transaction(|| {
-            let hashed_password_old =
-                format!("{:x}", Blake2b::digest(info.password_old.as_bytes()));
-            let res = administrators::table
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let stored_password = administrators::table
                 .filter(administrators::aid.eq(&aid))
-                .filter(administrators::password.eq(&hashed_password_old))
-                .count()
-                .get_result::<i64>(&conn)
-                .context("数据库错误")?;
-            if res != 1 {
-                bail!("密码错误");
-            }
+                .select(administrators::password)
+                .get_result::<String>(&conn)
+                .context(ApiError::Database)?;
+            crate::password::verify_password(&info.password_old, &stored_password)?;
 
-            let hashed_password_new =
-                format!("{:x}", Blake2b::digest(info.password_new.as_bytes()));
+            let hashed_password_new = crate::password::hash_password(&info.password_new)?;
             diesel::update(administrators::table.filter(administrators::aid.eq(&aid)))
                 .set(administrators::password.eq(&hashed_password_new))
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
+
+            diesel::delete(admin_logins::table.filter(admin_logins::aid.eq(&aid)))
+                .execute(&conn)
+                .context(ApiError::Database)?;
 
             Ok(())
         })
@@ -206,12 +339,14 @@ async fn modify_password_impl(
 
 async fn add_doctor_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<AddDoctorRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::doctors;
 
     let info = info.into_inner();
-    utils::get_aid_from_token(info.login_token.clone(), &pool).await?;
+    admin.assert_depart(&info.depart)?;
     assert::assert_depart(&pool, info.depart.clone()).await?;
 
     let conn = get_db_conn(&pool)?;
@@ -221,9 +356,9 @@ async fn add_doctor_impl(
                 .filter(doctors::did.eq(&info.did))
                 .count()
                 .get_result::<i64>(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
             if res > 0 {
-                bail!("ID 重复");
+                bail!(ApiError::DuplicateId);
             }
 
             crate::utils::assert_gender_str(&info.gender)?;
@@ -233,23 +368,65 @@ async fn add_doctor_impl(
                 Err(_) => None,
             };
             // TODO - frontend hashed password ?
-            let hashed_password = format!("{:x}", Blake2b::digest("123456".as_bytes()));
+            let hashed_password = crate::password::hash_password("123456")?;
+            let dpid = depart::id_of(&conn, &info.depart)?;
             let data = DoctorData {
                 did: info.did,
                 name: info.name,
                 password: hashed_password,
                 gender: info.gender,
                 birthday,
-                department: info.depart,
+                dpid,
                 rank: info.rank,
                 information: "".to_string(),
             };
             diesel::insert_into(doctors::table)
-                .values(data)
+                .values(&data)
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
+            depart::join(&conn, &data.did, dpid)
+        })
+    })
+    .await?;
 
-            Ok(())
+    Ok(SimpleResponse::ok())
+}
+
+async fn add_doctor_depart_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<DoctorDepartRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    assert::assert_doctor(&pool, info.did.clone()).await?;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || {
+        conn.transaction(|| {
+            admin.assert_depart(&depart::find(&conn, info.dpid)?.depart_name)?;
+            depart::join(&conn, &info.did, info.dpid)
+        })
+    })
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn remove_doctor_depart_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<DoctorDepartRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    assert::assert_doctor(&pool, info.did.clone()).await?;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || {
+        conn.transaction(|| {
+            admin.assert_depart(&depart::find(&conn, info.dpid)?.depart_name)?;
+            depart::leave(&conn, &info.did, info.dpid)
         })
     })
     .await?;
@@ -259,44 +436,63 @@ async fn add_doctor_impl(
 
 async fn search_doctor_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    _admin: AuthedAdmin,
     info: web::Json<SearchDoctorRequest>,
 ) -> anyhow::Result<SearchDoctorResponse> {
-    use crate::schema::doctors;
+    use crate::schema::{departments, doctor_ratings, doctors};
 
     let info = info.into_inner();
-    // get_aid_from_token(info.login_token, &pool).await?;
+    let sort = DoctorSort::parse(info.sort.as_deref())?;
 
     let conn = get_db_conn(&pool)?;
     let depart_name_pattern = crate::utils::get_str_pattern_opt(info.depart_name);
     let doctor_name_pattern = crate::utils::get_str_pattern_opt(info.doctor_name);
     let rank = crate::utils::get_str_pattern_opt(info.rank);
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(10).max(0);
+    let limit = config.pagination.admin_limit(info.limit);
     let docs = web::block(move || {
-        doctors::table
-            .filter(doctors::department.like(depart_name_pattern))
+        let query = doctors::table
+            .inner_join(departments::table.on(departments::dpid.eq(doctors::dpid)))
+            .left_join(doctor_ratings::table.on(doctor_ratings::did.eq(doctors::did)))
+            .filter(departments::depart_name.like(depart_name_pattern))
             .filter(doctors::name.like(doctor_name_pattern))
             .filter(doctors::rankk.like(rank))
-            .order(doctors::name.asc())
+            .select((
+                doctors::all_columns,
+                departments::depart_name,
+                (doctor_ratings::rating_total, doctor_ratings::rating_count).nullable(),
+            ))
+            .into_boxed();
+        let query = match sort {
+            DoctorSort::Name => query.order(doctors::name.asc()),
+            DoctorSort::Rating => query.order((
+                (doctor_ratings::rating_total / doctor_ratings::rating_count).desc(),
+                doctors::name.asc(),
+            )),
+        };
+        query
             .offset(first_index)
             .limit(limit)
-            .get_results::<DoctorData>(&conn)
+            .get_results::<(DoctorData, String, Option<(i32, i32)>)>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     let docs = docs
         .into_iter()
-        .map(|data| SearchDoctorItem {
+        .map(|(data, depart, rating)| SearchDoctorItem {
             did: data.did,
             name: data.name,
-            depart: data.department,
+            depart,
             rank: data.rank,
             gender: data.gender,
             age: data
                 .birthday
                 .map_or(-1, |birth| Utc::today().year() - birth.year()),
             info: data.information,
+            rating: review::average(rating),
+            review_count: rating.map_or(0, |(_, count)| count),
         })
         .collect();
 
@@ -309,22 +505,26 @@ async fn search_doctor_impl(
 
 async fn view_doctor_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    _admin: AuthedAdmin,
     info: web::Json<ViewDoctorRequest>,
 ) -> anyhow::Result<ViewDoctorResponse> {
-    use crate::schema::doctors;
+    use crate::schema::{departments, doctors};
 
     let info = info.into_inner();
     assert::assert_doctor(&pool, info.did.clone()).await?;
 
     let conn = get_db_conn(&pool)?;
     let did = info.did;
-    let data = web::block(move || {
+    let (data, depart) = web::block(move || {
         doctors::table
+            .inner_join(departments::table.on(departments::dpid.eq(doctors::dpid)))
             .filter(doctors::did.eq(did))
-            .get_result::<DoctorData>(&conn)
+            .select((doctors::all_columns, departments::depart_name))
+            .get_result::<(DoctorData, String)>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     Ok(ViewDoctorResponse {
         success: true,
@@ -336,117 +536,110 @@ async fn view_doctor_impl(
             data.birthday.unwrap_or(NaiveDate::from_ymd(1970, 1, 1))
         ),
         gender: data.gender,
-        depart: data.department,
+        depart,
         rank: data.rank,
     })
 }
 
 async fn modify_doctor_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<ModifyDoctorRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::doctors;
 
     let info = info.into_inner();
-    get_aid_from_token(info.login_token, &pool).await?;
     assert::assert_doctor(&pool, info.did.clone()).await?;
 
+    let conn = get_db_conn(&pool)?;
+    let did = info.did.clone();
+    let new_depart = info.depart.clone();
+    let (main, new_main) = web::block(move || -> anyhow::Result<_> {
+        let main = depart::main_of(&conn, &did)?;
+        admin.assert_depart(&depart::name_of(&conn, main)?)?;
+        let new_main = match new_depart {
+            Some(depart) => {
+                admin.assert_depart(&depart)?;
+                Some(depart::id_of(&conn, &depart)?)
+            }
+            None => None,
+        };
+        Ok((main, new_main))
+    })
+    .await?;
+
     let mut data = UpdateDoctor {
         name: info.name,
         gender: info.gender,
         rank: info.rank,
-        department: info.depart,
+        dpid: new_main,
         ..Default::default()
     };
     if let Some(birthday) = info.birthday {
-        let birthday = NaiveDate::parse_from_str(&birthday, "%Y-%m-%d").context("生日格式错误")?;
+        let birthday =
+            NaiveDate::parse_from_str(&birthday, "%Y-%m-%d").context(ApiError::BirthdayFormat)?;
         data.birthday = Some(birthday);
     }
 
     let conn = get_db_conn(&pool)?;
     let did = info.did;
     web::block(move || {
-        diesel::update(doctors::table.filter(doctors::did.eq(did)))
-            .set(&data)
-            .execute(&conn)
+        conn.transaction(|| {
+            diesel::update(doctors::table.filter(doctors::did.eq(&did)))
+                .set(&data)
+                .execute(&conn)
+                .context(ApiError::Database)?;
+            match data.dpid {
+                Some(new) => depart::change_main(&conn, &did, main, new),
+                None => Ok(()),
+            }
+        })
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
 
     Ok(SimpleResponse::ok())
 }
 
 async fn add_depart_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<AddDepartRequst>,
-) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::departments;
+) -> anyhow::Result<AddDepartResponse> {
+    admin.assert_super()?;
 
     let info = info.into_inner();
-    get_aid_from_token(info.login_token, &pool).await?;
 
     let conn = get_db_conn(&pool)?;
-    let depart_name = info.depart;
-    let information = info.info;
-    web::block(move || {
-        conn.transaction(|| {
-            let res = departments::table
-                .filter(departments::depart_name.eq(&depart_name))
-                .count()
-                .get_result::<i64>(&conn)
-                .context("数据库错误")?;
-            if res > 0 {
-                bail!("科室名称重复");
-            }
-
-            let data = DepartData {
-                depart_name,
-                information,
-            };
-            diesel::insert_into(departments::table)
-                .values(data)
-                .execute(&conn)
-                .context("数据库错误")?;
-
-            Ok(())
-        })
+    let dpid = web::block(move || {
+        conn.transaction(|| depart::create(&conn, info.depart, info.info, info.parent))
     })
     .await?;
 
-    Ok(SimpleResponse::ok())
+    Ok(AddDepartResponse {
+        success: true,
+        err: "".to_string(),
+        dpid,
+    })
 }
 
 async fn search_depart_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    _admin: AuthedAdmin,
     info: web::Json<SearchDepartRequest>,
 ) -> anyhow::Result<SearchDepartResponse> {
-    use crate::schema::departments;
-
     let info = info.into_inner();
-    // get_aid_from_token(info.login_token, &pool).await?;
 
     let conn = get_db_conn(&pool)?;
     let name_pattern = crate::utils::get_str_pattern_opt(info.depart_name);
+    let with_retired = info.retired.unwrap_or(false);
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(10).max(0);
-    let departs = web::block(move || {
-        departments::table
-            .filter(departments::depart_name.like(name_pattern))
-            .order(departments::depart_name.asc())
-            .offset(first_index)
-            .limit(limit)
-            .get_results::<DepartData>(&conn)
-    })
-    .await
-    .context("数据库错误")?;
-
-    let departs = departs
-        .into_iter()
-        .map(|data| SearchDepartItem {
-            name: data.depart_name,
-            info: data.information,
-        })
-        .collect();
+    let limit = config.pagination.admin_limit(info.limit);
+    let departs =
+        web::block(move || depart::tree(&conn, &name_pattern, with_retired, first_index, limit))
+            .await?;
 
     Ok(SearchDepartResponse {
         success: true,
@@ -457,12 +650,14 @@ async fn search_depart_impl(
 
 async fn modify_depart_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<ModifyDepartRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::departments;
 
     let info = info.into_inner();
-    get_aid_from_token(info.login_token, &pool).await?;
+    admin.assert_depart(&info.depart)?;
     assert::assert_depart(&pool, info.depart.clone()).await?;
 
     if let Some(information) = info.info {
@@ -474,39 +669,184 @@ async fn modify_depart_impl(
                 .execute(&conn)
         })
         .await
-        .context("数据库错误")?;
+        .context(ApiError::Database)?;
     }
 
     Ok(SimpleResponse::ok())
 }
 
+async fn rename_depart_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<RenameDepartRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    admin.assert_super()?;
+
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || conn.transaction(|| depart::rename(&conn, info.dpid, info.name))).await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn move_depart_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<MoveDepartRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    admin.assert_super()?;
+
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || conn.transaction(|| depart::move_to(&conn, info.dpid, info.parent))).await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn merge_depart_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<MergeDepartRequest>,
+) -> anyhow::Result<MergeDepartResponse> {
+    admin.assert_super()?;
+
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    let moved =
+        web::block(move || conn.transaction(|| depart::merge(&conn, info.dpid, info.into))).await?;
+
+    Ok(MergeDepartResponse {
+        success: true,
+        err: "".to_string(),
+        moved,
+    })
+}
+
+async fn retire_depart_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<RetireDepartRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    admin.assert_super()?;
+
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || conn.transaction(|| depart::retire(&conn, info.dpid))).await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn add_location_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<AddLocationRequest>,
+) -> anyhow::Result<AddLocationResponse> {
+    admin.assert_super()?;
+
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    let lcid =
+        web::block(move || conn.transaction(|| location::create(&conn, info.name, info.address)))
+            .await?;
+
+    Ok(AddLocationResponse {
+        success: true,
+        err: "".to_string(),
+        lcid,
+    })
+}
+
+async fn modify_location_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<ModifyLocationRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    admin.assert_super()?;
+
+    let info = info.into_inner();
+    let lcid = info.lcid;
+    let data = UpdateLocation {
+        name: info.name,
+        address: info.address,
+    };
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || conn.transaction(|| location::modify(&conn, lcid, data))).await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn search_location_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    _admin: AuthedAdmin,
+    info: web::Json<SearchLocationRequest>,
+) -> anyhow::Result<SearchLocationResponse> {
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    let name_pattern = crate::utils::get_str_pattern_opt(info.name);
+    let locs = web::block(move || location::search(&conn, &name_pattern)).await?;
+
+    Ok(SearchLocationResponse {
+        success: true,
+        err: "".to_string(),
+        locations: locs,
+    })
+}
+
 async fn search_comment_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    _admin: AuthedAdmin,
     info: web::Json<SearchCommentRequest>,
 ) -> anyhow::Result<SearchCommentResponse> {
     use crate::schema::comments;
 
     let info = info.into_inner();
-    // get_aid_from_token(info.login_token, &pool).await?;
 
     let (start_time, end_time) =
         crate::utils::parse_time_pair_str_opt(info.start_time, info.end_time)?;
+    let status = info
+        .status
+        .as_deref()
+        .map(CommentStatus::parse)
+        .transpose()?;
 
     let conn = get_db_conn(&pool)?;
     let did = info.did;
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(10).max(0);
-    let cmts = web::block(move || {
-        comments::table
+    let limit = config.pagination.admin_limit(info.limit);
+    let (cmts, mut replies) = web::block(move || -> anyhow::Result<_> {
+        let mut query = comments::table
             .filter(comments::did.eq(&did))
             .filter(comments::time.between(start_time, end_time))
+            .into_boxed();
+        if let Some(status) = status {
+            query = query.filter(comments::status.eq(status.as_str()));
+        }
+        let cmts = query
             .order(comments::time.desc())
             .offset(first_index)
             .limit(limit)
             .get_results::<Comment>(&conn)
+            .context(ApiError::Database)?;
+        let cids = cmts.iter().map(|data| data.cid).collect::<Vec<_>>();
+        let replies = review::replies(&conn, &cids)?;
+        Ok((cmts, replies))
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
 
     let cmts = cmts
         .into_iter()
@@ -515,6 +855,13 @@ async fn search_comment_impl(
             username: data.username,
             comment: data.comment,
             time: crate::utils::format_time_str(&data.time),
+            tid: data.tid,
+            rating: data.rating,
+            reply: replies.remove(&data.cid),
+            status: data.status,
+            status_actor_kind: data.status_actor_kind,
+            status_actor_id: data.status_actor_id,
+            status_time: data.status_time.as_ref().map(crate::utils::format_time_str),
         })
         .collect();
 
@@ -527,39 +874,116 @@ async fn search_comment_impl(
 
 async fn delete_comment_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<DeleteCommentRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::comments;
-
     let info = info.into_inner();
-    get_aid_from_token(info.login_token, &pool).await?;
     let cid = info.cid;
     assert::assert_comment(&pool, cid).await?;
 
     let conn = get_db_conn(&pool)?;
     web::block(move || {
-        diesel::delete(comments::table.filter(comments::cid.eq(cid))).execute(&conn)
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            admin.assert_any_depart(&review::comment_departs(&conn, cid)?)?;
+            review::delete(&conn, cid, Actor::Admin(&admin.aid))
+        })
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn search_moderation_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<SearchModerationRequest>,
+) -> anyhow::Result<SearchModerationResponse> {
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    let first_index = info.first_index.unwrap_or(0).max(0);
+    let limit = config.pagination.admin_limit(info.limit);
+    let depart = match admin.role {
+        AdminRole::DepartAdmin => admin.department,
+        _ => None,
+    };
+    let cmts =
+        web::block(move || review::queue(&conn, depart.as_deref(), first_index, limit)).await?;
+
+    let cmts = cmts
+        .into_iter()
+        .map(|(data, reports)| ModerationItem {
+            cid: data.cid,
+            did: data.did,
+            username: data.username,
+            comment: data.comment,
+            time: crate::utils::format_time_str(&data.time),
+            rating: data.rating,
+            status: data.status,
+            reports: reports
+                .into_iter()
+                .map(|report| ReportItem {
+                    rpid: report.rpid,
+                    reporter_kind: report.reporter_kind,
+                    reporter_id: report.reporter_id,
+                    reason: report.reason,
+                    time: crate::utils::format_time_str(&report.time),
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(SearchModerationResponse {
+        success: true,
+        err: "".to_string(),
+        comments: cmts,
+    })
+}
+
+async fn moderate_comment_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<ModerateCommentRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let cid = info.cid;
+    let status = CommentStatus::parse(&info.status)?;
+    if status == CommentStatus::Pending {
+        bail!(ApiError::StatusFormat);
+    }
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || {
+        conn.transaction(|| {
+            admin.assert_any_depart(&review::comment_departs(&conn, cid)?)?;
+            review::set_status(&conn, cid, status, Actor::Admin(&admin.aid))
+        })
+    })
+    .await?;
 
     Ok(SimpleResponse::ok())
 }
 
 async fn search_user_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<SearchUserRequest>,
 ) -> anyhow::Result<SearchUserResponse> {
     use crate::schema::users;
 
+    admin.assert_view_users()?;
+
     let info = info.into_inner();
-    // get_aid_from_token(info.login_token, &pool).await?;
 
     let username_pattern = crate::utils::get_str_pattern_opt(info.username);
 
     let conn = get_db_conn(&pool)?;
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(10).max(0);
+    let limit = config.pagination.admin_limit(info.limit);
     let usrs = web::block(move || {
         users::table
             .filter(users::username.like(username_pattern))
@@ -569,7 +993,7 @@ async fn search_user_impl(
             .get_results::<UserData>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     let usrs = usrs
         .into_iter()
@@ -594,23 +1018,28 @@ async fn search_user_impl(
 
 async fn view_user_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<ViewUserRequest>,
 ) -> anyhow::Result<ViewUserResponse> {
     use crate::schema::users;
 
+    admin.assert_view_users()?;
+
     let info = info.into_inner();
-    // get_aid_from_token(info.login_token, &pool).await?;
     assert::assert_user(&pool, info.username.clone(), false).await?;
 
     let conn = get_db_conn(&pool)?;
     let username = info.username;
-    let data = web::block(move || {
-        users::table
-            .filter(users::username.eq(username))
+    let (data, penalty) = web::block(move || -> anyhow::Result<_> {
+        let data = users::table
+            .filter(users::username.eq(&username))
             .get_result::<UserData>(&conn)
+            .context(ApiError::Database)?;
+        let penalty = penalty::penalty(&conn, &username, &config.penalty)?;
+        Ok((data, penalty))
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
 
     Ok(ViewUserResponse {
         success: true,
@@ -624,17 +1053,25 @@ async fn view_user_impl(
         gender: data.gender,
         telephone: data.telephone,
         is_banned: data.is_banned,
+        no_shows: penalty.no_shows,
+        blocked_until: penalty
+            .blocked_until
+            .as_ref()
+            .map(crate::utils::format_time_str),
     })
 }
 
 async fn ban_user_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<BanUserRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::users;
+    use crate::schema::{user_logins, users};
+
+    admin.assert_super()?;
 
     let info = info.into_inner();
-    get_aid_from_token(info.login_token, &pool).await?;
     assert::assert_user(&pool, info.username.clone(), false).await?;
 
     let conn = get_db_conn(&pool)?;
@@ -645,19 +1082,25 @@ async fn ban_user_impl(
             let data = users::table
                 .filter(users::username.eq(&username))
                 .get_result::<UserData>(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
 
             if data.is_banned && is_banned {
-                bail!("用户已被封禁");
+                bail!(ApiError::UserAlreadyBanned);
             }
             if !data.is_banned && !is_banned {
-                bail!("用户已被解封");
+                bail!(ApiError::UserAlreadyUnbanned);
             }
 
             diesel::update(users::table.filter(users::username.eq(&username)))
                 .set(users::is_banned.eq(is_banned))
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
+
+            if is_banned {
+                diesel::delete(user_logins::table.filter(user_logins::username.eq(&username)))
+                    .execute(&conn)
+                    .context(ApiError::Database)?;
+            }
 
             Ok(())
         })
@@ -667,14 +1110,35 @@ async fn ban_user_impl(
     Ok(SimpleResponse::ok())
 }
 
+async fn clear_penalty_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<ClearPenaltyRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    admin.assert_super()?;
+
+    let info = info.into_inner();
+    assert::assert_user(&pool, info.username.clone(), false).await?;
+
+    let conn = get_db_conn(&pool)?;
+    let username = info.username;
+    web::block(move || penalty::clear(&conn, &username)).await?;
+
+    Ok(SimpleResponse::ok())
+}
+
 async fn modify_user_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
     info: web::Json<ModifyUserRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::users;
 
+    admin.assert_super()?;
+
     let info = info.into_inner();
-    get_aid_from_token(info.login_token, &pool).await?;
     assert::assert_user(&pool, info.username.clone(), false).await?;
 
     let mut data = UpdateUser {
@@ -685,7 +1149,8 @@ async fn modify_user_impl(
         ..Default::default()
     };
     if let Some(birthday) = info.birthday {
-        let birthday = NaiveDate::parse_from_str(&birthday, "%Y-%m-%d").context("生日格式错误")?;
+        let birthday =
+            NaiveDate::parse_from_str(&birthday, "%Y-%m-%d").context(ApiError::BirthdayFormat)?;
         data.birthday = Some(birthday);
     }
 
@@ -697,7 +1162,287 @@ async fn modify_user_impl(
             .execute(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn add_schedule_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<AddScheduleRequest>,
+) -> anyhow::Result<AddScheduleResponse> {
+    let info = info.into_inner();
+    assert::assert_doctor(&pool, info.did.clone()).await?;
+    let input = ScheduleInput::parse(
+        info.weekday,
+        &info.start_time,
+        &info.end_time,
+        info.capacity,
+        &info.start_date,
+        info.end_date.as_deref(),
+        &info.exceptions,
+    )?
+    .at(info.dpid, info.lcid);
+
+    let conn = get_db_conn(&pool)?;
+    let did = info.did;
+    let (sid, generated) = web::block(move || {
+        conn.transaction(|| {
+            let dpid = depart::slot_depart(&conn, &did, input.dpid)?;
+            admin.assert_depart(&depart::name_of(&conn, dpid)?)?;
+
+            schedule::create(&conn, did, input, &config.schedule)
+        })
+    })
+    .await?;
+
+    Ok(AddScheduleResponse {
+        success: true,
+        err: "".to_string(),
+        sid,
+        generated,
+    })
+}
+
+async fn modify_schedule_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<ModifyScheduleRequest>,
+) -> anyhow::Result<ModifyScheduleResponse> {
+    let info = info.into_inner();
+    let input = ScheduleInput::parse(
+        info.weekday,
+        &info.start_time,
+        &info.end_time,
+        info.capacity,
+        &info.start_date,
+        info.end_date.as_deref(),
+        &info.exceptions,
+    )?
+    .at(info.dpid, info.lcid);
+
+    let conn = get_db_conn(&pool)?;
+    let sid = info.sid;
+    let generated = web::block(move || {
+        conn.transaction(|| {
+            admin.assert_depart(&schedule::schedule_depart(&conn, sid)?)?;
+            let generated = schedule::update(&conn, sid, input, &config.schedule)?;
+            // moving the template to another department needs the permission there too
+            admin.assert_depart(&schedule::schedule_depart(&conn, sid)?)?;
+            Ok::<_, anyhow::Error>(generated)
+        })
+    })
+    .await?;
+
+    Ok(ModifyScheduleResponse {
+        success: true,
+        err: "".to_string(),
+        generated,
+    })
+}
+
+async fn delete_schedule_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    admin: AuthedAdmin,
+    info: web::Json<DeleteScheduleRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let conn = get_db_conn(&pool)?;
+    let sid = info.sid;
+    web::block(move || {
+        conn.transaction(|| {
+            admin.assert_depart(&schedule::schedule_depart(&conn, sid)?)?;
+            schedule::delete(&conn, sid)
+        })
+    })
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn search_schedule_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    _admin: AuthedAdmin,
+    info: web::Json<SearchScheduleRequest>,
+) -> anyhow::Result<SearchScheduleResponse> {
+    let info = info.into_inner();
+    assert::assert_doctor(&pool, info.did.clone()).await?;
+
+    let conn = get_db_conn(&pool)?;
+    let schedules = web::block(move || schedule::search(&conn, &info.did)).await?;
+
+    Ok(SearchScheduleResponse {
+        success: true,
+        err: "".to_string(),
+        schedules,
+    })
+}
+
+async fn update_appoint_status_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    admin: AuthedAdmin,
+    info: web::Json<UpdateAppointStatusRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let status = AppointStatus::parse(&info.status)?;
+
+    let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
+    web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            admin.assert_depart(&schedule::time_depart(&conn, info.tid)?)?;
+
+            booking::transition(
+                &conn,
+                &info.username,
+                info.tid,
+                status,
+                Actor::Admin(&admin.aid),
+                &config.booking,
+                &config.penalty,
+            )
+        })
+    })
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn search_appoint_event_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    _admin: AuthedAdmin,
+    info: web::Json<SearchAppointEventRequest>,
+) -> anyhow::Result<SearchAppointEventResponse> {
+    use crate::schema::appointment_events;
+
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    let first_index = info.first_index.unwrap_or(0).max(0);
+    let limit = config.pagination.admin_limit(info.limit);
+    let events = web::block(move || {
+        let mut query = appointment_events::table.into_boxed();
+        if let Some(username) = info.username {
+            query = query.filter(appointment_events::username.eq(username));
+        }
+        if let Some(tid) = info.tid {
+            query = query.filter(appointment_events::tid.eq(tid));
+        }
+        query
+            .order(appointment_events::eid.desc())
+            .offset(first_index)
+            .limit(limit)
+            .get_results::<AppointEvent>(&conn)
+    })
+    .await
+    .context(ApiError::Database)?;
+
+    let events = events
+        .into_iter()
+        .map(|data| SearchAppointEventItem {
+            eid: data.eid,
+            username: data.username,
+            tid: data.tid,
+            from_status: data
+                .from_status
+                .map(|status| AppointStatus::parse(&status).unwrap_or_default()),
+            to_status: AppointStatus::parse(&data.to_status).unwrap_or_default(),
+            actor_kind: data.actor_kind,
+            actor_id: data.actor_id,
+            time: crate::utils::format_time_str(&data.time),
+        })
+        .collect();
+
+    Ok(SearchAppointEventResponse {
+        success: true,
+        err: "".to_string(),
+        events,
+    })
+}
+
+async fn add_leave_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    admin: AuthedAdmin,
+    info: web::Json<AddLeaveRequest>,
+) -> anyhow::Result<AddLeaveResponse> {
+    let info = info.into_inner();
+    assert::assert_doctor(&pool, info.did.clone()).await?;
+    let (start_time, end_time) =
+        crate::utils::parse_time_pair_str(&info.start_time, &info.end_time)?;
+
+    let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
+    let (lid, slots, canceled) = web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            admin.assert_any_depart(&depart::names_of(&conn, &info.did)?)?;
+
+            leave::create(
+                &conn,
+                info.did,
+                start_time,
+                end_time,
+                info.reason,
+                Actor::Admin(&admin.aid),
+                &config,
+            )
+        })
+    })
+    .await?;
+
+    Ok(AddLeaveResponse {
+        success: true,
+        err: "".to_string(),
+        lid,
+        slots,
+        canceled,
+    })
+}
+
+async fn delete_leave_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    admin: AuthedAdmin,
+    info: web::Json<DeleteLeaveRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
+    let lid = info.lid;
+    web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            admin.assert_any_depart(&leave::leave_departs(&conn, lid)?)?;
+            leave::delete(&conn, lid)
+        })
+    })
+    .await?;
 
     Ok(SimpleResponse::ok())
 }
+
+async fn search_leave_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    _admin: AuthedAdmin,
+    info: web::Json<SearchLeaveRequest>,
+) -> anyhow::Result<SearchLeaveResponse> {
+    let info = info.into_inner();
+    assert::assert_doctor(&pool, info.did.clone()).await?;
+
+    let conn = get_db_conn(&pool)?;
+    let leaves = web::block(move || leave::search(&conn, &info.did)).await?;
+
+    Ok(SearchLeaveResponse {
+        success: true,
+        err: "".to_string(),
+        leaves,
+    })
+}
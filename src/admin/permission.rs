@@ -0,0 +1,43 @@
+use anyhow::bail;
+
+use crate::{auth::AuthedAdmin, error::ApiError, models::administrators::AdminRole};
+
+impl AuthedAdmin {
+    pub fn assert_super(&self) -> anyhow::Result<()> {
+        if self.role != AdminRole::SuperAdmin {
+            bail!(ApiError::Forbidden);
+        }
+        Ok(())
+    }
+
+    pub fn assert_view_users(&self) -> anyhow::Result<()> {
+        if self.role == AdminRole::DepartAdmin {
+            bail!(ApiError::Forbidden);
+        }
+        Ok(())
+    }
+
+    /// Super-admins manage every department, department admins only their own one.
+    pub fn assert_depart(&self, depart: &str) -> anyhow::Result<()> {
+        match self.role {
+            AdminRole::SuperAdmin => Ok(()),
+            AdminRole::DepartAdmin if self.department.as_deref() == Some(depart) => Ok(()),
+            _ => bail!(ApiError::Forbidden),
+        }
+    }
+
+    /// For doctors in several departments, an admin of any of them will do.
+    pub fn assert_any_depart(&self, departs: &[String]) -> anyhow::Result<()> {
+        match self.role {
+            AdminRole::SuperAdmin => Ok(()),
+            AdminRole::DepartAdmin
+                if departs
+                    .iter()
+                    .any(|depart| self.department.as_ref() == Some(depart)) =>
+            {
+                Ok(())
+            }
+            _ => bail!(ApiError::Forbidden),
+        }
+    }
+}
@@ -2,8 +2,12 @@ use serde::Deserialize;
 
 #[derive(Deserialize)]
 pub struct RegisterRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub aid: String,
     pub password: String,
+    pub role: String,
+    pub depart: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -14,19 +18,28 @@ pub struct LoginRequest {
 
 #[derive(Deserialize)]
 pub struct LogoutRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LogoutAllRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct ModifyPasswordRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub password_old: String,
     pub password_new: String,
 }
 
 #[derive(Deserialize)]
 pub struct AddDoctorRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub did: String,
     pub name: String,
     pub depart: String,
@@ -38,23 +51,28 @@ pub struct AddDoctorRequest {
 
 #[derive(Deserialize)]
 pub struct SearchDoctorRequest {
-    // pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub doctor_name: Option<String>,
     pub depart_name: Option<String>,
     pub rank: Option<String>,
+    /// `name` or `rating`.
+    pub sort: Option<String>,
     pub first_index: Option<i64>,
     pub limit: Option<i64>,
 }
 
 #[derive(Deserialize)]
 pub struct ViewDoctorRequest {
-    // pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub did: String,
 }
 
 #[derive(Deserialize)]
 pub struct ModifyDoctorRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub did: String,
     pub name: Option<String>,
     pub gender: Option<String>,
@@ -65,44 +83,141 @@ pub struct ModifyDoctorRequest {
 
 #[derive(Deserialize)]
 pub struct AddDepartRequst {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub depart: String,
     pub info: String,
+    /// The `dpid` of the parent department, a top-level department when unset.
+    pub parent: Option<u64>,
 }
 
 #[derive(Deserialize)]
 pub struct SearchDepartRequest {
-    // pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub depart_name: Option<String>,
+    /// Also show retired departments.
+    pub retired: Option<bool>,
     pub first_index: Option<i64>,
     pub limit: Option<i64>,
 }
 
 #[derive(Deserialize)]
 pub struct ModifyDepartRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub depart: String,
     pub info: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct RenameDepartRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub dpid: u64,
+    pub name: String,
+}
+
+/// Moves the department below `parent`, or to the top level when unset.
+#[derive(Deserialize)]
+pub struct MoveDepartRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub dpid: u64,
+    pub parent: Option<u64>,
+}
+
+/// Merges department `dpid` into department `into`.
+#[derive(Deserialize)]
+pub struct MergeDepartRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub dpid: u64,
+    pub into: u64,
+}
+
+#[derive(Deserialize)]
+pub struct RetireDepartRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub dpid: u64,
+}
+
+/// Adds or removes a department the doctor consults in, besides the main one.
+#[derive(Deserialize)]
+pub struct DoctorDepartRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub did: String,
+    pub dpid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct AddLocationRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub address: String,
+}
+
+#[derive(Deserialize)]
+pub struct ModifyLocationRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub lcid: u64,
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SearchLocationRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub name: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct SearchCommentRequest {
-    // pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub did: String,
     pub start_time: Option<String>,
     pub end_time: Option<String>,
+    /// `visible`, `hidden`, `pending` or `deleted`, all of them when unset.
+    pub status: Option<String>,
     pub first_index: Option<i64>,
     pub limit: Option<i64>,
 }
 
+#[derive(Deserialize)]
+pub struct SearchModerationRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub first_index: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// `status` is `visible`, `hidden` or `deleted`.
+#[derive(Deserialize)]
+pub struct ModerateCommentRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub cid: u64,
+    pub status: String,
+}
+
 #[derive(Deserialize)]
 pub struct DeleteCommentRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub cid: u64,
 }
 
 #[derive(Deserialize)]
 pub struct SearchUserRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub username: Option<String>,
     pub first_index: Option<i64>,
     pub limit: Option<i64>,
@@ -110,20 +225,30 @@ pub struct SearchUserRequest {
 
 #[derive(Deserialize)]
 pub struct ViewUserRequest {
-    // pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub username: String,
 }
 
 #[derive(Deserialize)]
 pub struct BanUserRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub username: String,
     pub is_banned: bool,
 }
 
+#[derive(Deserialize)]
+pub struct ClearPenaltyRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub username: String,
+}
+
 #[derive(Deserialize)]
 pub struct ModifyUserRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub username: String,
     pub name: Option<String>,
     pub gender: Option<String>,
@@ -131,3 +256,102 @@ pub struct ModifyUserRequest {
     pub birthday: Option<String>,
     pub telephone: Option<String>,
 }
+
+/// `weekday` is 1 for Monday, ..., 7 for Sunday; `start_time` / `end_time` are `HH:MM[:SS]`
+/// in UTC; `exceptions` are dates without a slot.
+#[derive(Deserialize)]
+pub struct AddScheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub did: String,
+    pub weekday: i32,
+    pub start_time: String,
+    pub end_time: String,
+    pub capacity: i32,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+    /// One of the doctor's departments, the main one when unset.
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+/// Replaces the whole template, fields are the same as in `AddScheduleRequest`.
+#[derive(Deserialize)]
+pub struct ModifyScheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub sid: u64,
+    pub weekday: i32,
+    pub start_time: String,
+    pub end_time: String,
+    pub capacity: i32,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteScheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub sid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SearchScheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub did: String,
+}
+
+/// Times are RFC 3339 like the ones of time slots.
+#[derive(Deserialize)]
+pub struct AddLeaveRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub did: String,
+    pub start_time: String,
+    pub end_time: String,
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteLeaveRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub lid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SearchLeaveRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub did: String,
+}
+
+/// `status` is one of `checked_in`, `in_progress`, `finished`, `no_show` and
+/// `clinic_canceled`.
+#[derive(Deserialize)]
+pub struct UpdateAppointStatusRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub username: String,
+    pub tid: u64,
+    pub status: String,
+}
+
+#[derive(Deserialize)]
+pub struct SearchAppointEventRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub username: Option<String>,
+    pub tid: Option<u64>,
+    pub first_index: Option<i64>,
+    pub limit: Option<i64>,
+}
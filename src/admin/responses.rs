@@ -1,3 +1,5 @@
+use crate::models::appointments::AppointStatus;
+use crate::protocol::ReplyItem;
 use serde::Serialize;
 
 #[derive(Default, Serialize)]
@@ -5,6 +7,7 @@ pub struct LoginResponse {
     pub success: bool,
     pub err: String,
     pub login_token: String,
+    pub refresh_token: String,
 }
 
 #[derive(Default, Serialize)]
@@ -16,6 +19,9 @@ pub struct SearchDoctorItem {
     pub depart: String,
     pub rank: String,
     pub info: String,
+    /// Average of the ratings, unset before the first review.
+    pub rating: Option<f64>,
+    pub review_count: i32,
 }
 
 #[derive(Default, Serialize)]
@@ -37,25 +43,19 @@ pub struct ViewDoctorResponse {
     pub rank: String,
 }
 
-#[derive(Default, Serialize)]
-pub struct SearchDepartItem {
-    pub name: String,
-    pub info: String,
-}
-
-#[derive(Default, Serialize)]
-pub struct SearchDepartResponse {
-    pub success: bool,
-    pub err: String,
-    pub departments: Vec<SearchDepartItem>,
-}
-
 #[derive(Default, Serialize)]
 pub struct SearchCommentItem {
     pub cid: u64,
     pub username: String,
     pub comment: String,
     pub time: String,
+    pub tid: Option<u64>,
+    pub rating: Option<i32>,
+    pub status: String,
+    pub status_actor_kind: Option<String>,
+    pub status_actor_id: Option<String>,
+    pub status_time: Option<String>,
+    pub reply: Option<ReplyItem>,
 }
 
 #[derive(Default, Serialize)]
@@ -92,14 +92,76 @@ pub struct ViewUserResponse {
     pub gender: String,
     pub telephone: String,
     pub is_banned: bool,
+    pub no_shows: i64,
+    pub blocked_until: Option<String>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchAppointEventItem {
+    pub eid: u64,
+    pub username: String,
+    pub tid: u64,
+    pub from_status: Option<AppointStatus>,
+    pub to_status: AppointStatus,
+    pub actor_kind: String,
+    pub actor_id: Option<String>,
+    pub time: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchAppointEventResponse {
+    pub success: bool,
+    pub err: String,
+    pub events: Vec<SearchAppointEventItem>,
+}
+
+#[derive(Default, Serialize)]
+pub struct ReportItem {
+    pub rpid: u64,
+    pub reporter_kind: String,
+    pub reporter_id: String,
+    pub reason: String,
+    pub time: String,
+}
+
+/// A pending comment or one with open reports.
+#[derive(Default, Serialize)]
+pub struct ModerationItem {
+    pub cid: u64,
+    pub did: String,
+    pub username: String,
+    pub comment: String,
+    pub time: String,
+    pub rating: Option<i32>,
+    pub status: String,
+    pub reports: Vec<ReportItem>,
 }
 
-crate::impl_err_response! {
-    LoginResponse,
-    SearchDoctorResponse,
-    ViewDoctorResponse,
-    SearchDepartResponse,
-    SearchCommentResponse,
-    SearchUserResponse,
-    ViewUserResponse,
+#[derive(Default, Serialize)]
+pub struct SearchModerationResponse {
+    pub success: bool,
+    pub err: String,
+    pub comments: Vec<ModerationItem>,
+}
+
+#[derive(Default, Serialize)]
+pub struct AddDepartResponse {
+    pub success: bool,
+    pub err: String,
+    pub dpid: u64,
+}
+
+#[derive(Default, Serialize)]
+pub struct MergeDepartResponse {
+    pub success: bool,
+    pub err: String,
+    /// Number of doctors moved to the other department.
+    pub moved: usize,
+}
+
+#[derive(Default, Serialize)]
+pub struct AddLocationResponse {
+    pub success: bool,
+    pub err: String,
+    pub lcid: u64,
 }
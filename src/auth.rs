@@ -0,0 +1,464 @@
+use std::{future::Future, pin::Pin, time::Duration};
+
+use actix_web::{
+    dev::Payload,
+    error::{BlockingError, InternalError},
+    http::header,
+    web, FromRequest, HttpRequest, HttpResponse,
+};
+use anyhow::{anyhow, bail, Context};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, MysqlConnection};
+use subtle::ConstantTimeEq;
+
+use crate::{
+    config::{Config, SessionConfig},
+    database::get_db_conn,
+    error::ApiError,
+    models::{
+        admin_logins::AdminLoginData,
+        administrators::{AdminData, AdminRole},
+        doctor_logins::DoctorLoginData,
+        user_logins::UserLoginData,
+    },
+    protocol::{RefreshRequest, RefreshResponse},
+    DbPool,
+};
+
+pub struct AuthedUser {
+    pub username: String,
+    pub token_hash: String,
+}
+
+pub struct AuthedDoctor {
+    pub did: String,
+    pub token_hash: String,
+}
+
+pub struct AuthedAdmin {
+    pub aid: String,
+    pub token_hash: String,
+    pub role: AdminRole,
+    pub department: Option<String>,
+}
+
+// Only hashes of the tokens handed to clients are stored, see `crate::utils::hash_login_token`.
+pub struct Session {
+    pub id: String,
+    pub token_hash: String,
+    pub refresh_token_hash: String,
+    pub login_time: NaiveDateTime,
+    pub last_active: NaiveDateTime,
+}
+
+/// Access to one of the `*_logins` tables.
+pub trait SessionStore {
+    fn find_session(conn: &MysqlConnection, token_hash: &str) -> QueryResult<Option<Session>>;
+    fn find_session_by_refresh(
+        conn: &MysqlConnection,
+        refresh_token_hash: &str,
+    ) -> QueryResult<Option<Session>>;
+    fn insert_session(conn: &MysqlConnection, session: Session) -> QueryResult<()>;
+    fn touch_session(
+        conn: &MysqlConnection,
+        token_hash: &str,
+        now: NaiveDateTime,
+    ) -> QueryResult<()>;
+    fn delete_session(conn: &MysqlConnection, token_hash: &str) -> QueryResult<()>;
+    fn delete_expired_sessions(
+        conn: &MysqlConnection,
+        idle_before: NaiveDateTime,
+        issued_before: NaiveDateTime,
+    ) -> QueryResult<usize>;
+}
+
+pub trait Principal: SessionStore + Sized + Send + 'static {
+    /// Loads the principal, rejecting it if it can no longer act (deleted, banned, ...).
+    fn load(conn: &MysqlConnection, id: String, token_hash: String) -> anyhow::Result<Self>;
+}
+
+macro_rules! impl_session_store {
+    ( $( ($type:ty, $table:ident, $data:ident, $id:ident) ),+ $(,)? ) => {
+        $(
+            impl SessionStore for $type {
+                fn find_session(
+                    conn: &MysqlConnection,
+                    token_hash: &str,
+                ) -> QueryResult<Option<Session>> {
+                    use crate::schema::$table;
+
+                    let data = $table::table
+                        .filter($table::token.eq(token_hash))
+                        .first::<$data>(conn)
+                        .optional()?;
+                    Ok(data
+                        .filter(|data| hash_eq(&data.token, token_hash))
+                        .map(Session::from))
+                }
+
+                fn find_session_by_refresh(
+                    conn: &MysqlConnection,
+                    refresh_token_hash: &str,
+                ) -> QueryResult<Option<Session>> {
+                    use crate::schema::$table;
+
+                    let data = $table::table
+                        .filter($table::refresh_token.eq(refresh_token_hash))
+                        .first::<$data>(conn)
+                        .optional()?;
+                    Ok(data
+                        .filter(|data| hash_eq(&data.refresh_token, refresh_token_hash))
+                        .map(Session::from))
+                }
+
+                fn insert_session(conn: &MysqlConnection, session: Session) -> QueryResult<()> {
+                    use crate::schema::$table;
+
+                    let data = $data {
+                        token: session.token_hash,
+                        $id: session.id,
+                        login_time: session.login_time,
+                        last_active: session.last_active,
+                        refresh_token: session.refresh_token_hash,
+                    };
+                    diesel::insert_into($table::table)
+                        .values(data)
+                        .execute(conn)
+                        .map(|_| ())
+                }
+
+                fn touch_session(
+                    conn: &MysqlConnection,
+                    token_hash: &str,
+                    now: NaiveDateTime,
+                ) -> QueryResult<()> {
+                    use crate::schema::$table;
+
+                    diesel::update($table::table.filter($table::token.eq(token_hash)))
+                        .set($table::last_active.eq(now))
+                        .execute(conn)
+                        .map(|_| ())
+                }
+
+                fn delete_session(conn: &MysqlConnection, token_hash: &str) -> QueryResult<()> {
+                    use crate::schema::$table;
+
+                    diesel::delete($table::table.filter($table::token.eq(token_hash)))
+                        .execute(conn)
+                        .map(|_| ())
+                }
+
+                fn delete_expired_sessions(
+                    conn: &MysqlConnection,
+                    idle_before: NaiveDateTime,
+                    issued_before: NaiveDateTime,
+                ) -> QueryResult<usize> {
+                    use crate::schema::$table;
+
+                    diesel::delete(
+                        $table::table
+                            .filter($table::last_active.lt(idle_before))
+                            .filter($table::login_time.lt(issued_before)),
+                    )
+                    .execute(conn)
+                }
+            }
+
+            impl From<$data> for Session {
+                fn from(data: $data) -> Self {
+                    Self {
+                        id: data.$id,
+                        token_hash: data.token,
+                        refresh_token_hash: data.refresh_token,
+                        login_time: data.login_time,
+                        last_active: data.last_active,
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_session_store! {
+    (AuthedUser, user_logins, UserLoginData, username),
+    (AuthedDoctor, doctor_logins, DoctorLoginData, did),
+    (AuthedAdmin, admin_logins, AdminLoginData, aid),
+}
+
+impl Principal for AuthedUser {
+    fn load(conn: &MysqlConnection, username: String, token_hash: String) -> anyhow::Result<Self> {
+        use crate::schema::users;
+
+        let is_banned = users::table
+            .filter(users::username.eq(&username))
+            .select(users::is_banned)
+            .get_result::<bool>(conn)
+            .optional()
+            .context(ApiError::Database)?
+            .context(ApiError::UserNotFound)?;
+        if is_banned {
+            bail!(ApiError::UserBanned);
+        }
+
+        Ok(AuthedUser {
+            username,
+            token_hash,
+        })
+    }
+}
+
+impl Principal for AuthedDoctor {
+    fn load(conn: &MysqlConnection, did: String, token_hash: String) -> anyhow::Result<Self> {
+        use crate::schema::doctors;
+
+        let res = doctors::table
+            .filter(doctors::did.eq(&did))
+            .count()
+            .get_result::<i64>(conn)
+            .context(ApiError::Database)?;
+        if res == 0 {
+            bail!(ApiError::DoctorNotFound);
+        }
+
+        Ok(AuthedDoctor { did, token_hash })
+    }
+}
+
+impl Principal for AuthedAdmin {
+    fn load(conn: &MysqlConnection, aid: String, token_hash: String) -> anyhow::Result<Self> {
+        use crate::schema::administrators;
+
+        let data = administrators::table
+            .filter(administrators::aid.eq(&aid))
+            .get_result::<AdminData>(conn)
+            .optional()
+            .context(ApiError::Database)?;
+        let data = match data {
+            Some(data) => data,
+            None => bail!(ApiError::AdminNotFound),
+        };
+
+        Ok(AuthedAdmin {
+            aid,
+            token_hash,
+            role: AdminRole::parse(&data.role)?,
+            department: data
+                .dpid
+                .map(|dpid| crate::depart::name_of(conn, dpid))
+                .transpose()?,
+        })
+    }
+}
+
+/// Starts a new session for `id`, returns its login token and refresh token.
+pub fn create_session<P: Principal>(
+    conn: &MysqlConnection,
+    id: &str,
+) -> anyhow::Result<(String, String)> {
+    let now = Utc::now().naive_utc();
+    let token = crate::utils::generate_login_token();
+    let refresh_token = crate::utils::generate_login_token();
+    let session = Session {
+        id: id.to_string(),
+        token_hash: crate::utils::hash_login_token(&token),
+        refresh_token_hash: crate::utils::hash_login_token(&refresh_token),
+        login_time: now,
+        last_active: now,
+    };
+    P::insert_session(conn, session).context(ApiError::Database)?;
+
+    Ok((token, refresh_token))
+}
+
+fn hash_eq(stored: &str, hash: &str) -> bool {
+    stored.as_bytes().ct_eq(hash.as_bytes()).into()
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    parse_bearer(value)
+}
+
+/// The token of an `Authorization: Bearer <token>` value. The scheme is case-insensitive.
+fn parse_bearer(value: &str) -> Option<String> {
+    let (scheme, token) = value.trim_start().split_once(' ')?;
+    let token = token.trim();
+    if !scheme.eq_ignore_ascii_case("bearer") || token.is_empty() {
+        None
+    } else {
+        Some(token.to_string())
+    }
+}
+
+fn session_config(req: &HttpRequest) -> SessionConfig {
+    req.app_data::<web::Data<Config>>()
+        .map(|config| config.session.clone())
+        .unwrap_or_default()
+}
+
+fn flatten_blocking(err: BlockingError<anyhow::Error>) -> anyhow::Error {
+    match err {
+        BlockingError::Error(err) => err,
+        BlockingError::Canceled => anyhow!(ApiError::Database),
+    }
+}
+
+/// Resolves the session from the `Authorization: Bearer` header, falling back to the
+/// deprecated `login_token` JSON field for clients that have not migrated yet.
+pub async fn authenticate<P: Principal>(
+    req: &HttpRequest,
+    pool: &web::Data<DbPool>,
+    body_token: Option<String>,
+) -> anyhow::Result<P> {
+    let token = match bearer_token(req).or(body_token) {
+        Some(token) => token,
+        None => bail!(ApiError::NotLoggedIn),
+    };
+    let token_hash = crate::utils::hash_login_token(&token);
+    let config = session_config(req);
+
+    let conn = get_db_conn(pool)?;
+    web::block(move || {
+        let session = P::find_session(&conn, &token_hash)
+            .context(ApiError::Database)?
+            .context(ApiError::NotLoggedIn)?;
+        let now = Utc::now().naive_utc();
+        if now.signed_duration_since(session.last_active).num_seconds() > config.idle_secs {
+            bail!(ApiError::LoginExpired);
+        }
+        let principal = P::load(&conn, session.id, session.token_hash)?;
+        P::touch_session(&conn, &token_hash, now).context(ApiError::Database)?;
+        Ok(principal)
+    })
+    .await
+    .map_err(flatten_blocking)
+}
+
+async fn refresh_impl<P: Principal>(
+    pool: web::Data<DbPool>,
+    config: SessionConfig,
+    info: web::Json<RefreshRequest>,
+) -> anyhow::Result<RefreshResponse> {
+    let refresh_token_hash = crate::utils::hash_login_token(&info.refresh_token);
+    let conn = get_db_conn(&pool)?;
+    let (login_token, refresh_token) = web::block(move || {
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let session = P::find_session_by_refresh(&conn, &refresh_token_hash)
+                .context(ApiError::Database)?
+                .context(ApiError::NotLoggedIn)?;
+            let now = Utc::now().naive_utc();
+            if now.signed_duration_since(session.login_time).num_seconds() > config.refresh_secs {
+                bail!(ApiError::LoginExpired);
+            }
+
+            P::load(&conn, session.id.clone(), session.token_hash.clone())?;
+            P::delete_session(&conn, &session.token_hash).context(ApiError::Database)?;
+            create_session::<P>(&conn, &session.id)
+        })
+    })
+    .await
+    .map_err(flatten_blocking)?;
+
+    Ok(RefreshResponse {
+        success: true,
+        err: "".to_string(),
+        login_token,
+        refresh_token,
+    })
+}
+
+/// Exchanges a refresh token for a new login token, the refresh token is rotated as well.
+pub async fn refresh<P: Principal>(
+    pool: web::Data<DbPool>,
+    req: HttpRequest,
+    info: web::Json<RefreshRequest>,
+) -> HttpResponse {
+    let result = refresh_impl::<P>(pool, session_config(&req), info).await;
+    crate::i18n::localized_response(&req, result)
+}
+
+fn sweep_expired_sessions(pool: &DbPool, config: &SessionConfig) -> anyhow::Result<()> {
+    let conn = pool.get().context(ApiError::Database)?;
+    let now = Utc::now().naive_utc();
+    let idle_before = now - chrono::Duration::seconds(config.idle_secs);
+    let issued_before = now - chrono::Duration::seconds(config.refresh_secs);
+
+    AuthedUser::delete_expired_sessions(&conn, idle_before, issued_before)?;
+    AuthedDoctor::delete_expired_sessions(&conn, idle_before, issued_before)?;
+    AuthedAdmin::delete_expired_sessions(&conn, idle_before, issued_before)?;
+
+    Ok(())
+}
+
+/// Periodically deletes login rows whose login token and refresh token have both expired.
+pub async fn run_session_sweeper(pool: DbPool, config: SessionConfig) {
+    let mut interval =
+        actix_rt::time::interval(Duration::from_secs(config.sweep_interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let config = config.clone();
+        if let Err(err) = web::block(move || sweep_expired_sessions(&pool, &config)).await {
+            eprintln!(
+                "failed to sweep expired sessions: {}",
+                flatten_blocking(err)
+            );
+        }
+    }
+}
+
+macro_rules! impl_from_request {
+    ( $( $type:ty ),+ $(,)? ) => {
+        $(
+            impl FromRequest for $type {
+                type Error = actix_web::Error;
+                type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+                type Config = ();
+
+                fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+                    let req = req.clone();
+                    Box::pin(async move {
+                        let pool = req
+                            .app_data::<web::Data<DbPool>>()
+                            .cloned()
+                            .context(ApiError::Database);
+                        let res = match pool {
+                            Ok(pool) => authenticate::<Self>(&req, &pool, None).await,
+                            Err(err) => Err(err),
+                        };
+                        res.map_err(|err| {
+                            let message = err.to_string();
+                            let response = crate::i18n::localized_response::<()>(&req, Err(err));
+                            InternalError::from_response(message, response).into()
+                        })
+                    })
+                }
+            }
+        )+
+    };
+}
+
+impl_from_request! {
+    AuthedUser,
+    AuthedDoctor,
+    AuthedAdmin,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearer_scheme_ignores_case() {
+        for value in ["Bearer abc", "bearer abc", "BEARER  abc ", " Bearer abc"] {
+            assert_eq!(parse_bearer(value).as_deref(), Some("abc"), "{}", value);
+        }
+    }
+
+    #[test]
+    fn other_schemes_and_empty_tokens() {
+        for value in ["Basic abc", "Bearer", "Bearer ", "Bearerabc", "abc", ""] {
+            assert_eq!(parse_bearer(value), None, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,622 @@
+use anyhow::{bail, Context};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    config::{BookingConfig, PenaltyConfig},
+    error::ApiError,
+    models::{
+        appointment_events::NewAppointEvent,
+        appointments::{AppointStatus, Appointment, NewAppointment},
+        reschedules::NewReschedule,
+        times::TimeData,
+        waitlists::{NewWaitlistEntry, WaitlistEntry},
+    },
+    notify,
+    outbox::{self, Kind},
+    rules,
+};
+
+// These run on the connection of the caller's transaction. The slot row is locked while
+// its seats are counted, so `times.appointed` always matches the appointments holding a seat.
+
+/// Who changes the status of an appointment, recorded in `appointment_events`.
+#[derive(Clone, Copy)]
+pub enum Actor<'a> {
+    User(&'a str),
+    Doctor(&'a str),
+    Admin(&'a str),
+    /// Changes made by the server itself, like waitlist promotions.
+    System,
+}
+
+impl Actor<'_> {
+    pub fn kind(self) -> &'static str {
+        match self {
+            Actor::User(_) => "user",
+            Actor::Doctor(_) => "doctor",
+            Actor::Admin(_) => "admin",
+            Actor::System => "system",
+        }
+    }
+
+    pub fn id(self) -> Option<String> {
+        match self {
+            Actor::User(id) | Actor::Doctor(id) | Actor::Admin(id) => Some(id.to_string()),
+            Actor::System => None,
+        }
+    }
+}
+
+/// Locks the slot row until the end of the transaction.
+pub fn lock_time(conn: &MysqlConnection, tid: u64) -> anyhow::Result<TimeData> {
+    use crate::schema::times;
+
+    times::table
+        .filter(times::tid.eq(tid))
+        .for_update()
+        .get_result::<TimeData>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::TimeNotFound)
+}
+
+fn find_appointment(
+    conn: &MysqlConnection,
+    username: &str,
+    tid: u64,
+) -> anyhow::Result<Option<Appointment>> {
+    use crate::schema::appointments;
+
+    appointments::table
+        .filter(appointments::username.eq(username))
+        .filter(appointments::tid.eq(tid))
+        .get_result::<Appointment>(conn)
+        .optional()
+        .context(ApiError::Database)
+}
+
+/// Only an appointment canceled by the patient can be booked again.
+fn is_active(appointment: &Option<Appointment>) -> bool {
+    matches!(appointment, Some(appo) if appo.status != AppointStatus::Canceled.as_str())
+}
+
+/// Records the change and notifies the patient and the doctor once it commits.
+fn record_event(
+    conn: &MysqlConnection,
+    username: &str,
+    time_data: &TimeData,
+    from: Option<AppointStatus>,
+    to: AppointStatus,
+    actor: Actor,
+) -> anyhow::Result<()> {
+    use crate::schema::appointment_events;
+
+    let data = NewAppointEvent {
+        username: username.to_string(),
+        tid: time_data.tid,
+        from_status: from.map(|from| from.as_str().to_string()),
+        to_status: to.as_str().to_string(),
+        actor_kind: actor.kind().to_string(),
+        actor_id: actor.id(),
+    };
+    diesel::insert_into(appointment_events::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    notify::appointment_changed(username, time_data, from, to, actor.kind());
+    Ok(())
+}
+
+/// Takes a seat of the locked `time_data`, the caller has checked that there is one. The
+/// appointment joins the end of the queue, even if it was booked before.
+fn take_seat(
+    conn: &MysqlConnection,
+    username: &str,
+    time_data: &TimeData,
+    existing: Option<Appointment>,
+    actor: Actor,
+) -> anyhow::Result<()> {
+    use crate::schema::{appointments, times, waitlists};
+
+    let tid = time_data.tid;
+    let queue_number = time_data.issued_number + 1;
+    let from = match existing {
+        None => {
+            let data = NewAppointment {
+                username: username.to_string(),
+                tid,
+                status: AppointStatus::Unfinished.as_str().to_string(),
+                time: None,
+                queue_number,
+            };
+            diesel::insert_into(appointments::table)
+                .values(data)
+                .execute(conn)
+                .context(ApiError::Database)?;
+            None
+        }
+        Some(existing) => {
+            diesel::update(
+                appointments::table
+                    .filter(appointments::username.eq(username))
+                    .filter(appointments::tid.eq(tid)),
+            )
+            .set((
+                appointments::status.eq(AppointStatus::Unfinished.as_str()),
+                appointments::queue_number.eq(queue_number),
+                appointments::called_at.eq(None::<NaiveDateTime>),
+                appointments::skipped.eq(false),
+            ))
+            .execute(conn)
+            .context(ApiError::Database)?;
+            Some(AppointStatus::parse(&existing.status)?)
+        }
+    };
+    record_event(
+        conn,
+        username,
+        time_data,
+        from,
+        AppointStatus::Unfinished,
+        actor,
+    )?;
+
+    diesel::update(times::table.filter(times::tid.eq(tid)))
+        .set((
+            times::appointed.eq(times::appointed + 1),
+            times::issued_number.eq(queue_number),
+        ))
+        .execute(conn)
+        .context(ApiError::Database)?;
+
+    diesel::delete(
+        waitlists::table
+            .filter(waitlists::username.eq(username))
+            .filter(waitlists::tid.eq(tid)),
+    )
+    .execute(conn)
+    .context(ApiError::Database)?;
+
+    Ok(())
+}
+
+pub fn book(
+    conn: &MysqlConnection,
+    username: &str,
+    tid: u64,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<()> {
+    book_except(conn, username, tid, None, config, penalty_config)?;
+    outbox::enqueue(conn, Kind::Booked, username, tid, None)
+}
+
+/// Books `tid` as if the appointment at `except` were already given back.
+fn book_except(
+    conn: &MysqlConnection,
+    username: &str,
+    tid: u64,
+    except: Option<u64>,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<()> {
+    let time_data = lock_time(conn, tid)?;
+    let existing = find_appointment(conn, username, tid)?;
+    if is_active(&existing) {
+        bail!(ApiError::AppointExists);
+    }
+    rules::check(conn, username, &time_data, except, config, penalty_config)?;
+    if time_data.capacity <= time_data.appointed {
+        bail!(ApiError::TimeFull);
+    }
+
+    take_seat(conn, username, &time_data, existing, Actor::User(username))
+}
+
+pub fn cancel(
+    conn: &MysqlConnection,
+    username: &str,
+    tid: u64,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<()> {
+    transition(
+        conn,
+        username,
+        tid,
+        AppointStatus::Canceled,
+        Actor::User(username),
+        config,
+        penalty_config,
+    )?;
+    outbox::enqueue(conn, Kind::Canceled, username, tid, None)
+}
+
+fn invalid_transition(from: AppointStatus, to: AppointStatus) -> ApiError {
+    match from {
+        AppointStatus::Finished => ApiError::AppointFinished,
+        AppointStatus::Canceled | AppointStatus::ClinicCanceled => ApiError::AppointCanceled,
+        _ => ApiError::InvalidTransition { from, to },
+    }
+}
+
+/// Who may move an appointment to `to`.
+fn may_set(actor: Actor, to: AppointStatus) -> bool {
+    match actor {
+        Actor::User(_) => to == AppointStatus::Canceled,
+        Actor::Doctor(_) | Actor::Admin(_) => {
+            !matches!(to, AppointStatus::Unfinished | AppointStatus::Canceled)
+        }
+        Actor::System => matches!(to, AppointStatus::NoShow | AppointStatus::Finished),
+    }
+}
+
+/// When an appointment of the slot from `start_time` to `end_time` may move to `to`, as the
+/// earliest and the latest time.
+fn window(
+    to: AppointStatus,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    config: &BookingConfig,
+) -> (Option<NaiveDateTime>, Option<NaiveDateTime>) {
+    let minutes = |mins: i64| chrono::Duration::minutes(mins);
+    match to {
+        AppointStatus::Canceled => (None, Some(start_time - minutes(config.cancel_cutoff_mins))),
+        AppointStatus::CheckedIn => (
+            Some(start_time - minutes(config.check_in_before_mins)),
+            Some(end_time),
+        ),
+        AppointStatus::InProgress => (
+            Some(start_time - minutes(config.check_in_before_mins)),
+            None,
+        ),
+        AppointStatus::Finished => (Some(start_time), None),
+        AppointStatus::NoShow => (Some(start_time + minutes(config.no_show_after_mins)), None),
+        AppointStatus::Unfinished | AppointStatus::ClinicCanceled => (None, None),
+    }
+}
+
+/// Moves the appointment of `username` at `tid` to `to`, enforcing the legal transitions,
+/// who may make them and when. A seat given back is handed to the waitlist.
+pub fn transition(
+    conn: &MysqlConnection,
+    username: &str,
+    tid: u64,
+    to: AppointStatus,
+    actor: Actor,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<()> {
+    use crate::schema::{appointments, times};
+
+    if !may_set(actor, to) {
+        bail!(ApiError::Forbidden);
+    }
+
+    let time_data = lock_time(conn, tid)?;
+    let appointment = find_appointment(conn, username, tid)?.context(ApiError::AppointNotFound)?;
+    let from = AppointStatus::parse(&appointment.status)?;
+    if !from.can_become(to) {
+        bail!(invalid_transition(from, to));
+    }
+
+    let now = Utc::now().naive_utc();
+    let (not_before, not_after) = window(to, time_data.start_time, time_data.end_time, config);
+    if matches!(not_before, Some(not_before) if now < not_before) {
+        bail!(ApiError::AppointTooEarly);
+    }
+    if matches!(not_after, Some(not_after) if now > not_after) {
+        if to == AppointStatus::Canceled && config.cancel_cutoff_mins > 0 {
+            bail!(ApiError::CancelTooLate {
+                minutes: config.cancel_cutoff_mins,
+            });
+        }
+        bail!(ApiError::AppointTooLate);
+    }
+
+    diesel::update(
+        appointments::table
+            .filter(appointments::username.eq(username))
+            .filter(appointments::tid.eq(tid)),
+    )
+    .set(appointments::status.eq(to.as_str()))
+    .execute(conn)
+    .context(ApiError::Database)?;
+    record_event(conn, username, &time_data, Some(from), to, actor)?;
+    if to == AppointStatus::ClinicCanceled {
+        outbox::enqueue(conn, Kind::ClinicCanceled, username, tid, None)?;
+    }
+
+    if from.holds_seat() && !to.holds_seat() {
+        diesel::update(times::table.filter(times::tid.eq(tid)))
+            .set(times::appointed.eq(times::appointed - 1))
+            .execute(conn)
+            .context(ApiError::Database)?;
+        promote(conn, tid, config, penalty_config)?;
+    }
+
+    Ok(())
+}
+
+/// Moves an unfinished appointment to another slot of the same doctor or department. The
+/// new seat is taken before the old one is given back, so the user never ends up without
+/// either.
+pub fn reschedule(
+    conn: &MysqlConnection,
+    username: &str,
+    from_tid: u64,
+    to_tid: u64,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<()> {
+    use crate::schema::reschedules;
+
+    if from_tid == to_tid {
+        bail!(ApiError::RescheduleSameTime);
+    }
+    // lock in a fixed order, two reschedules in opposite directions would deadlock otherwise
+    let (from, to) = if from_tid < to_tid {
+        let from = lock_time(conn, from_tid)?;
+        (from, lock_time(conn, to_tid)?)
+    } else {
+        let to = lock_time(conn, to_tid)?;
+        (lock_time(conn, from_tid)?, to)
+    };
+
+    let appointment =
+        find_appointment(conn, username, from_tid)?.context(ApiError::AppointNotFound)?;
+    let status = AppointStatus::parse(&appointment.status)?;
+    // an appointment the clinic canceled, e.g. for a leave of the doctor, may be moved once
+    // to another slot, whenever the patient likes
+    let clinic_canceled = status == AppointStatus::ClinicCanceled;
+    if clinic_canceled {
+        let moved = reschedules::table
+            .filter(reschedules::username.eq(username))
+            .filter(reschedules::from_tid.eq(from_tid))
+            .count()
+            .get_result::<i64>(conn)
+            .context(ApiError::Database)?;
+        if moved > 0 {
+            bail!(ApiError::AppointRescheduled);
+        }
+    } else if status != AppointStatus::Unfinished {
+        bail!(invalid_transition(status, AppointStatus::Canceled));
+    } else {
+        let deadline = from.start_time - chrono::Duration::hours(config.reschedule_cutoff_hours);
+        if Utc::now().naive_utc() > deadline {
+            bail!(ApiError::RescheduleTooLate {
+                hours: config.reschedule_cutoff_hours,
+            });
+        }
+    }
+
+    // slots without a department can only move within the same doctor
+    if from.dpid != to.dpid || (from.dpid.is_none() && from.did != to.did) {
+        bail!(ApiError::RescheduleOtherDepart);
+    }
+
+    book_except(
+        conn,
+        username,
+        to_tid,
+        Some(from_tid),
+        config,
+        penalty_config,
+    )?;
+    if !clinic_canceled {
+        transition(
+            conn,
+            username,
+            from_tid,
+            AppointStatus::Canceled,
+            Actor::User(username),
+            config,
+            penalty_config,
+        )?;
+    }
+    outbox::enqueue(conn, Kind::Rescheduled, username, to_tid, Some(from_tid))?;
+
+    let data = NewReschedule {
+        username: username.to_string(),
+        from_tid,
+        to_tid,
+    };
+    diesel::insert_into(reschedules::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+
+    Ok(())
+}
+
+/// Books free seats of `tid` for the head of its waitlist. Users the booking rules no longer
+/// allow to take the seat are dropped from the waitlist. Returns the promoted users.
+pub fn promote(
+    conn: &MysqlConnection,
+    tid: u64,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<Vec<String>> {
+    use crate::schema::waitlists;
+
+    let mut promoted = vec![];
+    loop {
+        let time_data = lock_time(conn, tid)?;
+        if time_data.capacity <= time_data.appointed {
+            break;
+        }
+
+        let next = waitlists::table
+            .filter(waitlists::tid.eq(tid))
+            .order(waitlists::wid.asc())
+            .first::<WaitlistEntry>(conn)
+            .optional()
+            .context(ApiError::Database)?;
+        let next = match next {
+            Some(next) => next,
+            None => break,
+        };
+
+        let existing = find_appointment(conn, &next.username, tid)?;
+        if is_active(&existing)
+            || rules::violation(
+                conn,
+                &next.username,
+                &time_data,
+                None,
+                config,
+                penalty_config,
+            )?
+            .is_some()
+        {
+            diesel::delete(waitlists::table.filter(waitlists::wid.eq(next.wid)))
+                .execute(conn)
+                .context(ApiError::Database)?;
+        } else {
+            take_seat(conn, &next.username, &time_data, existing, Actor::System)?;
+            outbox::enqueue(conn, Kind::Booked, &next.username, tid, None)?;
+            promoted.push(next.username);
+        }
+    }
+
+    Ok(promoted)
+}
+
+/// Queues the user for a full slot and returns the position, starting from 1.
+pub fn join_waitlist(
+    conn: &MysqlConnection,
+    username: &str,
+    tid: u64,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<i64> {
+    use crate::schema::waitlists;
+
+    let time_data = lock_time(conn, tid)?;
+    if is_active(&find_appointment(conn, username, tid)?) {
+        bail!(ApiError::AppointExists);
+    }
+    rules::check(conn, username, &time_data, None, config, penalty_config)?;
+    if time_data.capacity > time_data.appointed {
+        bail!(ApiError::TimeNotFull);
+    }
+
+    let res = waitlists::table
+        .filter(waitlists::username.eq(username))
+        .filter(waitlists::tid.eq(tid))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    if res > 0 {
+        bail!(ApiError::WaitlistExists);
+    }
+
+    let data = NewWaitlistEntry {
+        username: username.to_string(),
+        tid,
+    };
+    diesel::insert_into(waitlists::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+
+    position(conn, username, tid)
+}
+
+pub fn leave_waitlist(conn: &MysqlConnection, username: &str, tid: u64) -> anyhow::Result<()> {
+    use crate::schema::waitlists;
+
+    let res = diesel::delete(
+        waitlists::table
+            .filter(waitlists::username.eq(username))
+            .filter(waitlists::tid.eq(tid)),
+    )
+    .execute(conn)
+    .context(ApiError::Database)?;
+    if res == 0 {
+        bail!(ApiError::WaitlistNotFound);
+    }
+    Ok(())
+}
+
+/// 1 for the head of the waitlist.
+pub fn position(conn: &MysqlConnection, username: &str, tid: u64) -> anyhow::Result<i64> {
+    use crate::schema::waitlists;
+
+    let wid = waitlists::table
+        .filter(waitlists::username.eq(username))
+        .filter(waitlists::tid.eq(tid))
+        .select(waitlists::wid)
+        .get_result::<u64>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::WaitlistNotFound)?;
+
+    waitlists::table
+        .filter(waitlists::tid.eq(tid))
+        .filter(waitlists::wid.le(wid))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use super::*;
+
+    fn slot() -> (NaiveDateTime, NaiveDateTime) {
+        let start_time = NaiveDate::from_ymd_opt(2026, 10, 19)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        (start_time, start_time + Duration::hours(1))
+    }
+
+    #[test]
+    fn who_may_set_what() {
+        assert!(may_set(Actor::User("user_a"), AppointStatus::Canceled));
+        assert!(!may_set(Actor::User("user_a"), AppointStatus::Finished));
+        assert!(may_set(Actor::Doctor("doctor_a"), AppointStatus::Finished));
+        assert!(may_set(
+            Actor::Admin("admin_a"),
+            AppointStatus::ClinicCanceled
+        ));
+        assert!(!may_set(Actor::Doctor("doctor_a"), AppointStatus::Canceled));
+        assert!(!may_set(Actor::Admin("admin_a"), AppointStatus::Unfinished));
+        assert!(may_set(Actor::System, AppointStatus::NoShow));
+        assert!(may_set(Actor::System, AppointStatus::Finished));
+        assert!(!may_set(Actor::System, AppointStatus::ClinicCanceled));
+    }
+
+    #[test]
+    fn time_windows() {
+        let config = BookingConfig {
+            cancel_cutoff_mins: 30,
+            ..BookingConfig::default()
+        };
+        let (start_time, end_time) = slot();
+        let window = |to| window(to, start_time, end_time, &config);
+
+        assert_eq!(
+            window(AppointStatus::Canceled),
+            (None, Some(start_time - Duration::minutes(30)))
+        );
+        assert_eq!(
+            window(AppointStatus::CheckedIn),
+            (Some(start_time - Duration::minutes(60)), Some(end_time))
+        );
+        assert_eq!(
+            window(AppointStatus::InProgress),
+            (Some(start_time - Duration::minutes(60)), None)
+        );
+        assert_eq!(window(AppointStatus::Finished), (Some(start_time), None));
+        assert_eq!(
+            window(AppointStatus::NoShow),
+            (Some(start_time + Duration::minutes(15)), None)
+        );
+        assert_eq!(window(AppointStatus::ClinicCanceled), (None, None));
+    }
+}
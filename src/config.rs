@@ -0,0 +1,608 @@
+use std::{path::Path, str::FromStr};
+
+use anyhow::{bail, Context};
+use chrono::NaiveTime;
+use serde::Deserialize;
+
+/// Loaded from the TOML file named by `CONFIG_FILE` (default `config.toml`),
+/// then overridden by environment variables, see `apply_env`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub session: SessionConfig,
+    pub pagination: PaginationConfig,
+    pub clinic: ClinicConfig,
+    pub schedule: ScheduleConfig,
+    pub booking: BookingConfig,
+    pub penalty: PenaltyConfig,
+    pub events: EventsConfig,
+    pub notifier: NotifierConfig,
+    pub moderation: ModerationConfig,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    /// Defaults to the number of CPUs.
+    pub workers: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1:8080".to_string(),
+            workers: None,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+    pub min_idle: Option<u32>,
+    pub connection_timeout_secs: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "".to_string(),
+            pool_size: 10,
+            min_idle: None,
+            connection_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// A session expires after being idle for this long; every request restarts the clock.
+    pub idle_secs: i64,
+    /// How long a refresh token stays usable after it was issued.
+    pub refresh_secs: i64,
+    pub sweep_interval_secs: u64,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_secs: 3600,
+            refresh_secs: 7 * 24 * 3600,
+            sweep_interval_secs: 600,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaginationConfig {
+    pub default_limit: i64,
+    pub admin_default_limit: i64,
+    pub max_limit: i64,
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        Self {
+            default_limit: 30,
+            admin_default_limit: 10,
+            max_limit: 100,
+        }
+    }
+}
+
+impl PaginationConfig {
+    pub fn limit(&self, limit: Option<i64>) -> i64 {
+        limit.unwrap_or(self.default_limit).clamp(0, self.max_limit)
+    }
+
+    pub fn admin_limit(&self, limit: Option<i64>) -> i64 {
+        limit
+            .unwrap_or(self.admin_default_limit)
+            .clamp(0, self.max_limit)
+    }
+}
+
+/// Opening hours of the morning and afternoon sessions, in UTC.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClinicConfig {
+    pub am_start: NaiveTime,
+    pub am_end: NaiveTime,
+    pub pm_start: NaiveTime,
+    pub pm_end: NaiveTime,
+}
+
+impl Default for ClinicConfig {
+    fn default() -> Self {
+        Self {
+            am_start: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            am_end: NaiveTime::from_hms_opt(11, 0, 0).unwrap(),
+            pm_start: NaiveTime::from_hms_opt(15, 0, 0).unwrap(),
+            pm_end: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// Slots are generated from the weekly templates this many days ahead.
+    pub horizon_days: i64,
+    /// How often the horizon is rolled forward.
+    pub generate_interval_secs: u64,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            horizon_days: 28,
+            generate_interval_secs: 3600,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BookingConfig {
+    /// An appointment can't be rescheduled later than this many hours before its slot starts.
+    pub reschedule_cutoff_hours: i64,
+    /// Patients can check in from this many minutes before their slot starts.
+    pub check_in_before_mins: i64,
+    /// A patient can be marked as a no-show this many minutes after the slot started.
+    pub no_show_after_mins: i64,
+    /// Unfinished, checked-in and in-progress appointments a user may hold at once, 0 for no
+    /// limit.
+    pub max_active_appoints: i64,
+    /// Whether a user may book only one slot per department and day.
+    pub one_per_depart_per_day: bool,
+    /// Slots can be booked until this many minutes before they start.
+    pub min_lead_mins: i64,
+    /// Slots can be booked at most this many days ahead, 0 for no limit.
+    pub max_advance_days: i64,
+    /// Whether a user may hold appointments whose slots overlap.
+    pub allow_overlap: bool,
+    /// An appointment can't be canceled later than this many minutes before its slot starts.
+    pub cancel_cutoff_mins: i64,
+}
+
+impl Default for BookingConfig {
+    fn default() -> Self {
+        Self {
+            reschedule_cutoff_hours: 24,
+            check_in_before_mins: 60,
+            no_show_after_mins: 15,
+            max_active_appoints: 5,
+            one_per_depart_per_day: true,
+            min_lead_mins: 0,
+            max_advance_days: 28,
+            allow_overlap: false,
+            cancel_cutoff_mins: 0,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PenaltyConfig {
+    /// No-shows are counted over this many days.
+    pub window_days: i64,
+    /// Users with this many no-shows in the window can't book.
+    pub threshold: i64,
+    /// How often the appointments of past slots are closed, see `penalty::run_no_show_marker`.
+    pub mark_interval_secs: u64,
+}
+
+impl Default for PenaltyConfig {
+    fn default() -> Self {
+        Self {
+            window_days: 30,
+            threshold: 3,
+            mark_interval_secs: 600,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventsConfig {
+    /// Idle event streams get a comment this often, so proxies keep them open.
+    pub heartbeat_secs: u64,
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self { heartbeat_secs: 15 }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifierConfig {
+    /// How emails are sent: `smtp`, `log` or `none`.
+    pub email: String,
+    /// How text messages are sent: `gateway`, `log` or `none`.
+    pub sms: String,
+    /// The `log` sink appends every message to this file.
+    pub log_file: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    /// `starttls`, `tls` for implicit TLS, or `none` for a local relay that takes no
+    /// credentials.
+    pub smtp_tls: String,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_from: String,
+    /// An endpoint that takes `{"to": ..., "text": ...}` as JSON.
+    pub sms_gateway_url: String,
+    /// Sent as a bearer token to the gateway, which must then be an `https://` URL.
+    pub sms_gateway_token: Option<String>,
+    /// How often the outbox is delivered and reminders are queued.
+    pub deliver_interval_secs: u64,
+    /// A message is given up after this many failed attempts.
+    pub max_attempts: i32,
+    /// The first retry waits this long, every further one twice as long.
+    pub retry_base_secs: i64,
+    /// Reminders go out this many hours before the slot starts.
+    pub reminder_hours: i64,
+}
+
+impl Default for NotifierConfig {
+    fn default() -> Self {
+        Self {
+            email: "log".to_string(),
+            sms: "log".to_string(),
+            log_file: "notifications.log".to_string(),
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: 587,
+            smtp_tls: "starttls".to_string(),
+            smtp_username: None,
+            smtp_password: None,
+            smtp_from: "noreply@localhost".to_string(),
+            sms_gateway_url: "".to_string(),
+            sms_gateway_token: None,
+            deliver_interval_secs: 30,
+            max_attempts: 5,
+            retry_base_secs: 60,
+            reminder_hours: 24,
+        }
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ModerationConfig {
+    /// Hold every new comment for a moderator.
+    pub pre_moderation: bool,
+    /// Comments containing one of these, ignoring case, are held for a moderator.
+    pub banned_words: Vec<String>,
+}
+
+impl ModerationConfig {
+    /// Whether a new comment waits for a moderator before it is shown.
+    pub fn holds(&self, comment: &str) -> bool {
+        let comment = comment.to_lowercase();
+        self.pre_moderation
+            || self
+                .banned_words
+                .iter()
+                .any(|word| comment.contains(&word.to_lowercase()))
+    }
+}
+
+impl Config {
+    pub fn load() -> anyhow::Result<Self> {
+        let mut config = match std::env::var("CONFIG_FILE") {
+            Ok(path) => Self::from_file(&path)?,
+            Err(_) if Path::new("config.toml").exists() => Self::from_file("config.toml")?,
+            Err(_) => Self::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &str) -> anyhow::Result<Self> {
+        let content =
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?;
+        toml::from_str(&content).with_context(|| format!("failed to parse {}", path))
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        override_var(&mut self.server.bind, "SERVER_BIND")?;
+        override_opt(&mut self.server.workers, "SERVER_WORKERS")?;
+        override_var(&mut self.database.url, "DATABASE_URL")?;
+        override_var(&mut self.database.pool_size, "DATABASE_POOL_SIZE")?;
+        override_opt(&mut self.database.min_idle, "DATABASE_MIN_IDLE")?;
+        override_var(
+            &mut self.database.connection_timeout_secs,
+            "DATABASE_CONNECTION_TIMEOUT_SECS",
+        )?;
+        override_var(&mut self.session.idle_secs, "SESSION_IDLE_SECS")?;
+        override_var(&mut self.session.refresh_secs, "SESSION_REFRESH_SECS")?;
+        override_var(&mut self.session.sweep_interval_secs, "SESSION_SWEEP_SECS")?;
+        override_var(
+            &mut self.pagination.default_limit,
+            "PAGINATION_DEFAULT_LIMIT",
+        )?;
+        override_var(
+            &mut self.pagination.admin_default_limit,
+            "PAGINATION_ADMIN_DEFAULT_LIMIT",
+        )?;
+        override_var(&mut self.pagination.max_limit, "PAGINATION_MAX_LIMIT")?;
+        override_var(&mut self.clinic.am_start, "CLINIC_AM_START")?;
+        override_var(&mut self.clinic.am_end, "CLINIC_AM_END")?;
+        override_var(&mut self.clinic.pm_start, "CLINIC_PM_START")?;
+        override_var(&mut self.clinic.pm_end, "CLINIC_PM_END")?;
+        override_var(&mut self.schedule.horizon_days, "SCHEDULE_HORIZON_DAYS")?;
+        override_var(
+            &mut self.schedule.generate_interval_secs,
+            "SCHEDULE_GENERATE_SECS",
+        )?;
+        override_var(
+            &mut self.booking.reschedule_cutoff_hours,
+            "BOOKING_RESCHEDULE_CUTOFF_HOURS",
+        )?;
+        override_var(
+            &mut self.booking.check_in_before_mins,
+            "BOOKING_CHECK_IN_BEFORE_MINS",
+        )?;
+        override_var(
+            &mut self.booking.no_show_after_mins,
+            "BOOKING_NO_SHOW_AFTER_MINS",
+        )?;
+        override_var(
+            &mut self.booking.max_active_appoints,
+            "BOOKING_MAX_ACTIVE_APPOINTS",
+        )?;
+        override_var(
+            &mut self.booking.one_per_depart_per_day,
+            "BOOKING_ONE_PER_DEPART_PER_DAY",
+        )?;
+        override_var(&mut self.booking.min_lead_mins, "BOOKING_MIN_LEAD_MINS")?;
+        override_var(
+            &mut self.booking.max_advance_days,
+            "BOOKING_MAX_ADVANCE_DAYS",
+        )?;
+        override_var(&mut self.booking.allow_overlap, "BOOKING_ALLOW_OVERLAP")?;
+        override_var(
+            &mut self.booking.cancel_cutoff_mins,
+            "BOOKING_CANCEL_CUTOFF_MINS",
+        )?;
+        override_var(&mut self.penalty.window_days, "PENALTY_WINDOW_DAYS")?;
+        override_var(&mut self.penalty.threshold, "PENALTY_THRESHOLD")?;
+        override_var(
+            &mut self.penalty.mark_interval_secs,
+            "PENALTY_MARK_INTERVAL_SECS",
+        )?;
+        override_var(&mut self.events.heartbeat_secs, "EVENTS_HEARTBEAT_SECS")?;
+        override_var(&mut self.notifier.email, "NOTIFIER_EMAIL")?;
+        override_var(&mut self.notifier.sms, "NOTIFIER_SMS")?;
+        override_var(&mut self.notifier.log_file, "NOTIFIER_LOG_FILE")?;
+        override_var(&mut self.notifier.smtp_host, "SMTP_HOST")?;
+        override_var(&mut self.notifier.smtp_port, "SMTP_PORT")?;
+        override_var(&mut self.notifier.smtp_tls, "SMTP_TLS")?;
+        override_opt(&mut self.notifier.smtp_username, "SMTP_USERNAME")?;
+        override_opt(&mut self.notifier.smtp_password, "SMTP_PASSWORD")?;
+        override_var(&mut self.notifier.smtp_from, "SMTP_FROM")?;
+        override_var(&mut self.notifier.sms_gateway_url, "SMS_GATEWAY_URL")?;
+        override_opt(&mut self.notifier.sms_gateway_token, "SMS_GATEWAY_TOKEN")?;
+        override_var(
+            &mut self.notifier.deliver_interval_secs,
+            "NOTIFIER_DELIVER_SECS",
+        )?;
+        override_var(&mut self.notifier.max_attempts, "NOTIFIER_MAX_ATTEMPTS")?;
+        override_var(
+            &mut self.notifier.retry_base_secs,
+            "NOTIFIER_RETRY_BASE_SECS",
+        )?;
+        override_var(&mut self.notifier.reminder_hours, "NOTIFIER_REMINDER_HOURS")?;
+        override_var(
+            &mut self.moderation.pre_moderation,
+            "MODERATION_PRE_MODERATION",
+        )?;
+        if let Ok(words) = std::env::var("MODERATION_BANNED_WORDS") {
+            self.moderation.banned_words = words
+                .split(',')
+                .map(|word| word.trim().to_string())
+                .filter(|word| !word.is_empty())
+                .collect();
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.database.url.is_empty() {
+            bail!("database.url (or DATABASE_URL) is not set");
+        }
+        if self.server.workers == Some(0) {
+            bail!("server.workers must be positive");
+        }
+        if self.database.pool_size == 0 {
+            bail!("database.pool_size must be positive");
+        }
+        if matches!(self.database.min_idle, Some(min_idle) if min_idle > self.database.pool_size) {
+            bail!("database.min_idle must not exceed database.pool_size");
+        }
+        if self.session.idle_secs <= 0 || self.session.refresh_secs <= 0 {
+            bail!("session.idle_secs and session.refresh_secs must be positive");
+        }
+        if self.session.sweep_interval_secs == 0 {
+            bail!("session.sweep_interval_secs must be positive");
+        }
+        let pagination = &self.pagination;
+        if pagination.max_limit <= 0
+            || pagination.default_limit <= 0
+            || pagination.admin_default_limit <= 0
+            || pagination.default_limit > pagination.max_limit
+            || pagination.admin_default_limit > pagination.max_limit
+        {
+            bail!("pagination limits must be positive and not exceed pagination.max_limit");
+        }
+        let clinic = &self.clinic;
+        if !(clinic.am_start < clinic.am_end
+            && clinic.am_end <= clinic.pm_start
+            && clinic.pm_start < clinic.pm_end)
+        {
+            bail!("clinic hours must satisfy am_start < am_end <= pm_start < pm_end");
+        }
+        if self.schedule.horizon_days <= 0 || self.schedule.generate_interval_secs == 0 {
+            bail!("schedule.horizon_days and schedule.generate_interval_secs must be positive");
+        }
+        let booking = &self.booking;
+        if booking.reschedule_cutoff_hours < 0
+            || booking.check_in_before_mins < 0
+            || booking.no_show_after_mins < 0
+            || booking.max_active_appoints < 0
+            || booking.min_lead_mins < 0
+            || booking.max_advance_days < 0
+            || booking.cancel_cutoff_mins < 0
+        {
+            bail!("booking limits must not be negative");
+        }
+        let penalty = &self.penalty;
+        if penalty.window_days <= 0 || penalty.threshold <= 0 || penalty.mark_interval_secs == 0 {
+            bail!("penalty.window_days, penalty.threshold and penalty.mark_interval_secs must be positive");
+        }
+        if self.events.heartbeat_secs == 0 {
+            bail!("events.heartbeat_secs must be positive");
+        }
+        let notifier = &self.notifier;
+        if !["smtp", "log", "none"].contains(&notifier.email.as_str()) {
+            bail!("notifier.email must be one of smtp, log and none");
+        }
+        if !["gateway", "log", "none"].contains(&notifier.sms.as_str()) {
+            bail!("notifier.sms must be one of gateway, log and none");
+        }
+        if !["starttls", "tls", "none"].contains(&notifier.smtp_tls.as_str()) {
+            bail!("notifier.smtp_tls must be one of starttls, tls and none");
+        }
+        if notifier.smtp_tls == "none" && notifier.smtp_username.is_some() {
+            bail!("SMTP credentials are only sent over TLS, notifier.smtp_tls must not be none");
+        }
+        if notifier.sms == "gateway" {
+            let url = &notifier.sms_gateway_url;
+            if !url.starts_with("https://") && !url.starts_with("http://") {
+                bail!("notifier.sms_gateway_url must be an http:// or https:// URL");
+            }
+            if notifier.sms_gateway_token.is_some() && !url.starts_with("https://") {
+                bail!("notifier.sms_gateway_url must be an https:// URL to send the token");
+            }
+        }
+        if notifier.deliver_interval_secs == 0
+            || notifier.max_attempts <= 0
+            || notifier.retry_base_secs <= 0
+            || notifier.reminder_hours <= 0
+        {
+            bail!("notifier intervals, attempts and hours must be positive");
+        }
+        if self
+            .moderation
+            .banned_words
+            .iter()
+            .any(|word| word.trim().is_empty())
+        {
+            bail!("moderation.banned_words must not contain empty words");
+        }
+        Ok(())
+    }
+}
+
+fn override_var<T: FromStr>(field: &mut T, key: &str) -> anyhow::Result<()> {
+    if let Ok(value) = std::env::var(key) {
+        *field = value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid value of {}: {}", key, value))?;
+    }
+    Ok(())
+}
+
+fn override_opt<T: FromStr>(field: &mut Option<T>, key: &str) -> anyhow::Result<()> {
+    if let Ok(value) = std::env::var(key) {
+        let value = value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid value of {}: {}", key, value))?;
+        *field = Some(value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.database.url = "mysql://localhost/hospital".to_string();
+        config
+    }
+
+    #[test]
+    fn moderation_holds() {
+        let config = ModerationConfig {
+            pre_moderation: false,
+            banned_words: vec!["Spam".to_string()],
+        };
+        assert!(config.holds("buy SPAM here"));
+        assert!(config.holds("spammy"));
+        assert!(!config.holds("a kind doctor"));
+
+        assert!(!ModerationConfig::default().holds("anything"));
+        let config = ModerationConfig {
+            pre_moderation: true,
+            banned_words: vec![],
+        };
+        assert!(config.holds("a kind doctor"));
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(valid().validate().is_ok());
+    }
+
+    #[test]
+    fn database_url_is_required() {
+        assert!(Config::default().validate().is_err());
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let invalid: Vec<fn(&mut Config)> = vec![
+            |config| config.database.pool_size = 0,
+            |config| config.pagination.default_limit = config.pagination.max_limit + 1,
+            |config| config.booking.cancel_cutoff_mins = -1,
+            |config| config.penalty.threshold = 0,
+            |config| config.events.heartbeat_secs = 0,
+            |config| config.notifier.email = "fax".to_string(),
+            |config| {
+                config.notifier.smtp_tls = "none".to_string();
+                config.notifier.smtp_username = Some("mailer".to_string());
+            },
+            |config| {
+                config.notifier.sms = "gateway".to_string();
+                config.notifier.sms_gateway_url = "http://sms.example.com".to_string();
+                config.notifier.sms_gateway_token = Some("secret".to_string());
+            },
+            |config| config.moderation.banned_words = vec![" ".to_string()],
+        ];
+        for (i, change) in invalid.into_iter().enumerate() {
+            let mut config = valid();
+            change(&mut config);
+            assert!(config.validate().is_err(), "change {} passed", i);
+        }
+    }
+
+    #[test]
+    fn sms_gateway_without_token_may_use_http() {
+        let mut config = valid();
+        config.notifier.sms = "gateway".to_string();
+        config.notifier.sms_gateway_url = "http://sms.example.com".to_string();
+        assert!(config.validate().is_ok());
+    }
+}
@@ -2,7 +2,7 @@ use actix_web::web;
 use anyhow::{bail, Context};
 use diesel::prelude::*;
 
-use crate::{database::get_db_conn, models::users::UserData, DbPool};
+use crate::{database::get_db_conn, error::ApiError, models::users::UserData, DbPool};
 
 pub async fn assert_user(
     pool: &web::Data<DbPool>,
@@ -18,14 +18,14 @@ pub async fn assert_user(
             .get_results::<UserData>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     if res.len() != 1 {
-        bail!("用户不存在");
+        bail!(ApiError::UserNotFound);
     }
 
     if check_ban && res[0].is_banned {
-        bail!("用户被封禁");
+        bail!(ApiError::UserBanned);
     }
 
     Ok(())
@@ -42,10 +42,10 @@ pub async fn assert_doctor(pool: &web::Data<DbPool>, did: String) -> anyhow::Res
             .get_result::<i64>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     if res == 0 {
-        bail!("医生不存在");
+        bail!(ApiError::DoctorNotFound);
     }
 
     Ok(())
@@ -62,10 +62,10 @@ pub async fn assert_admin(pool: &web::Data<DbPool>, aid: String) -> anyhow::Resu
             .get_result::<i64>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     if res == 0 {
-        bail!("管理员不存在");
+        bail!(ApiError::AdminNotFound);
     }
 
     Ok(())
@@ -78,14 +78,15 @@ pub async fn assert_depart(pool: &web::Data<DbPool>, depart_name: String) -> any
     let res = web::block(move || {
         departments::table
             .filter(departments::depart_name.eq(depart_name))
+            .filter(departments::retired.eq(false))
             .count()
             .get_result::<i64>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     if res == 0 {
-        bail!("科室不存在");
+        bail!(ApiError::DepartNotFound);
     }
 
     Ok(())
@@ -102,10 +103,10 @@ pub async fn assert_comment(pool: &web::Data<DbPool>, cid: u64) -> anyhow::Resul
             .get_result::<i64>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     if res == 0 {
-        bail!("评论不存在");
+        bail!(ApiError::CommentNotFound);
     }
 
     Ok(())
@@ -122,10 +123,10 @@ pub async fn assert_time(pool: &web::Data<DbPool>, tid: u64) -> anyhow::Result<(
             .get_result::<i64>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     if res == 0 {
-        bail!("时间段不存在");
+        bail!(ApiError::TimeNotFound);
     }
 
     Ok(())
@@ -148,10 +149,10 @@ pub async fn assert_appoint(
             .get_result::<i64>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     if res == 0 {
-        bail!("预约不存在");
+        bail!(ApiError::AppointNotFound);
     }
 
     Ok(())
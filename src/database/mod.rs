@@ -1,6 +1,8 @@
 pub mod assert;
+pub mod owner;
+pub mod times;
 
-use crate::DbPool;
+use crate::{error::ApiError, DbPool};
 use actix_web::web;
 use anyhow::Context;
 use diesel::{r2d2::ConnectionManager, MysqlConnection};
@@ -9,5 +11,5 @@ use r2d2::PooledConnection;
 pub fn get_db_conn(
     pool: &web::Data<DbPool>,
 ) -> anyhow::Result<PooledConnection<ConnectionManager<MysqlConnection>>> {
-    pool.get().context("DB connection")
+    pool.get().context(ApiError::Database)
 }
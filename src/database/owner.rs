@@ -0,0 +1,111 @@
+use anyhow::{bail, Context};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::error::ApiError;
+
+// These run on the connection of the caller's transaction and lock the row until it
+// ends, so the ownership can't change between the check and the mutation.
+
+/// Passes if the row exists and belongs to `expected`.
+fn decide(owner: Option<String>, expected: &str, not_found: ApiError) -> anyhow::Result<()> {
+    match owner {
+        None => bail!(not_found),
+        Some(owner) if owner != expected => bail!(ApiError::Forbidden),
+        Some(_) => Ok(()),
+    }
+}
+
+pub fn assert_time_owner(conn: &MysqlConnection, tid: u64, did: &str) -> anyhow::Result<()> {
+    use crate::schema::times;
+
+    let owner = times::table
+        .filter(times::tid.eq(tid))
+        .select(times::did)
+        .for_update()
+        .get_result::<String>(conn)
+        .optional()
+        .context(ApiError::Database)?;
+
+    decide(owner, did, ApiError::TimeNotFound)
+}
+
+pub fn assert_comment_owner(
+    conn: &MysqlConnection,
+    cid: u64,
+    username: &str,
+) -> anyhow::Result<()> {
+    use crate::schema::comments;
+
+    let owner = comments::table
+        .filter(comments::cid.eq(cid))
+        .select(comments::username)
+        .for_update()
+        .get_result::<String>(conn)
+        .optional()
+        .context(ApiError::Database)?;
+
+    decide(owner, username, ApiError::CommentNotFound)
+}
+
+pub fn assert_schedule_owner(conn: &MysqlConnection, sid: u64, did: &str) -> anyhow::Result<()> {
+    use crate::schema::schedules;
+
+    let owner = schedules::table
+        .filter(schedules::sid.eq(sid))
+        .select(schedules::did)
+        .for_update()
+        .get_result::<String>(conn)
+        .optional()
+        .context(ApiError::Database)?;
+
+    decide(owner, did, ApiError::ScheduleNotFound)
+}
+
+pub fn assert_leave_owner(conn: &MysqlConnection, lid: u64, did: &str) -> anyhow::Result<()> {
+    use crate::schema::doctor_leaves;
+
+    let owner = doctor_leaves::table
+        .filter(doctor_leaves::lid.eq(lid))
+        .select(doctor_leaves::did)
+        .for_update()
+        .get_result::<String>(conn)
+        .optional()
+        .context(ApiError::Database)?;
+
+    decide(owner, did, ApiError::LeaveNotFound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(res: anyhow::Result<()>) -> ApiError {
+        res.unwrap_err().downcast::<ApiError>().unwrap()
+    }
+
+    #[test]
+    fn owner_passes() {
+        assert!(decide(
+            Some("doctor_a".to_string()),
+            "doctor_a",
+            ApiError::TimeNotFound
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn other_owner_is_forbidden() {
+        let res = decide(
+            Some("doctor_b".to_string()),
+            "doctor_a",
+            ApiError::TimeNotFound,
+        );
+        assert_eq!(error(res), ApiError::Forbidden);
+    }
+
+    #[test]
+    fn missing_row_is_not_found() {
+        let res = decide(None, "doctor_a", ApiError::CommentNotFound);
+        assert_eq!(error(res), ApiError::CommentNotFound);
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::{bail, Context};
+use chrono::NaiveDateTime;
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::error::ApiError;
+
+/// Whether `[start_time, end_time)` intersects another slot of the doctor. Slots that only
+/// touch, like 9:00-9:15 and 9:15-9:30, don't overlap. `except` is the slot being moved.
+pub fn has_overlap(
+    conn: &MysqlConnection,
+    did: &str,
+    start_time: &NaiveDateTime,
+    end_time: &NaiveDateTime,
+    except: Option<u64>,
+) -> anyhow::Result<bool> {
+    use crate::schema::times;
+
+    let mut query = times::table
+        .filter(times::did.eq(did))
+        .filter(times::start_time.lt(end_time))
+        .filter(times::end_time.gt(start_time))
+        .into_boxed();
+    if let Some(tid) = except {
+        query = query.filter(times::tid.ne(tid));
+    }
+    let res = query
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    Ok(res > 0)
+}
+
+pub fn assert_no_overlap(
+    conn: &MysqlConnection,
+    did: &str,
+    start_time: &NaiveDateTime,
+    end_time: &NaiveDateTime,
+    except: Option<u64>,
+) -> anyhow::Result<()> {
+    if has_overlap(conn, did, start_time, end_time, except)? {
+        bail!(ApiError::TimeConflict);
+    }
+    Ok(())
+}
@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Context};
+use chrono::Utc;
+use diesel::{
+    prelude::*,
+    sql_types::{Bigint, Nullable, Unsigned},
+    MysqlConnection,
+};
+
+use crate::{
+    error::ApiError,
+    models::{
+        departments::{DepartData, NewDepart},
+        doctors::DoctorDepart,
+    },
+    protocol::DepartItem,
+    schedule::last_insert_id,
+};
+
+sql_function! {
+    /// The department of a slot, its doctor's main department for slots from before they
+    /// had their own, to join `departments` on.
+    #[sql_name = "COALESCE"]
+    fn slot_or_main(dpid: Nullable<Unsigned<Bigint>>, main: Unsigned<Bigint>) -> Unsigned<Bigint>;
+}
+
+// Departments form a tree by `parent`. Everything refers to a department by its id: the
+// main department of doctors and the department of department admins, the departments a
+// doctor consults in, the main one included, in `doctor_departments`, and the department of
+// time slots and templates. Renaming only touches `departments`.
+
+pub fn find(conn: &MysqlConnection, dpid: u64) -> anyhow::Result<DepartData> {
+    use crate::schema::departments;
+
+    departments::table
+        .filter(departments::dpid.eq(dpid))
+        .get_result::<DepartData>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::DepartNotFound)
+}
+
+/// The name of the department, retired or not.
+pub fn name_of(conn: &MysqlConnection, dpid: u64) -> anyhow::Result<String> {
+    Ok(find(conn, dpid)?.depart_name)
+}
+
+/// The id of the active department named `name`.
+pub fn id_of(conn: &MysqlConnection, name: &str) -> anyhow::Result<u64> {
+    use crate::schema::departments;
+
+    departments::table
+        .filter(departments::depart_name.eq(name))
+        .filter(departments::retired.eq(false))
+        .select(departments::dpid)
+        .get_result::<u64>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::DepartNotFound)
+}
+
+/// The department and its sub-departments, at any depth.
+pub fn subtree(conn: &MysqlConnection, dpid: u64) -> anyhow::Result<Vec<u64>> {
+    use crate::schema::departments;
+
+    let mut ids = vec![dpid];
+    let mut level = vec![dpid];
+    while !level.is_empty() {
+        level = departments::table
+            .filter(departments::parent.eq_any(&level))
+            .select(departments::dpid)
+            .get_results::<u64>(conn)
+            .context(ApiError::Database)?
+            .into_iter()
+            .filter(|id| !ids.contains(id))
+            .collect();
+        ids.extend(&level);
+    }
+    Ok(ids)
+}
+
+/// The names of the departments of each doctor, main department first.
+pub fn doctor_departs(
+    conn: &MysqlConnection,
+    dids: &[String],
+) -> anyhow::Result<HashMap<String, Vec<String>>> {
+    use crate::schema::{departments, doctor_departments, doctors};
+
+    let rows = doctor_departments::table
+        .inner_join(departments::table.on(departments::dpid.eq(doctor_departments::dpid)))
+        .inner_join(doctors::table.on(doctors::did.eq(doctor_departments::did)))
+        .filter(doctor_departments::did.eq_any(dids))
+        .order(departments::depart_name.asc())
+        .select((
+            doctor_departments::did,
+            departments::depart_name,
+            departments::dpid,
+            doctors::dpid,
+        ))
+        .get_results::<(String, String, u64, u64)>(conn)
+        .context(ApiError::Database)?;
+
+    let mut res = HashMap::<String, Vec<String>>::new();
+    for (did, name, dpid, main) in rows {
+        let names = res.entry(did).or_default();
+        if dpid == main {
+            names.insert(0, name);
+        } else {
+            names.push(name);
+        }
+    }
+    Ok(res)
+}
+
+/// The names of the departments the doctor belongs to, the main one first.
+pub fn names_of(conn: &MysqlConnection, did: &str) -> anyhow::Result<Vec<String>> {
+    Ok(doctor_departs(conn, &[did.to_string()])?
+        .remove(did)
+        .unwrap_or_default())
+}
+
+/// The main department of the doctor.
+pub fn main_of(conn: &MysqlConnection, did: &str) -> anyhow::Result<u64> {
+    use crate::schema::doctors;
+
+    doctors::table
+        .filter(doctors::did.eq(did))
+        .select(doctors::dpid)
+        .get_result::<u64>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::DoctorNotFound)
+}
+
+/// Adds the department to the ones of the doctor.
+pub fn join(conn: &MysqlConnection, did: &str, dpid: u64) -> anyhow::Result<()> {
+    use crate::schema::doctor_departments;
+
+    if find(conn, dpid)?.retired {
+        bail!(ApiError::DepartNotFound);
+    }
+    let data = DoctorDepart {
+        did: did.to_string(),
+        dpid,
+    };
+    diesel::replace_into(doctor_departments::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// Takes the department from the ones of the doctor. Its templates and future slots move to
+/// the main department.
+pub fn leave(conn: &MysqlConnection, did: &str, dpid: u64) -> anyhow::Result<()> {
+    let main = main_of(conn, did)?;
+    if main == dpid {
+        bail!(ApiError::MainDepart);
+    }
+    detach(conn, did, dpid, main)
+}
+
+/// Syncs the departments of the doctor after its main department changed from `old` to
+/// `new`. The templates and future slots of the old one move to the new one.
+pub fn change_main(conn: &MysqlConnection, did: &str, old: u64, new: u64) -> anyhow::Result<()> {
+    join(conn, did, new)?;
+    if old != new {
+        detach(conn, did, old, new)?;
+    }
+    Ok(())
+}
+
+fn detach(conn: &MysqlConnection, did: &str, from: u64, to: u64) -> anyhow::Result<()> {
+    use crate::schema::{doctor_departments, schedules, times};
+
+    let deleted = diesel::delete(
+        doctor_departments::table
+            .filter(doctor_departments::did.eq(did))
+            .filter(doctor_departments::dpid.eq(from)),
+    )
+    .execute(conn)
+    .context(ApiError::Database)?;
+    if deleted == 0 {
+        bail!(ApiError::DoctorNotInDepart);
+    }
+
+    diesel::update(
+        schedules::table
+            .filter(schedules::did.eq(did))
+            .filter(schedules::dpid.eq(from)),
+    )
+    .set(schedules::dpid.eq(to))
+    .execute(conn)
+    .context(ApiError::Database)?;
+    diesel::update(
+        times::table
+            .filter(times::did.eq(did))
+            .filter(times::dpid.eq(from))
+            .filter(times::start_time.gt(Utc::now().naive_utc())),
+    )
+    .set(times::dpid.eq(to))
+    .execute(conn)
+    .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// The department a new slot or template of the doctor is for: `dpid` if it is one of the
+/// doctor's, the main department when unset.
+pub fn slot_depart(conn: &MysqlConnection, did: &str, dpid: Option<u64>) -> anyhow::Result<u64> {
+    use crate::schema::doctor_departments;
+
+    match dpid {
+        Some(dpid) => {
+            let res = doctor_departments::table
+                .filter(doctor_departments::did.eq(did))
+                .filter(doctor_departments::dpid.eq(dpid))
+                .count()
+                .get_result::<i64>(conn)
+                .context(ApiError::Database)?;
+            if res == 0 {
+                bail!(ApiError::DoctorNotInDepart);
+            }
+            Ok(dpid)
+        }
+        None => main_of(conn, did),
+    }
+}
+
+fn assert_name_free(conn: &MysqlConnection, name: &str) -> anyhow::Result<()> {
+    use crate::schema::departments;
+
+    let res = departments::table
+        .filter(departments::depart_name.eq(name))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    if res > 0 {
+        bail!(ApiError::DuplicateDepart);
+    }
+    Ok(())
+}
+
+/// Whether `dpid` is `ancestor` or lies below it.
+fn within(conn: &MysqlConnection, dpid: u64, ancestor: u64) -> anyhow::Result<bool> {
+    let mut current = Some(dpid);
+    while let Some(id) = current {
+        if id == ancestor {
+            return Ok(true);
+        }
+        current = find(conn, id)?.parent;
+    }
+    Ok(false)
+}
+
+/// Checks that `parent` can take `dpid` as a sub-department, `dpid` being unset for a new
+/// department.
+fn assert_parent(conn: &MysqlConnection, dpid: Option<u64>, parent: u64) -> anyhow::Result<()> {
+    if find(conn, parent)?.retired {
+        bail!(ApiError::DepartNotFound);
+    }
+    if let Some(dpid) = dpid {
+        if within(conn, parent, dpid)? {
+            bail!(ApiError::DepartCycle);
+        }
+    }
+    Ok(())
+}
+
+pub fn create(
+    conn: &MysqlConnection,
+    name: String,
+    information: String,
+    parent: Option<u64>,
+) -> anyhow::Result<u64> {
+    use crate::schema::departments;
+
+    assert_name_free(conn, &name)?;
+    if let Some(parent) = parent {
+        assert_parent(conn, None, parent)?;
+    }
+
+    let data = NewDepart {
+        depart_name: name,
+        information,
+        parent,
+    };
+    diesel::insert_into(departments::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::select(last_insert_id)
+        .get_result::<u64>(conn)
+        .context(ApiError::Database)
+}
+
+/// Moves the department below `parent`, or to the top level.
+pub fn move_to(conn: &MysqlConnection, dpid: u64, parent: Option<u64>) -> anyhow::Result<()> {
+    use crate::schema::departments;
+
+    if find(conn, dpid)?.retired {
+        bail!(ApiError::DepartNotFound);
+    }
+    if let Some(parent) = parent {
+        assert_parent(conn, Some(dpid), parent)?;
+    }
+
+    diesel::update(departments::table.filter(departments::dpid.eq(dpid)))
+        .set(departments::parent.eq(parent))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// Points the doctors and department admins of department `from` to `to`.
+fn reassign(conn: &MysqlConnection, from: u64, to: u64) -> anyhow::Result<usize> {
+    use crate::schema::{administrators, doctors};
+
+    let moved = diesel::update(doctors::table.filter(doctors::dpid.eq(from)))
+        .set(doctors::dpid.eq(to))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::update(administrators::table.filter(administrators::dpid.eq(from)))
+        .set(administrators::dpid.eq(to))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(moved)
+}
+
+pub fn rename(conn: &MysqlConnection, dpid: u64, name: String) -> anyhow::Result<()> {
+    use crate::schema::departments;
+
+    let data = find(conn, dpid)?;
+    if data.depart_name == name {
+        return Ok(());
+    }
+    assert_name_free(conn, &name)?;
+
+    diesel::update(departments::table.filter(departments::dpid.eq(dpid)))
+        .set(departments::depart_name.eq(&name))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// Moves the doctors, department admins, sub-departments, templates and slots of `dpid` to
+/// `into` and retires `dpid`. Returns the number of doctors whose main department moved.
+pub fn merge(conn: &MysqlConnection, dpid: u64, into: u64) -> anyhow::Result<usize> {
+    use crate::schema::{departments, doctor_departments, schedules, times};
+
+    let data = find(conn, dpid)?;
+    let target = find(conn, into)?;
+    if data.retired || target.retired {
+        bail!(ApiError::DepartNotFound);
+    }
+    if within(conn, into, dpid)? {
+        bail!(ApiError::DepartCycle);
+    }
+
+    let moved = reassign(conn, dpid, into)?;
+    let members = doctor_departments::table
+        .filter(doctor_departments::dpid.eq(dpid))
+        .select(doctor_departments::did)
+        .get_results::<String>(conn)
+        .context(ApiError::Database)?
+        .into_iter()
+        .map(|did| DoctorDepart { did, dpid: into })
+        .collect::<Vec<_>>();
+    diesel::replace_into(doctor_departments::table)
+        .values(members)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::delete(doctor_departments::table.filter(doctor_departments::dpid.eq(dpid)))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::update(schedules::table.filter(schedules::dpid.eq(dpid)))
+        .set(schedules::dpid.eq(into))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::update(times::table.filter(times::dpid.eq(dpid)))
+        .set(times::dpid.eq(into))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::update(departments::table.filter(departments::parent.eq(dpid)))
+        .set(departments::parent.eq(into))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::update(departments::table.filter(departments::dpid.eq(dpid)))
+        .set(departments::retired.eq(true))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(moved)
+}
+
+/// Retires a department without doctors, department admins or active sub-departments.
+pub fn retire(conn: &MysqlConnection, dpid: u64) -> anyhow::Result<()> {
+    use crate::schema::{administrators, departments, doctor_departments};
+
+    let data = find(conn, dpid)?;
+    if data.retired {
+        bail!(ApiError::DepartNotFound);
+    }
+
+    let doctors = doctor_departments::table
+        .filter(doctor_departments::dpid.eq(dpid))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    let admins = administrators::table
+        .filter(administrators::dpid.eq(dpid))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    let children = departments::table
+        .filter(departments::parent.eq(dpid))
+        .filter(departments::retired.eq(false))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    if doctors + admins + children > 0 {
+        bail!(ApiError::DepartNotEmpty);
+    }
+
+    diesel::update(departments::table.filter(departments::dpid.eq(dpid)))
+        .set(departments::retired.eq(true))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// The departments whose name matches `name_pattern`, with their ancestors and all their
+/// sub-departments, as trees sorted by name. Pagination counts the top-level departments.
+pub fn tree(
+    conn: &MysqlConnection,
+    name_pattern: &str,
+    with_retired: bool,
+    first_index: i64,
+    limit: i64,
+) -> anyhow::Result<Vec<DepartItem>> {
+    use crate::schema::departments;
+
+    let mut query = departments::table.into_boxed();
+    if !with_retired {
+        query = query.filter(departments::retired.eq(false));
+    }
+    let departs = query
+        .order(departments::depart_name.asc())
+        .get_results::<DepartData>(conn)
+        .context(ApiError::Database)?;
+    let matched = departments::table
+        .filter(departments::depart_name.like(name_pattern))
+        .select(departments::dpid)
+        .get_results::<u64>(conn)
+        .context(ApiError::Database)?
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    // Departments below a retired parent that is left out are shown at the top level.
+    let ids = departs.iter().map(|data| data.dpid).collect::<HashSet<_>>();
+    let mut children = HashMap::<Option<u64>, Vec<DepartData>>::new();
+    for data in departs {
+        let parent = data.parent.filter(|parent| ids.contains(parent));
+        children.entry(parent).or_default().push(data);
+    }
+
+    let roots = children.remove(&None).unwrap_or_default();
+    Ok(roots
+        .into_iter()
+        .filter_map(|data| build(data, false, &matched, &mut children))
+        .skip(first_index as usize)
+        .take(limit as usize)
+        .collect())
+}
+
+fn build(
+    data: DepartData,
+    ancestor_matched: bool,
+    matched: &HashSet<u64>,
+    children: &mut HashMap<Option<u64>, Vec<DepartData>>,
+) -> Option<DepartItem> {
+    let shown = ancestor_matched || matched.contains(&data.dpid);
+    let subs = children
+        .remove(&Some(data.dpid))
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|child| build(child, shown, matched, children))
+        .collect::<Vec<_>>();
+    if !shown && subs.is_empty() {
+        return None;
+    }
+    Some(DepartItem {
+        dpid: data.dpid,
+        name: data.depart_name,
+        info: data.information,
+        parent: data.parent,
+        retired: data.retired,
+        children: subs,
+    })
+}
@@ -0,0 +1,79 @@
+mod requests;
+mod responses;
+
+use crate::{
+    config::Config,
+    database::get_db_conn,
+    events::{self, Broker},
+    protocol::QueueItem,
+    queue::{self, Serving},
+    DbPool,
+};
+use actix::Addr;
+use actix_web::{post, web, HttpRequest, HttpResponse};
+
+use self::{requests::*, responses::*};
+
+// Read-only endpoints for the screens in the waiting room, no login needed.
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(view_queue)
+        .route("/queue_stream", web::get().to(queue_stream));
+}
+
+crate::post_funcs! {
+    (view_queue, "/view_queue", ViewQueueRequest, ViewQueueResponse),
+}
+
+async fn serving_today(
+    pool: &web::Data<DbPool>,
+    info: ViewQueueRequest,
+) -> anyhow::Result<Vec<Serving>> {
+    let conn = get_db_conn(pool)?;
+    let res = web::block(move || queue::today(&conn, info.did, info.department)).await?;
+    Ok(res)
+}
+
+async fn view_queue_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    info: web::Json<ViewQueueRequest>,
+) -> anyhow::Result<ViewQueueResponse> {
+    let queues = serving_today(&pool, info.into_inner())
+        .await?
+        .iter()
+        .map(QueueItem::from)
+        .collect();
+
+    Ok(ViewQueueResponse {
+        success: true,
+        err: "".to_string(),
+        queues,
+    })
+}
+
+/// Streams a `queue` event whenever a doctor calls a patient, starting with the current state.
+async fn queue_stream(
+    pool: web::Data<DbPool>,
+    broker: web::Data<Addr<Broker>>,
+    req: HttpRequest,
+    info: web::Query<ViewQueueRequest>,
+) -> HttpResponse {
+    let info = info.into_inner();
+    let topics = match (&info.did, &info.department) {
+        (Some(did), _) => vec![format!("queue/doctor/{}", did)],
+        (None, Some(department)) => vec![format!("queue/depart/{}", department)],
+        (None, None) => vec!["queue".to_string()],
+    };
+
+    match serving_today(&pool, info).await {
+        Ok(servings) => {
+            let initial = servings
+                .iter()
+                .map(|serving| events::frame("queue", &QueueItem::from(serving)))
+                .collect();
+            events::stream(&broker, topics, initial)
+        }
+        Err(err) => crate::i18n::localized_response::<ViewQueueResponse>(&req, Err(err)),
+    }
+}
@@ -0,0 +1,10 @@
+use serde::Deserialize;
+
+/// Both filters are optional, all doctors are shown without them.
+#[derive(Default, Deserialize)]
+pub struct ViewQueueRequest {
+    #[serde(default)]
+    pub did: Option<String>,
+    #[serde(default)]
+    pub department: Option<String>,
+}
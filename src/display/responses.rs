@@ -0,0 +1,9 @@
+use crate::protocol::QueueItem;
+use serde::Serialize;
+
+#[derive(Default, Serialize)]
+pub struct ViewQueueResponse {
+    pub success: bool,
+    pub err: String,
+    pub queues: Vec<QueueItem>,
+}
@@ -1,32 +1,50 @@
 mod requests;
 mod responses;
-mod utils;
 
 use crate::{
-    database::{assert, get_db_conn},
+    auth::AuthedDoctor,
+    booking::{self, Actor},
+    config::Config,
+    database::{self, assert, get_db_conn, owner},
+    depart,
+    error::ApiError,
+    events::{self, Broker},
+    leave, location,
     models::{
-        appointments::{Appointment, APPOINT_STATUS_FINISHED, APPOINT_STATUS_UNFINISHED},
-        comments::Comment,
+        appointments::{AppointStatus, Appointment},
+        comments::{Comment, CommentStatus},
         departments::DepartData,
-        doctor_logins::DoctorLoginData,
         doctors::{DoctorData, UpdateDoctor},
         times::{NewTime, TimeData, UpdateTime},
         users::UserData,
     },
-    protocol::SimpleResponse,
+    notify,
+    protocol::{
+        AddLeaveResponse, AddScheduleResponse, ModifyScheduleResponse, SearchLeaveResponse,
+        SearchScheduleResponse, SimpleResponse,
+    },
+    queue::{self, Serving},
+    review,
+    schedule::{self, ScheduleInput},
     DbPool,
 };
-use actix_web::{post, web, HttpResponse, Responder};
+use actix::Addr;
+use actix_web::{post, web, HttpRequest, HttpResponse};
 use anyhow::{bail, Context};
-use blake2::{Blake2b, Digest};
 use chrono::{Datelike, NaiveDate, Utc};
 use diesel::prelude::*;
 
-use self::{requests::*, responses::*, utils::get_did_from_token};
+use self::{requests::*, responses::*};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(login)
         .service(logout)
+        .service(logout_all)
+        .route(
+            "/refresh",
+            web::post().to(crate::auth::refresh::<AuthedDoctor>),
+        )
+        .route("/events", web::get().to(event_stream))
         .service(view_info)
         .service(modify_password)
         .service(modify_info)
@@ -36,59 +54,97 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         .service(search_time)
         .service(search_appoint)
         .service(finish_appoint)
-        .service(search_comment);
+        .service(update_appoint_status)
+        .service(call_next)
+        .service(skip_patient)
+        .service(recall_patient)
+        .service(search_comment)
+        .service(report_comment)
+        .service(reply_comment)
+        .service(modify_reply)
+        .service(delete_reply)
+        .service(add_schedule)
+        .service(modify_schedule)
+        .service(delete_schedule)
+        .service(search_schedule)
+        .service(add_leave)
+        .service(delete_leave)
+        .service(search_leave);
 }
 
 crate::post_funcs! {
     (login, "/login", LoginRequest, LoginResponse),
-    (logout, "/logout", LogoutRequest, SimpleResponse),
-    (view_info, "/view_info", ViewInfoRequest, ViewInfoResponse),
-    (modify_password, "/modify_password", ModifyPasswordRequest, SimpleResponse),
-    (modify_info, "/modify_info", ModifyInfoRequest, SimpleResponse),
-    (add_time, "/add_time", AddTimeRequest, AddTimeResponse),
-    (modify_time, "/modify_time", ModifyTimeRequest, SimpleResponse),
-    (delete_time, "/delete_time", DeleteTimeRequest, SimpleResponse),
-    (search_time, "/search_time", SearchTimeRequest, SearchTimeResponse),
-    (search_appoint, "/search_appoint", SearchAppointRequest, SearchAppointResponse),
-    (finish_appoint, "/finish_appoint", FinishAppointRequest, SimpleResponse),
-    (search_comment, "/search_comment", SearchCommentRequest, SearchCommentResponse),
+    (logout, "/logout", LogoutRequest, SimpleResponse, AuthedDoctor),
+    (logout_all, "/logout_all", LogoutAllRequest, SimpleResponse, AuthedDoctor),
+    (view_info, "/view_info", ViewInfoRequest, ViewInfoResponse, AuthedDoctor),
+    (modify_password, "/modify_password", ModifyPasswordRequest, SimpleResponse, AuthedDoctor),
+    (modify_info, "/modify_info", ModifyInfoRequest, SimpleResponse, AuthedDoctor),
+    (add_time, "/add_time", AddTimeRequest, AddTimeResponse, AuthedDoctor),
+    (modify_time, "/modify_time", ModifyTimeRequest, SimpleResponse, AuthedDoctor, events),
+    (delete_time, "/delete_time", DeleteTimeRequest, SimpleResponse, AuthedDoctor, events),
+    (search_time, "/search_time", SearchTimeRequest, SearchTimeResponse, AuthedDoctor),
+    (search_appoint, "/search_appoint", SearchAppointRequest, SearchAppointResponse, AuthedDoctor),
+    (finish_appoint, "/finish_appoint", FinishAppointRequest, SimpleResponse, AuthedDoctor, events),
+    (update_appoint_status, "/update_appoint_status", UpdateAppointStatusRequest, SimpleResponse, AuthedDoctor, events),
+    (call_next, "/call_next", CallNextRequest, CallPatientResponse, AuthedDoctor, events),
+    (skip_patient, "/skip_patient", SkipPatientRequest, CallPatientResponse, AuthedDoctor, events),
+    (recall_patient, "/recall_patient", RecallPatientRequest, CallPatientResponse, AuthedDoctor, events),
+    (search_comment, "/search_comment", SearchCommentRequest, SearchCommentResponse, AuthedDoctor),
+    (report_comment, "/report_comment", ReportCommentRequest, SimpleResponse, AuthedDoctor),
+    (reply_comment, "/reply_comment", ReplyCommentRequest, SimpleResponse, AuthedDoctor),
+    (modify_reply, "/modify_reply", ReplyCommentRequest, SimpleResponse, AuthedDoctor),
+    (delete_reply, "/delete_reply", DeleteReplyRequest, SimpleResponse, AuthedDoctor),
+    (add_schedule, "/add_schedule", AddScheduleRequest, AddScheduleResponse, AuthedDoctor),
+    (modify_schedule, "/modify_schedule", ModifyScheduleRequest, ModifyScheduleResponse, AuthedDoctor),
+    (delete_schedule, "/delete_schedule", DeleteScheduleRequest, SimpleResponse, AuthedDoctor),
+    (search_schedule, "/search_schedule", SearchScheduleRequest, SearchScheduleResponse, AuthedDoctor),
+    (add_leave, "/add_leave", AddLeaveRequest, AddLeaveResponse, AuthedDoctor, events),
+    (delete_leave, "/delete_leave", DeleteLeaveRequest, SimpleResponse, AuthedDoctor, events),
+    (search_leave, "/search_leave", SearchLeaveRequest, SearchLeaveResponse, AuthedDoctor),
+}
+
+/// Streams `appointment` and `time` events of the doctor's appointments and slots. Browsers
+/// can't set headers on an `EventSource`, so the login token may also be passed in the query.
+async fn event_stream(
+    pool: web::Data<DbPool>,
+    broker: web::Data<Addr<Broker>>,
+    req: HttpRequest,
+    info: web::Query<EventsRequest>,
+) -> HttpResponse {
+    let token = info.into_inner().login_token;
+    match crate::auth::authenticate::<AuthedDoctor>(&req, &pool, token).await {
+        Ok(doctor) => events::stream(&broker, vec![notify::doctor_topic(&doctor.did)], vec![]),
+        Err(err) => crate::i18n::localized_response::<SimpleResponse>(&req, Err(err)),
+    }
 }
 
 async fn login_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
     info: web::Json<LoginRequest>,
 ) -> anyhow::Result<LoginResponse> {
-    use crate::schema::{doctor_logins, doctors};
+    use crate::schema::doctors;
 
     let info = info.into_inner();
     assert::assert_doctor(&pool, info.did.clone()).await?;
 
     let conn = get_db_conn(&pool)?;
-    let login_token = web::block(move || {
-        conn.transaction(|| {
-            let hashed_password = format!("{:x}", Blake2b::digest(info.password.as_bytes()));
-            let res = doctors::table
+    let (login_token, refresh_token) = web::block(move || {
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let stored_password = doctors::table
                 .filter(doctors::did.eq(&info.did))
-                .filter(doctors::password.eq(hashed_password))
-                .count()
-                .get_result::<i64>(&conn)
-                .context("数据库错误")?;
-            if res != 1 {
-                bail!("密码错误");
+                .select(doctors::password)
+                .get_result::<String>(&conn)
+                .context(ApiError::Database)?;
+            if crate::password::verify_password(&info.password, &stored_password)? {
+                let rehashed_password = crate::password::hash_password(&info.password)?;
+                diesel::update(doctors::table.filter(doctors::did.eq(&info.did)))
+                    .set(doctors::password.eq(&rehashed_password))
+                    .execute(&conn)
+                    .context(ApiError::Database)?;
             }
 
-            let login_token = crate::utils::generate_login_token(&info.did, "doctor");
-            let token_data = DoctorLoginData {
-                token: login_token.clone(),
-                did: info.did,
-                login_time: Utc::now().naive_utc(),
-            };
-            diesel::insert_into(doctor_logins::table)
-                .values(token_data)
-                .execute(&conn)
-                .context("数据库错误")?;
-
-            Ok(login_token)
+            crate::auth::create_session::<AuthedDoctor>(&conn, &info.did)
         })
     })
     .await?;
@@ -97,45 +153,67 @@ async fn login_impl(
         success: true,
         err: "".to_string(),
         login_token,
+        refresh_token,
     })
 }
 
 async fn logout_impl(
     pool: web::Data<DbPool>,
-    info: web::Json<LogoutRequest>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    _info: web::Json<LogoutRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::doctor_logins;
 
-    let info = info.into_inner();
     let conn = get_db_conn(&pool)?;
     web::block(move || {
-        diesel::delete(doctor_logins::table.filter(doctor_logins::token.eq(info.login_token)))
+        diesel::delete(doctor_logins::table.filter(doctor_logins::token.eq(doctor.token_hash)))
             .execute(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn logout_all_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    _info: web::Json<LogoutAllRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    use crate::schema::doctor_logins;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || {
+        diesel::delete(doctor_logins::table.filter(doctor_logins::did.eq(doctor.did)))
+            .execute(&conn)
+    })
+    .await
+    .context(ApiError::Database)?;
 
     Ok(SimpleResponse::ok())
 }
 
 async fn view_info_impl(
     pool: web::Data<DbPool>,
-    info: web::Json<ViewInfoRequest>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    _info: web::Json<ViewInfoRequest>,
 ) -> anyhow::Result<ViewInfoResponse> {
     use crate::schema::{departments, doctors};
 
-    let info = info.into_inner();
-    let did = get_did_from_token(info.login_token, &pool).await?;
+    let did = doctor.did;
 
     let conn = get_db_conn(&pool)?;
     let (doctor_data, depart_data) = web::block(move || {
         doctors::table
             .filter(doctors::did.eq(&did))
-            .inner_join(departments::table.on(doctors::department.eq(departments::depart_name)))
+            .inner_join(departments::table.on(departments::dpid.eq(doctors::dpid)))
             .get_result::<(DoctorData, DepartData)>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     let res = ViewInfoResponse {
         success: true,
@@ -151,7 +229,7 @@ async fn view_info_impl(
         gender: doctor_data.gender,
         rankk: doctor_data.rank,
         info: doctor_data.information,
-        depart: doctor_data.department,
+        depart: depart_data.depart_name,
         depart_info: depart_data.information,
     };
 
@@ -160,34 +238,34 @@ async fn view_info_impl(
 
 async fn modify_password_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
     info: web::Json<ModifyPasswordRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::doctors;
+    use crate::schema::{doctor_logins, doctors};
 
     let info = info.into_inner();
-    let did = get_did_from_token(info.login_token.clone(), &pool).await?;
+    let did = doctor.did;
 
     let conn = get_db_conn(&pool)?;
     web::block(move || {
-        conn.transaction(|| {
-            let hashed_password_old =
-                format!("{:x}", Blake2b::digest(info.password_old.as_bytes()));
-            let res = doctors::table
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let stored_password = doctors::table
                 .filter(doctors::did.eq(&did))
-                .filter(doctors::password.eq(&hashed_password_old))
-                .count()
-                .get_result::<i64>(&conn)
-                .context("数据库错误")?;
-            if res != 1 {
-                bail!("密码错误");
-            }
+                .select(doctors::password)
+                .get_result::<String>(&conn)
+                .context(ApiError::Database)?;
+            crate::password::verify_password(&info.password_old, &stored_password)?;
 
-            let hashed_password_new =
-                format!("{:x}", Blake2b::digest(info.password_new.as_bytes()));
+            let hashed_password_new = crate::password::hash_password(&info.password_new)?;
             diesel::update(doctors::table.filter(doctors::did.eq(&did)))
                 .set(doctors::password.eq(&hashed_password_new))
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
+
+            diesel::delete(doctor_logins::table.filter(doctor_logins::did.eq(&did)))
+                .execute(&conn)
+                .context(ApiError::Database)?;
 
             Ok(())
         })
@@ -199,12 +277,14 @@ async fn modify_password_impl(
 
 async fn modify_info_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
     info: web::Json<ModifyInfoRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::doctors;
 
     let info = info.into_inner();
-    let did = get_did_from_token(info.login_token, &pool).await?;
+    let did = doctor.did;
 
     let mut data = UpdateDoctor {
         name: info.name,
@@ -213,7 +293,8 @@ async fn modify_info_impl(
         ..Default::default()
     };
     if let Some(birthday) = info.birthday {
-        let birthday = NaiveDate::parse_from_str(&birthday, "%Y-%m-%d").context("生日格式错误")?;
+        let birthday =
+            NaiveDate::parse_from_str(&birthday, "%Y-%m-%d").context(ApiError::BirthdayFormat)?;
         data.birthday = Some(birthday);
     }
 
@@ -224,59 +305,63 @@ async fn modify_info_impl(
             .execute(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     Ok(SimpleResponse::ok())
 }
 
 async fn add_time_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    doctor: AuthedDoctor,
     info: web::Json<AddTimeRequest>,
 ) -> anyhow::Result<AddTimeResponse> {
     use crate::schema::times;
 
     let info = info.into_inner();
-    let did = get_did_from_token(info.login_token.clone(), &pool).await?;
-
-    let (start_time, end_time) = crate::utils::get_time_from_str(&info.date, &info.time)?;
-    // if start_time >= end_time {
-    //     bail!("非法的时间区间");
-    // }
+    let did = doctor.did;
+
+    let (start_time, end_time) = match (&info.start_time, &info.end_time, &info.date, &info.time) {
+        (Some(start_time), Some(end_time), _, _) => {
+            crate::utils::parse_time_pair_str(start_time, end_time)?
+        }
+        (None, None, Some(date), Some(time)) => {
+            crate::utils::get_time_from_str(date, time, &config.clinic)?
+        }
+        _ => bail!(ApiError::TimeFormat),
+    };
+    if start_time >= end_time {
+        bail!(ApiError::InvalidTimeRange);
+    }
 
     let conn = get_db_conn(&pool)?;
     let tid = web::block(move || {
-        conn.transaction(|| {
-            let res = times::table
-                .filter(times::did.eq(&did))
-                .filter(
-                    times::start_time
-                        .between(&start_time, &end_time)
-                        .or(times::end_time.between(&start_time, &end_time)),
-                )
-                .count()
-                .get_result::<i64>(&conn)
-                .context("数据库错误")?;
-            if res > 0 {
-                bail!("时间区间与已有的冲突");
-            }
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            database::times::assert_no_overlap(&conn, &did, &start_time, &end_time, None)?;
+            leave::assert_not_on_leave(&conn, &did, &start_time, &end_time)?;
+            let dpid = depart::slot_depart(&conn, &did, info.dpid)?;
+            location::assert_location(&conn, info.lcid)?;
 
             let data = NewTime {
                 did: did.clone(),
                 start_time: start_time.clone(),
                 end_time: end_time.clone(),
                 capacity: info.capacity,
+                sid: None,
+                dpid: Some(dpid),
+                lcid: info.lcid,
             };
             diesel::insert_into(times::table)
                 .values(data)
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
 
             let data = times::table
                 .filter(times::did.eq(did))
                 .filter(times::start_time.eq(start_time))
                 .filter(times::end_time.eq(end_time))
                 .get_result::<TimeData>(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
 
             Ok(data.tid)
         })
@@ -292,53 +377,87 @@ async fn add_time_impl(
 
 async fn modify_time_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
     info: web::Json<ModifyTimeRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::times;
 
     let info = info.into_inner();
-    get_did_from_token(info.login_token.clone(), &pool).await?;
+    let did = doctor.did;
     assert::assert_time(&pool, info.tid).await?;
 
     let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
     web::block(move || {
-        conn.transaction(|| {
-            let time_data = times::table
-                .filter(times::tid.eq(info.tid))
-                .get_result::<TimeData>(&conn)
-                .context("数据库错误")?;
+        notify::transaction(&conn, &broker, || {
+            owner::assert_time_owner(&conn, info.tid, &did)?;
 
-            // if time_data.appointed > 0 && (info.start_time.is_some() || info.end_time.is_some()) {
-            //     bail!("不能在已有用户预约的情况下修改时间")
-            // }
+            // held until commit, so no booking can slip in above the new capacity
+            let time_data = booking::lock_time(&conn, info.tid)?;
+
+            if time_data.appointed > 0 && (info.start_time.is_some() || info.end_time.is_some()) {
+                bail!(ApiError::TimeBooked);
+            }
 
             let mut data = UpdateTime::default();
             if let Some(cap) = info.capacity {
                 if cap < time_data.appointed {
-                    bail!(format!(
-                        "不能修改容量为 {}, 因为已经有 {} 个用户预约了",
-                        cap, time_data.appointed
-                    ));
+                    bail!(ApiError::CapacityBelowAppointed {
+                        capacity: cap,
+                        appointed: time_data.appointed,
+                    });
                 } else {
                     data.capacity = Some(cap);
                 }
             }
 
-            // if let Some(start_time) = info.start_time {
-            //     let start_time =
-            //         crate::utils::parse_time_str(&start_time).context("起始时间格式错误")?;
-            //     data.start_time = Some(start_time);
-            // }
-            // if let Some(end_time) = info.end_time {
-            //     let end_time =
-            //         crate::utils::parse_time_str(&end_time).context("结束时间格式错误")?;
-            //     data.end_time = Some(end_time);
-            // }
+            if info.start_time.is_some() || info.end_time.is_some() {
+                let start_time = match &info.start_time {
+                    Some(start_time) => crate::utils::parse_time_str(start_time)
+                        .context(ApiError::StartTimeFormat)?,
+                    None => time_data.start_time,
+                };
+                let end_time = match &info.end_time {
+                    Some(end_time) => {
+                        crate::utils::parse_time_str(end_time).context(ApiError::EndTimeFormat)?
+                    }
+                    None => time_data.end_time,
+                };
+                if start_time >= end_time {
+                    bail!(ApiError::InvalidTimeRange);
+                }
+                database::times::assert_no_overlap(
+                    &conn,
+                    &did,
+                    &start_time,
+                    &end_time,
+                    Some(info.tid),
+                )?;
+                leave::assert_not_on_leave(&conn, &did, &start_time, &end_time)?;
+                data.start_time = Some(start_time);
+                data.end_time = Some(end_time);
+                // moved out of a leave
+                data.available = Some(true);
+            }
+            if info.dpid.is_some() {
+                data.dpid = Some(depart::slot_depart(&conn, &did, info.dpid)?);
+            }
+            if info.lcid.is_some() {
+                location::assert_location(&conn, info.lcid)?;
+                data.lcid = info.lcid;
+            }
 
             diesel::update(times::table.filter(times::tid.eq(info.tid)))
                 .set(&data)
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
+
+            if data.capacity.is_some() {
+                booking::promote(&conn, info.tid, &config.booking, &config.penalty)?;
+            }
+            notify::time_changed(&conn, &booking::lock_time(&conn, info.tid)?, "modified")?;
 
             Ok(())
         })
@@ -350,29 +469,40 @@ async fn modify_time_impl(
 
 async fn delete_time_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
     info: web::Json<DeleteTimeRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::times;
+    use crate::schema::{times, waitlists};
 
     let info = info.into_inner();
-    get_did_from_token(info.login_token, &pool).await?;
+    let did = doctor.did;
     let tid = info.tid;
     assert::assert_time(&pool, tid).await?;
 
     let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
     web::block(move || {
-        conn.transaction(|| {
-            let time_data = times::table
-                .filter(times::tid.eq(tid))
-                .get_result::<TimeData>(&conn)
-                .context("数据库错误")?;
+        notify::transaction(&conn, &broker, || {
+            owner::assert_time_owner(&conn, tid, &did)?;
+
+            // held until the row is deleted, so no booking can slip in meanwhile
+            let time_data = booking::lock_time(&conn, tid)?;
             if time_data.appointed > 0 {
-                bail!("不能在已有用户预约的情况下删除时间");
+                bail!(ApiError::TimeHasAppointments);
+            }
+            if let Some(sid) = time_data.sid {
+                schedule::skip_date(&conn, sid, time_data.start_time.date())?;
             }
+            notify::time_changed(&conn, &time_data, "deleted")?;
 
             diesel::delete(times::table.filter(times::tid.eq(tid)))
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
+            diesel::delete(waitlists::table.filter(waitlists::tid.eq(tid)))
+                .execute(&conn)
+                .context(ApiError::Database)?;
 
             Ok(())
         })
@@ -384,18 +514,20 @@ async fn delete_time_impl(
 
 async fn search_time_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    doctor: AuthedDoctor,
     info: web::Json<SearchTimeRequest>,
 ) -> anyhow::Result<SearchTimeResponse> {
     use crate::schema::times;
 
     let info = info.into_inner();
-    let did = get_did_from_token(info.login_token, &pool).await?;
+    let did = doctor.did;
 
     let (start_time, end_time) = crate::utils::get_time_pair_from_date_opt(info.date)?;
 
     let conn = get_db_conn(&pool)?;
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(30).max(0);
+    let limit = config.pagination.limit(info.limit);
     let tms = web::block(move || {
         times::table
             .filter(times::did.eq(&did))
@@ -407,16 +539,22 @@ async fn search_time_impl(
             .get_results::<TimeData>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     let tms = tms
         .into_iter()
         .map(|data| SearchTimeItem {
             tid: data.tid,
             date: data.start_time.date().format("%Y-%m-%d").to_string(),
-            time: crate::utils::get_time_str(&data.start_time, &data.end_time).to_owned(),
+            time: crate::utils::get_period(&data.start_time, &data.end_time, &config.clinic),
+            start_time: crate::utils::format_time_str(&data.start_time),
+            end_time: crate::utils::format_time_str(&data.end_time),
             capacity: data.capacity,
             rest: data.capacity - data.appointed,
+            sid: data.sid,
+            available: data.available,
+            dpid: data.dpid,
+            lcid: data.lcid,
         })
         .collect();
 
@@ -429,31 +567,31 @@ async fn search_time_impl(
 
 async fn search_appoint_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    doctor: AuthedDoctor,
     info: web::Json<SearchAppointRequest>,
 ) -> anyhow::Result<SearchAppointResponse> {
     use crate::schema::{appointments, times, users};
 
     let info = info.into_inner();
-    let did = get_did_from_token(info.login_token, &pool).await?;
+    let did = doctor.did;
 
     let (start_time, end_time) =
         crate::utils::parse_time_pair_str_opt(info.start_time, info.end_time)?;
 
     let conn = get_db_conn(&pool)?;
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(30).max(0);
-    let status = if info.status.is_empty() {
-        APPOINT_STATUS_UNFINISHED.to_string()
-    } else {
-        info.status
-    };
+    let limit = config.pagination.limit(info.limit);
+    let status = AppointStatus::parse_filter(&info.status)?;
+    let all_status = status.is_none();
+    let status = status.unwrap_or_default().as_str();
     let appos = web::block(move || {
         times::table
             .filter(times::did.eq(&did))
             .filter(times::start_time.ge(&start_time))
             .filter(times::end_time.le(&end_time))
             .inner_join(appointments::table.on(times::tid.eq(appointments::tid)))
-            .filter((appointments::status.eq(&status)).or(&status == "所有"))
+            .filter(appointments::status.eq(status).or(all_status))
             .inner_join(users::table.on(appointments::username.eq(users::username)))
             .order(times::start_time.desc())
             .offset(first_index)
@@ -461,7 +599,7 @@ async fn search_appoint_impl(
             .get_results::<(TimeData, Appointment, UserData)>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     let appos = appos
         .into_iter()
@@ -473,9 +611,21 @@ async fn search_appoint_impl(
                 .map_or(-1, |birth| Utc::now().year() - birth.year()),
             tid: time_data.tid,
             date: time_data.start_time.date().format("%Y-%m-%d").to_string(),
-            time: crate::utils::get_time_str(&time_data.start_time, &time_data.end_time).to_owned(),
-            status: appo_data.status,
+            time: crate::utils::get_period(
+                &time_data.start_time,
+                &time_data.end_time,
+                &config.clinic,
+            ),
+            start_time: crate::utils::format_time_str(&time_data.start_time),
+            end_time: crate::utils::format_time_str(&time_data.end_time),
+            status: AppointStatus::parse(&appo_data.status).unwrap_or_default(),
             appo_time: crate::utils::format_time_str(&appo_data.time),
+            queue_number: appo_data.queue_number,
+            called_at: appo_data
+                .called_at
+                .as_ref()
+                .map(crate::utils::format_time_str),
+            skipped: appo_data.skipped,
         })
         .collect();
 
@@ -488,87 +638,177 @@ async fn search_appoint_impl(
 
 async fn finish_appoint_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
     info: web::Json<FinishAppointRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    // use crate::schema::{appointments, times};
-    use crate::schema::appointments;
+    let info = info.into_inner();
+    let did = doctor.did;
+
+    let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
+    web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            owner::assert_time_owner(&conn, info.tid, &did)?;
+            booking::transition(
+                &conn,
+                &info.username,
+                info.tid,
+                AppointStatus::Finished,
+                Actor::Doctor(&did),
+                &config.booking,
+                &config.penalty,
+            )
+        })
+    })
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
 
+async fn update_appoint_status_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
+    info: web::Json<UpdateAppointStatusRequest>,
+) -> anyhow::Result<SimpleResponse> {
     let info = info.into_inner();
-    get_did_from_token(info.login_token, &pool).await?;
+    let did = doctor.did;
+    let status = AppointStatus::parse(&info.status)?;
 
     let conn = get_db_conn(&pool)?;
-    let username = info.username;
-    let tid = info.tid;
+    let broker = broker.get_ref().clone();
     web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            owner::assert_time_owner(&conn, info.tid, &did)?;
+            booking::transition(
+                &conn,
+                &info.username,
+                info.tid,
+                status,
+                Actor::Doctor(&did),
+                &config.booking,
+                &config.penalty,
+            )
+        })
+    })
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+fn call_patient_response(broker: &Addr<Broker>, serving: Serving) -> CallPatientResponse {
+    queue::publish(broker, &serving);
+    CallPatientResponse {
+        success: true,
+        err: "".to_string(),
+        serving_number: serving.serving_number,
+        username: serving.username,
+        waiting: serving.waiting,
+    }
+}
+
+async fn call_next_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
+    info: web::Json<CallNextRequest>,
+) -> anyhow::Result<CallPatientResponse> {
+    let did = doctor.did;
+    let tid = info.tid;
+
+    let conn = get_db_conn(&pool)?;
+    let serving = web::block(move || {
         conn.transaction(|| {
-            let appo_data = appointments::table
-                .filter(appointments::username.eq(&username))
-                .filter(appointments::tid.eq(&tid))
-                .get_results::<Appointment>(&conn)
-                .context("数据库错误")?;
-            if appo_data.len() != 1 {
-                bail!("科室不存在");
-            }
-            if appo_data[0].status != APPOINT_STATUS_UNFINISHED {
-                bail!("只能完成未完成的预约");
-            }
+            owner::assert_time_owner(&conn, tid, &did)?;
+            queue::call_next(&conn, tid)
+        })
+    })
+    .await?;
 
-            // let time_data = times::table
-            //     .filter(times::tid.eq(&appo_data[0].tid))
-            //     .get_result::<TimeData>(&conn)
-            //     .context("数据库错误")?;
-            // let now = Utc::now().naive_utc();
-            // if now
-            //     .signed_duration_since(time_data.start_time)
-            //     .num_seconds()
-            //     < 0
-            // {
-            //     bail!("不能完成一个还在未来的预约");
-            // }
-
-            diesel::update(
-                appointments::table
-                    .filter(appointments::username.eq(&username))
-                    .filter(appointments::tid.eq(&tid)),
-            )
-            .set(appointments::status.eq(APPOINT_STATUS_FINISHED))
-            .execute(&conn)
-            .context("数据库错误")?;
+    Ok(call_patient_response(&broker, serving))
+}
 
-            Ok(())
+async fn skip_patient_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
+    info: web::Json<SkipPatientRequest>,
+) -> anyhow::Result<CallPatientResponse> {
+    let did = doctor.did;
+    let tid = info.tid;
+
+    let conn = get_db_conn(&pool)?;
+    let serving = web::block(move || {
+        conn.transaction(|| {
+            owner::assert_time_owner(&conn, tid, &did)?;
+            queue::skip(&conn, tid)
         })
     })
     .await?;
 
-    Ok(SimpleResponse::ok())
+    Ok(call_patient_response(&broker, serving))
+}
+
+async fn recall_patient_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
+    info: web::Json<RecallPatientRequest>,
+) -> anyhow::Result<CallPatientResponse> {
+    let did = doctor.did;
+    let tid = info.tid;
+    let queue_number = info.queue_number;
+
+    let conn = get_db_conn(&pool)?;
+    let serving = web::block(move || {
+        conn.transaction(|| {
+            owner::assert_time_owner(&conn, tid, &did)?;
+            queue::recall(&conn, tid, queue_number)
+        })
+    })
+    .await?;
+
+    Ok(call_patient_response(&broker, serving))
 }
 
 async fn search_comment_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    doctor: AuthedDoctor,
     info: web::Json<SearchCommentRequest>,
 ) -> anyhow::Result<SearchCommentResponse> {
     use crate::schema::comments;
 
     let info = info.into_inner();
-    let did = get_did_from_token(info.login_token, &pool).await?;
+    let did = doctor.did;
 
     let (start_time, end_time) =
         crate::utils::parse_time_pair_str_opt(info.start_time, info.end_time)?;
 
     let conn = get_db_conn(&pool)?;
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(30).max(0);
-    let cmts = web::block(move || {
-        comments::table
+    let limit = config.pagination.limit(info.limit);
+    let (cmts, mut replies) = web::block(move || -> anyhow::Result<_> {
+        let cmts = comments::table
             .filter(comments::did.eq(&did))
+            .filter(comments::status.eq(CommentStatus::Visible.as_str()))
             .filter(comments::time.between(start_time, end_time))
             .order(comments::time.desc())
             .offset(first_index)
             .limit(limit)
             .get_results::<Comment>(&conn)
+            .context(ApiError::Database)?;
+        let cids = cmts.iter().map(|data| data.cid).collect::<Vec<_>>();
+        let replies = review::replies(&conn, &cids)?;
+        Ok((cmts, replies))
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
 
     let cmts = cmts
         .into_iter()
@@ -577,6 +817,9 @@ async fn search_comment_impl(
             username: data.username,
             comment: data.comment,
             time: crate::utils::format_time_str(&data.time),
+            tid: data.tid,
+            rating: data.rating,
+            reply: replies.remove(&data.cid),
         })
         .collect();
 
@@ -586,3 +829,330 @@ async fn search_comment_impl(
         comments: cmts,
     })
 }
+
+async fn report_comment_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    info: web::Json<ReportCommentRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let did = doctor.did;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || {
+        conn.transaction(|| review::report(&conn, info.cid, Actor::Doctor(&did), info.reason))
+    })
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn reply_comment_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    info: web::Json<ReplyCommentRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let did = doctor.did;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || conn.transaction(|| review::reply(&conn, info.cid, &did, info.reply)))
+        .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn modify_reply_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    info: web::Json<ReplyCommentRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let did = doctor.did;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || review::modify_reply(&conn, info.cid, &did, info.reply)).await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn delete_reply_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    info: web::Json<DeleteReplyRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let did = doctor.did;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || review::delete_reply(&conn, info.cid, &did)).await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn add_schedule_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    info: web::Json<AddScheduleRequest>,
+) -> anyhow::Result<AddScheduleResponse> {
+    let info = info.into_inner();
+    let input = ScheduleInput::parse(
+        info.weekday,
+        &info.start_time,
+        &info.end_time,
+        info.capacity,
+        &info.start_date,
+        info.end_date.as_deref(),
+        &info.exceptions,
+    )?
+    .at(info.dpid, info.lcid);
+
+    let conn = get_db_conn(&pool)?;
+    let did = doctor.did;
+    let (sid, generated) = web::block(move || {
+        conn.transaction(|| schedule::create(&conn, did, input, &config.schedule))
+    })
+    .await?;
+
+    Ok(AddScheduleResponse {
+        success: true,
+        err: "".to_string(),
+        sid,
+        generated,
+    })
+}
+
+async fn modify_schedule_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    info: web::Json<ModifyScheduleRequest>,
+) -> anyhow::Result<ModifyScheduleResponse> {
+    let info = info.into_inner();
+    let input = ScheduleInput::parse(
+        info.weekday,
+        &info.start_time,
+        &info.end_time,
+        info.capacity,
+        &info.start_date,
+        info.end_date.as_deref(),
+        &info.exceptions,
+    )?
+    .at(info.dpid, info.lcid);
+
+    let conn = get_db_conn(&pool)?;
+    let did = doctor.did;
+    let sid = info.sid;
+    let generated = web::block(move || {
+        conn.transaction(|| {
+            owner::assert_schedule_owner(&conn, sid, &did)?;
+            schedule::update(&conn, sid, input, &config.schedule)
+        })
+    })
+    .await?;
+
+    Ok(ModifyScheduleResponse {
+        success: true,
+        err: "".to_string(),
+        generated,
+    })
+}
+
+async fn delete_schedule_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    info: web::Json<DeleteScheduleRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let conn = get_db_conn(&pool)?;
+    let did = doctor.did;
+    let sid = info.sid;
+    web::block(move || {
+        conn.transaction(|| {
+            owner::assert_schedule_owner(&conn, sid, &did)?;
+            schedule::delete(&conn, sid)
+        })
+    })
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn search_schedule_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    _info: web::Json<SearchScheduleRequest>,
+) -> anyhow::Result<SearchScheduleResponse> {
+    let conn = get_db_conn(&pool)?;
+    let did = doctor.did;
+    let schedules = web::block(move || schedule::search(&conn, &did)).await?;
+
+    Ok(SearchScheduleResponse {
+        success: true,
+        err: "".to_string(),
+        schedules,
+    })
+}
+
+async fn add_leave_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
+    info: web::Json<AddLeaveRequest>,
+) -> anyhow::Result<AddLeaveResponse> {
+    let info = info.into_inner();
+    let (start_time, end_time) =
+        crate::utils::parse_time_pair_str(&info.start_time, &info.end_time)?;
+
+    let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
+    let did = doctor.did;
+    let (lid, slots, canceled) = web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            leave::create(
+                &conn,
+                did.clone(),
+                start_time,
+                end_time,
+                info.reason,
+                Actor::Doctor(&did),
+                &config,
+            )
+        })
+    })
+    .await?;
+
+    Ok(AddLeaveResponse {
+        success: true,
+        err: "".to_string(),
+        lid,
+        slots,
+        canceled,
+    })
+}
+
+async fn delete_leave_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    doctor: AuthedDoctor,
+    info: web::Json<DeleteLeaveRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
+    let did = doctor.did;
+    let lid = info.lid;
+    web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            owner::assert_leave_owner(&conn, lid, &did)?;
+            leave::delete(&conn, lid)
+        })
+    })
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn search_leave_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    doctor: AuthedDoctor,
+    _info: web::Json<SearchLeaveRequest>,
+) -> anyhow::Result<SearchLeaveResponse> {
+    let conn = get_db_conn(&pool)?;
+    let did = doctor.did;
+    let leaves = web::block(move || leave::search(&conn, &did)).await?;
+
+    Ok(SearchLeaveResponse {
+        success: true,
+        err: "".to_string(),
+        leaves,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn doctor(did: &str) -> AuthedDoctor {
+        AuthedDoctor {
+            did: did.to_string(),
+            token_hash: String::new(),
+        }
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn modify_time_of_other_doctor() {
+        let pool = testing::pool();
+        let tid = testing::add_time(&pool, "doctor_b");
+
+        let res = modify_time_impl(
+            pool,
+            testing::config(),
+            testing::broker(),
+            doctor("doctor_a"),
+            web::Json(ModifyTimeRequest {
+                login_token: None,
+                tid,
+                capacity: Some(1),
+                start_time: None,
+                end_time: None,
+                dpid: None,
+                lcid: None,
+            }),
+        )
+        .await;
+        assert_eq!(testing::api_error(res), ApiError::Forbidden);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn delete_time_of_other_doctor() {
+        let pool = testing::pool();
+        let tid = testing::add_time(&pool, "doctor_b");
+
+        let res = delete_time_impl(
+            pool,
+            testing::config(),
+            testing::broker(),
+            doctor("doctor_a"),
+            web::Json(DeleteTimeRequest {
+                login_token: None,
+                tid,
+            }),
+        )
+        .await;
+        assert_eq!(testing::api_error(res), ApiError::Forbidden);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn finish_appoint_of_other_doctor() {
+        let pool = testing::pool();
+        let tid = testing::add_time(&pool, "doctor_b");
+        testing::add_appoint(&pool, "user_a", tid);
+
+        let res = finish_appoint_impl(
+            pool,
+            testing::config(),
+            testing::broker(),
+            doctor("doctor_a"),
+            web::Json(FinishAppointRequest {
+                login_token: None,
+                username: "user_a".to_string(),
+                tid,
+            }),
+        )
+        .await;
+        assert_eq!(testing::api_error(res), ApiError::Forbidden);
+    }
+}
@@ -8,32 +8,51 @@ pub struct LoginRequest {
 
 #[derive(Deserialize)]
 pub struct LogoutRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LogoutAllRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct ViewInfoRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct AddTimeRequest {
-    pub login_token: String,
-    pub date: String,
-    pub time: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
+    /// Either `start_time` and `end_time`, or `date` and a `time` period as a shorthand for
+    /// the clinic hours of that period.
+    pub date: Option<String>,
+    pub time: Option<String>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
     pub capacity: i32,
+    /// One of the doctor's departments, the main one when unset.
+    pub dpid: Option<u64>,
+    /// The campus the slot takes place at.
+    pub lcid: Option<u64>,
 }
 
 #[derive(Deserialize)]
 pub struct ModifyPasswordRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub password_old: String,
     pub password_new: String,
 }
 
 #[derive(Deserialize)]
 pub struct ModifyInfoRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub name: Option<String>,
     pub birthday: Option<String>,
     pub gender: Option<String>,
@@ -42,20 +61,27 @@ pub struct ModifyInfoRequest {
 
 #[derive(Deserialize)]
 pub struct ModifyTimeRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub tid: u64,
     pub capacity: Option<i32>,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
 }
 
 #[derive(Deserialize)]
 pub struct DeleteTimeRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub tid: u64,
 }
 
 #[derive(Deserialize)]
 pub struct SearchTimeRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub date: Option<String>,
     pub first_index: Option<i64>,
     pub limit: Option<i64>,
@@ -63,7 +89,8 @@ pub struct SearchTimeRequest {
 
 #[derive(Deserialize)]
 pub struct SearchAppointRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub start_time: Option<String>,
     pub end_time: Option<String>,
     #[serde(default = "search_appoint_request_status_default")]
@@ -74,20 +101,161 @@ pub struct SearchAppointRequest {
 
 #[derive(Deserialize)]
 pub struct FinishAppointRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub username: String,
     pub tid: u64,
 }
 
+#[derive(Deserialize)]
+pub struct CallNextRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub tid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SkipPatientRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub tid: u64,
+}
+
+/// Recalls the patient being called when `queue_number` is left out.
+#[derive(Deserialize)]
+pub struct RecallPatientRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub tid: u64,
+    pub queue_number: Option<i32>,
+}
+
+/// `status` is one of `checked_in`, `in_progress`, `finished`, `no_show` and
+/// `clinic_canceled`.
+#[derive(Deserialize)]
+pub struct UpdateAppointStatusRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub username: String,
+    pub tid: u64,
+    pub status: String,
+}
+
 #[derive(Deserialize)]
 pub struct SearchCommentRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub start_time: Option<String>,
     pub end_time: Option<String>,
     pub first_index: Option<i64>,
     pub limit: Option<i64>,
 }
 
+#[derive(Deserialize)]
+pub struct ReportCommentRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub cid: u64,
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReplyCommentRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub cid: u64,
+    pub reply: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteReplyRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub cid: u64,
+}
+
+/// `weekday` is 1 for Monday, ..., 7 for Sunday; `start_time` / `end_time` are `HH:MM[:SS]`
+/// in UTC; `exceptions` are dates without a slot.
+#[derive(Deserialize)]
+pub struct AddScheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub weekday: i32,
+    pub start_time: String,
+    pub end_time: String,
+    pub capacity: i32,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+    /// One of the doctor's departments, the main one when unset.
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+/// Replaces the whole template, fields are the same as in `AddScheduleRequest`.
+#[derive(Deserialize)]
+pub struct ModifyScheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub sid: u64,
+    pub weekday: i32,
+    pub start_time: String,
+    pub end_time: String,
+    pub capacity: i32,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteScheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub sid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SearchScheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+}
+
+/// Times are RFC 3339 like the ones of time slots.
+#[derive(Deserialize)]
+pub struct AddLeaveRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub start_time: String,
+    pub end_time: String,
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteLeaveRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub lid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SearchLeaveRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+}
+
 fn search_appoint_request_status_default() -> String {
-    crate::models::appointments::APPOINT_STATUS_UNFINISHED.to_string()
+    crate::models::appointments::AppointStatus::Unfinished
+        .as_str()
+        .to_string()
+}
+
+#[derive(Deserialize)]
+pub struct EventsRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
 }
@@ -1,3 +1,5 @@
+use crate::models::{appointments::AppointStatus, times::Period};
+use crate::protocol::ReplyItem;
 use serde::Serialize;
 
 #[derive(Default, Serialize)]
@@ -5,6 +7,7 @@ pub struct LoginResponse {
     pub success: bool,
     pub err: String,
     pub login_token: String,
+    pub refresh_token: String,
 }
 
 #[derive(Default, Serialize)]
@@ -32,9 +35,17 @@ pub struct AddTimeResponse {
 pub struct SearchTimeItem {
     pub tid: u64,
     pub date: String,
-    pub time: String,
+    pub time: Period,
+    pub start_time: String,
+    pub end_time: String,
     pub capacity: i32,
     pub rest: i32,
+    /// The schedule template the slot was generated from.
+    pub sid: Option<u64>,
+    /// False while the slot is in a leave.
+    pub available: bool,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
 }
 
 #[derive(Default, Serialize)]
@@ -51,9 +62,14 @@ pub struct SearchAppointItem {
     pub age: i32,
     pub tid: u64,
     pub date: String,
-    pub time: String,
-    pub status: String,
+    pub time: Period,
+    pub start_time: String,
+    pub end_time: String,
+    pub status: AppointStatus,
     pub appo_time: String,
+    pub queue_number: i32,
+    pub called_at: Option<String>,
+    pub skipped: bool,
 }
 
 #[derive(Default, Serialize)]
@@ -63,12 +79,24 @@ pub struct SearchAppointResponse {
     pub appointments: Vec<SearchAppointItem>,
 }
 
+#[derive(Default, Serialize)]
+pub struct CallPatientResponse {
+    pub success: bool,
+    pub err: String,
+    pub serving_number: Option<i32>,
+    pub username: Option<String>,
+    pub waiting: i64,
+}
+
 #[derive(Default, Serialize)]
 pub struct SearchCommentItem {
     pub cid: u64,
     pub username: String,
     pub comment: String,
     pub time: String,
+    pub tid: Option<u64>,
+    pub rating: Option<i32>,
+    pub reply: Option<ReplyItem>,
 }
 
 #[derive(Default, Serialize)]
@@ -77,12 +105,3 @@ pub struct SearchCommentResponse {
     pub err: String,
     pub comments: Vec<SearchCommentItem>,
 }
-
-crate::impl_err_response! {
-    LoginResponse,
-    ViewInfoResponse,
-    AddTimeResponse,
-    SearchTimeResponse,
-    SearchAppointResponse,
-    SearchCommentResponse,
-}
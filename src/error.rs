@@ -0,0 +1,373 @@
+use std::fmt;
+
+use actix_web::{error::BlockingError, http::StatusCode, HttpResponse, ResponseError};
+use chrono::NaiveDateTime;
+
+use crate::{
+    i18n::Locale, models::appointments::AppointStatus, protocol::ErrorResponse,
+    utils::format_time_str,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Validation,
+    NotFound,
+    Conflict,
+    Unauthorized,
+    Forbidden,
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn status_code(self) -> StatusCode {
+        match self {
+            ErrorKind::Validation => StatusCode::BAD_REQUEST,
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::Conflict => StatusCode::CONFLICT,
+            ErrorKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorKind::Forbidden => StatusCode::FORBIDDEN,
+            ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Every error a handler can report. `code()` is part of the API and must stay stable,
+/// the message may change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApiError {
+    GenderFormat,
+    BirthdayFormat,
+    DateFormat,
+    TimeFormat,
+    StartTimeFormat,
+    EndTimeFormat,
+    InvalidTimeRange,
+    WeekdayFormat,
+    InvalidDateRange,
+    RescheduleSameTime,
+    RescheduleOtherDepart,
+    StatusFormat,
+    InvalidAdminRole,
+    DepartRequired,
+    EmailFormat,
+    EmailRequired,
+    LocaleFormat,
+    RatingFormat,
+    SortFormat,
+
+    UserNotFound,
+    DoctorNotFound,
+    AdminNotFound,
+    DepartNotFound,
+    CommentNotFound,
+    TimeNotFound,
+    AppointNotFound,
+    ScheduleNotFound,
+    WaitlistNotFound,
+    QueueNumberNotFound,
+    LeaveNotFound,
+    ReplyNotFound,
+    LocationNotFound,
+    DoctorNotInDepart,
+
+    DuplicateId,
+    DuplicateUsername,
+    DuplicateDepart,
+    CommentExists,
+    ReportExists,
+    ReplyExists,
+    DepartCycle,
+    DepartNotEmpty,
+    MainDepart,
+    DuplicateLocation,
+    TimeConflict,
+    TimeFull,
+    TimeNotFull,
+    TimeHasAppointments,
+    TimeBooked,
+    CapacityBelowAppointed {
+        capacity: i32,
+        appointed: i32,
+    },
+    AppointExists,
+    WaitlistExists,
+    RescheduleTooLate {
+        hours: i64,
+    },
+    AppointFinished,
+    AppointCanceled,
+    InvalidTransition {
+        from: AppointStatus,
+        to: AppointStatus,
+    },
+    AppointTooEarly,
+    AppointTooLate,
+    TimeStarted,
+    TimeUnavailable,
+    DoctorOnLeave,
+    AppointRescheduled,
+    BookingTooSoon {
+        minutes: i64,
+    },
+    BookingTooFarAhead {
+        days: i64,
+    },
+    ActiveAppointLimit {
+        max: i64,
+    },
+    DepartDailyLimit,
+    AppointOverlap,
+    CancelTooLate {
+        minutes: i64,
+    },
+    QueueEmpty,
+    QueueNotServing,
+    QueueNotCalled,
+    UserAlreadyBanned,
+    UserAlreadyUnbanned,
+
+    NotLoggedIn,
+    LoginExpired,
+    WrongPassword,
+
+    Forbidden,
+    UserBanned,
+    CommentNotAllowed,
+    BookingBlocked {
+        until: NaiveDateTime,
+    },
+
+    Database,
+    PasswordHash,
+    Internal,
+}
+
+impl ApiError {
+    pub fn kind(&self) -> ErrorKind {
+        use ApiError::*;
+
+        match self {
+            GenderFormat
+            | BirthdayFormat
+            | DateFormat
+            | TimeFormat
+            | StartTimeFormat
+            | EndTimeFormat
+            | InvalidTimeRange
+            | WeekdayFormat
+            | InvalidDateRange
+            | RescheduleSameTime
+            | RescheduleOtherDepart
+            | StatusFormat
+            | InvalidAdminRole
+            | DepartRequired
+            | EmailFormat
+            | EmailRequired
+            | LocaleFormat
+            | RatingFormat
+            | SortFormat => ErrorKind::Validation,
+            UserNotFound | DoctorNotFound | AdminNotFound | DepartNotFound | CommentNotFound
+            | TimeNotFound | AppointNotFound | ScheduleNotFound | WaitlistNotFound
+            | QueueNumberNotFound | LeaveNotFound | ReplyNotFound | LocationNotFound
+            | DoctorNotInDepart => ErrorKind::NotFound,
+            DuplicateId
+            | DuplicateUsername
+            | DuplicateDepart
+            | CommentExists
+            | ReportExists
+            | ReplyExists
+            | DepartCycle
+            | DepartNotEmpty
+            | MainDepart
+            | DuplicateLocation
+            | TimeConflict
+            | TimeFull
+            | TimeNotFull
+            | TimeHasAppointments
+            | TimeBooked
+            | CapacityBelowAppointed { .. }
+            | AppointExists
+            | WaitlistExists
+            | RescheduleTooLate { .. }
+            | AppointFinished
+            | AppointCanceled
+            | InvalidTransition { .. }
+            | AppointTooEarly
+            | AppointTooLate
+            | TimeStarted
+            | TimeUnavailable
+            | DoctorOnLeave
+            | AppointRescheduled
+            | BookingTooSoon { .. }
+            | BookingTooFarAhead { .. }
+            | ActiveAppointLimit { .. }
+            | DepartDailyLimit
+            | AppointOverlap
+            | CancelTooLate { .. }
+            | QueueEmpty
+            | QueueNotServing
+            | QueueNotCalled
+            | UserAlreadyBanned
+            | UserAlreadyUnbanned => ErrorKind::Conflict,
+            NotLoggedIn | LoginExpired | WrongPassword => ErrorKind::Unauthorized,
+            Forbidden | UserBanned | CommentNotAllowed | BookingBlocked { .. } => {
+                ErrorKind::Forbidden
+            }
+            Database | PasswordHash | Internal => ErrorKind::Internal,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        use ApiError::*;
+
+        match self {
+            GenderFormat => "GENDER_FORMAT",
+            BirthdayFormat => "BIRTHDAY_FORMAT",
+            DateFormat => "DATE_FORMAT",
+            TimeFormat => "TIME_FORMAT",
+            StartTimeFormat => "START_TIME_FORMAT",
+            EndTimeFormat => "END_TIME_FORMAT",
+            InvalidTimeRange => "INVALID_TIME_RANGE",
+            WeekdayFormat => "WEEKDAY_FORMAT",
+            InvalidDateRange => "INVALID_DATE_RANGE",
+            RescheduleSameTime => "RESCHEDULE_SAME_TIME",
+            RescheduleOtherDepart => "RESCHEDULE_OTHER_DEPART",
+            StatusFormat => "STATUS_FORMAT",
+            InvalidAdminRole => "INVALID_ADMIN_ROLE",
+            DepartRequired => "DEPART_REQUIRED",
+            EmailFormat => "EMAIL_FORMAT",
+            EmailRequired => "EMAIL_REQUIRED",
+            LocaleFormat => "LOCALE_FORMAT",
+            RatingFormat => "RATING_FORMAT",
+            SortFormat => "SORT_FORMAT",
+            UserNotFound => "USER_NOT_FOUND",
+            DoctorNotFound => "DOCTOR_NOT_FOUND",
+            AdminNotFound => "ADMIN_NOT_FOUND",
+            DepartNotFound => "DEPART_NOT_FOUND",
+            CommentNotFound => "COMMENT_NOT_FOUND",
+            TimeNotFound => "TIME_NOT_FOUND",
+            AppointNotFound => "APPOINT_NOT_FOUND",
+            ScheduleNotFound => "SCHEDULE_NOT_FOUND",
+            WaitlistNotFound => "WAITLIST_NOT_FOUND",
+            QueueNumberNotFound => "QUEUE_NUMBER_NOT_FOUND",
+            LeaveNotFound => "LEAVE_NOT_FOUND",
+            ReplyNotFound => "REPLY_NOT_FOUND",
+            LocationNotFound => "LOCATION_NOT_FOUND",
+            DoctorNotInDepart => "DOCTOR_NOT_IN_DEPART",
+            DuplicateId => "DUPLICATE_ID",
+            DuplicateUsername => "DUPLICATE_USERNAME",
+            DuplicateDepart => "DUPLICATE_DEPART",
+            CommentExists => "COMMENT_EXISTS",
+            ReportExists => "REPORT_EXISTS",
+            ReplyExists => "REPLY_EXISTS",
+            DepartCycle => "DEPART_CYCLE",
+            DepartNotEmpty => "DEPART_NOT_EMPTY",
+            MainDepart => "MAIN_DEPART",
+            DuplicateLocation => "DUPLICATE_LOCATION",
+            TimeConflict => "TIME_CONFLICT",
+            TimeFull => "TIME_FULL",
+            TimeNotFull => "TIME_NOT_FULL",
+            TimeHasAppointments => "TIME_HAS_APPOINTMENTS",
+            TimeBooked => "TIME_BOOKED",
+            CapacityBelowAppointed { .. } => "CAPACITY_BELOW_APPOINTED",
+            AppointExists => "APPOINT_EXISTS",
+            WaitlistExists => "WAITLIST_EXISTS",
+            RescheduleTooLate { .. } => "RESCHEDULE_TOO_LATE",
+            AppointFinished => "APPOINT_FINISHED",
+            AppointCanceled => "APPOINT_CANCELED",
+            InvalidTransition { .. } => "INVALID_TRANSITION",
+            AppointTooEarly => "APPOINT_TOO_EARLY",
+            AppointTooLate => "APPOINT_TOO_LATE",
+            TimeStarted => "TIME_STARTED",
+            TimeUnavailable => "TIME_UNAVAILABLE",
+            DoctorOnLeave => "DOCTOR_ON_LEAVE",
+            AppointRescheduled => "APPOINT_RESCHEDULED",
+            BookingTooSoon { .. } => "BOOKING_TOO_SOON",
+            BookingTooFarAhead { .. } => "BOOKING_TOO_FAR_AHEAD",
+            ActiveAppointLimit { .. } => "ACTIVE_APPOINT_LIMIT",
+            DepartDailyLimit => "DEPART_DAILY_LIMIT",
+            AppointOverlap => "APPOINT_OVERLAP",
+            CancelTooLate { .. } => "CANCEL_TOO_LATE",
+            QueueEmpty => "QUEUE_EMPTY",
+            QueueNotServing => "QUEUE_NOT_SERVING",
+            QueueNotCalled => "QUEUE_NOT_CALLED",
+            UserAlreadyBanned => "USER_ALREADY_BANNED",
+            UserAlreadyUnbanned => "USER_ALREADY_UNBANNED",
+            NotLoggedIn => "NOT_LOGGED_IN",
+            LoginExpired => "LOGIN_EXPIRED",
+            WrongPassword => "WRONG_PASSWORD",
+            Forbidden => "FORBIDDEN",
+            UserBanned => "USER_BANNED",
+            CommentNotAllowed => "COMMENT_NOT_ALLOWED",
+            BookingBlocked { .. } => "BOOKING_BLOCKED",
+            Database => "DATABASE",
+            PasswordHash => "PASSWORD_HASH",
+            Internal => "INTERNAL",
+        }
+    }
+
+    pub fn message(&self, locale: Locale) -> String {
+        let template = crate::i18n::message(self.code(), locale);
+        match self {
+            ApiError::CapacityBelowAppointed {
+                capacity,
+                appointed,
+            } => template
+                .replace("{capacity}", &capacity.to_string())
+                .replace("{appointed}", &appointed.to_string()),
+            ApiError::RescheduleTooLate { hours } => {
+                template.replace("{hours}", &hours.to_string())
+            }
+            ApiError::BookingTooSoon { minutes } | ApiError::CancelTooLate { minutes } => {
+                template.replace("{minutes}", &minutes.to_string())
+            }
+            ApiError::BookingTooFarAhead { days } => template.replace("{days}", &days.to_string()),
+            ApiError::ActiveAppointLimit { max } => template.replace("{max}", &max.to_string()),
+            ApiError::InvalidTransition { from, to } => template
+                .replace("{from}", from.label(locale))
+                .replace("{to}", to.label(locale)),
+            ApiError::BookingBlocked { until } => {
+                template.replace("{until}", &format_time_str(until))
+            }
+            _ => template.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message(Locale::current()))
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<ApiError>() {
+            return err.clone();
+        }
+        match err.downcast::<BlockingError<anyhow::Error>>() {
+            Ok(BlockingError::Error(err)) => ApiError::from(err),
+            Ok(BlockingError::Canceled) => ApiError::Database,
+            Err(err) => {
+                eprintln!("unexpected error: {:?}", err);
+                ApiError::Internal
+            }
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.kind().status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorResponse {
+            success: false,
+            err: self.to_string(),
+            code: self.code(),
+        })
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use actix::{Actor, Addr, AsyncContext, Context, Handler, Message};
+use actix_web::{web::Bytes, HttpResponse};
+use futures::{channel::mpsc, stream, StreamExt};
+use serde::Serialize;
+
+use crate::config::EventsConfig;
+
+// Server-sent events. Each stream subscribes to a few topics at the broker and receives the
+// events published to any of them, until the client goes away.
+
+struct Subscriber {
+    topics: Vec<String>,
+    sender: mpsc::UnboundedSender<Bytes>,
+}
+
+/// Keeps the open streams and fans events out to them. Started once and shared with the
+/// handlers as `web::Data<Addr<Broker>>`.
+pub struct Broker {
+    subscribers: Vec<Subscriber>,
+    heartbeat: Duration,
+}
+
+impl Broker {
+    pub fn new(config: &EventsConfig) -> Self {
+        Self {
+            subscribers: vec![],
+            heartbeat: Duration::from_secs(config.heartbeat_secs.max(1)),
+        }
+    }
+}
+
+impl Actor for Broker {
+    type Context = Context<Self>;
+
+    /// Periodically pings every stream and forgets the closed ones.
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(self.heartbeat, |broker, _| {
+            broker.subscribers.retain(|subscriber| {
+                subscriber
+                    .sender
+                    .unbounded_send(Bytes::from_static(b": ping\n\n"))
+                    .is_ok()
+            });
+        });
+    }
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+struct Subscribe(Subscriber);
+
+/// An encoded event for every stream subscribed to one of `topics`.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Publish {
+    pub topics: Vec<String>,
+    pub frame: Bytes,
+}
+
+impl Handler<Subscribe> for Broker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Subscribe, _: &mut Self::Context) {
+        self.subscribers.push(msg.0);
+    }
+}
+
+impl Handler<Publish> for Broker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Publish, _: &mut Self::Context) {
+        let Publish { topics, frame } = msg;
+        self.subscribers.retain(|subscriber| {
+            !subscriber.topics.iter().any(|topic| topics.contains(topic))
+                || subscriber.sender.unbounded_send(frame.clone()).is_ok()
+        });
+    }
+}
+
+/// Encodes one event in the `text/event-stream` format.
+pub fn frame<T: Serialize>(event: &str, data: &T) -> Bytes {
+    let data = serde_json::to_string(data).unwrap_or_default();
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event, data))
+}
+
+/// Sends the event to every stream subscribed to one of `topics`. Call it after the change
+/// is committed.
+pub fn publish<T: Serialize>(broker: &Addr<Broker>, topics: Vec<String>, event: &str, data: &T) {
+    broker.do_send(Publish {
+        topics,
+        frame: frame(event, data),
+    });
+}
+
+/// Subscribes to `topics` and returns the receiving end of the encoded events.
+pub fn subscribe(broker: &Addr<Broker>, topics: Vec<String>) -> mpsc::UnboundedReceiver<Bytes> {
+    let (sender, receiver) = mpsc::unbounded();
+    broker.do_send(Subscribe(Subscriber { topics, sender }));
+    receiver
+}
+
+/// Opens a stream of `topics` that starts with the `initial` frames.
+pub fn stream(broker: &Addr<Broker>, topics: Vec<String>, initial: Vec<Bytes>) -> HttpResponse {
+    let body = stream::iter(initial)
+        .chain(subscribe(broker, topics))
+        .map(Ok::<_, actix_web::Error>);
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(body)
+}
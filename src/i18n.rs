@@ -0,0 +1,463 @@
+use std::cell::Cell;
+
+use actix_web::{http::header, HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+
+use crate::error::ApiError;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    ZhCn,
+    EnUs,
+}
+
+thread_local! {
+    static CURRENT_LOCALE: Cell<Locale> = Cell::new(Locale::default());
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::ZhCn, Locale::EnUs];
+
+    /// Picks the supported locale with the highest `q` in `Accept-Language`.
+    pub fn from_request(req: &HttpRequest) -> Self {
+        req.headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::negotiate)
+            .unwrap_or_default()
+    }
+
+    /// Stored in `notification_preferences.locale`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Locale::ZhCn => "zh-CN",
+            Locale::EnUs => "en-US",
+        }
+    }
+
+    /// Accepts a language tag like `en` or `zh-CN`.
+    pub fn parse(tag: &str) -> Option<Self> {
+        Self::negotiate(tag)
+    }
+
+    fn negotiate(accept_language: &str) -> Option<Self> {
+        let mut best: Option<(Locale, f32)> = None;
+        for range in accept_language.split(',') {
+            let mut parts = range.split(';');
+            let tag = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let locale = match tag.split('-').next() {
+                Some("zh") => Locale::ZhCn,
+                Some("en") => Locale::EnUs,
+                _ => continue,
+            };
+            if q > 0.0 && !matches!(best, Some((_, best_q)) if best_q >= q) {
+                best = Some((locale, q));
+            }
+        }
+        best.map(|(locale, _)| locale)
+    }
+
+    /// The locale of the response being rendered on this thread.
+    pub fn current() -> Self {
+        CURRENT_LOCALE.with(|locale| locale.get())
+    }
+}
+
+/// Runs `f` with `Locale::current()` set to `locale`. Serialization of responses is
+/// synchronous, so values rendered inside `f` see the requested locale.
+pub fn with_locale<R>(locale: Locale, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT_LOCALE.with(|current| current.replace(locale));
+    let res = f();
+    CURRENT_LOCALE.with(|current| current.set(previous));
+    res
+}
+
+pub fn localized_response<T: Serialize>(
+    req: &HttpRequest,
+    result: anyhow::Result<T>,
+) -> HttpResponse {
+    with_locale(Locale::from_request(req), || match result {
+        Ok(response) => HttpResponse::Ok().json(response),
+        Err(err) => ApiError::from(err).error_response(),
+    })
+}
+
+/// Whether `s` is how `key` is rendered in any of the supported locales.
+pub fn is_label(key: &str, s: &str) -> bool {
+    Locale::ALL
+        .iter()
+        .any(|&locale| message(key, locale).eq_ignore_ascii_case(s))
+}
+
+/// Looks `key` up in the catalog, falls back to the key itself.
+pub fn message(key: &str, locale: Locale) -> &str {
+    MESSAGES
+        .iter()
+        .find(|(k, _, _)| *k == key)
+        .map_or(key, |(_, zh_cn, en_us)| match locale {
+            Locale::ZhCn => zh_cn,
+            Locale::EnUs => en_us,
+        })
+}
+
+// (key, zh-CN, en-US)
+const MESSAGES: &[(&str, &str, &str)] = &[
+    ("GENDER_FORMAT", "性别格式错误", "Invalid gender"),
+    ("BIRTHDAY_FORMAT", "生日格式错误", "Invalid birthday"),
+    ("DATE_FORMAT", "日期格式错误", "Invalid date"),
+    ("TIME_FORMAT", "时间格式错误", "Invalid time"),
+    (
+        "START_TIME_FORMAT",
+        "起始时间格式错误",
+        "Invalid start time",
+    ),
+    ("END_TIME_FORMAT", "结束时间格式错误", "Invalid end time"),
+    (
+        "INVALID_TIME_RANGE",
+        "起始时间必须早于结束时间",
+        "Start time must be before end time",
+    ),
+    ("WEEKDAY_FORMAT", "星期格式错误", "Invalid weekday"),
+    (
+        "INVALID_DATE_RANGE",
+        "起始日期不能晚于结束日期",
+        "Start date must not be after end date",
+    ),
+    (
+        "RESCHEDULE_SAME_TIME",
+        "不能改约到同一时间段",
+        "Cannot reschedule to the same time slot",
+    ),
+    (
+        "RESCHEDULE_OTHER_DEPART",
+        "只能改约到同一医生或同一科室的时间段",
+        "Can only reschedule to the same doctor or department",
+    ),
+    (
+        "STATUS_FORMAT",
+        "预约状态格式错误",
+        "Invalid appointment status",
+    ),
+    (
+        "INVALID_ADMIN_ROLE",
+        "管理员角色错误",
+        "Invalid administrator role",
+    ),
+    (
+        "DEPART_REQUIRED",
+        "科室管理员需指定科室",
+        "A department administrator needs a department",
+    ),
+    ("EMAIL_FORMAT", "邮箱格式错误", "Invalid email address"),
+    (
+        "EMAIL_REQUIRED",
+        "开启邮件通知需先填写邮箱",
+        "An email address is needed for email notifications",
+    ),
+    ("LOCALE_FORMAT", "语言格式错误", "Unsupported language"),
+    ("RATING_FORMAT", "评分须为 1 到 5", "Rating must be 1 to 5"),
+    ("SORT_FORMAT", "排序方式错误", "Unsupported sort order"),
+    ("USER_NOT_FOUND", "用户不存在", "User not found"),
+    ("DOCTOR_NOT_FOUND", "医生不存在", "Doctor not found"),
+    ("ADMIN_NOT_FOUND", "管理员不存在", "Administrator not found"),
+    ("DEPART_NOT_FOUND", "科室不存在", "Department not found"),
+    ("COMMENT_NOT_FOUND", "评论不存在", "Comment not found"),
+    ("TIME_NOT_FOUND", "时间段不存在", "Time slot not found"),
+    ("APPOINT_NOT_FOUND", "预约不存在", "Appointment not found"),
+    ("SCHEDULE_NOT_FOUND", "排班不存在", "Schedule not found"),
+    ("LEAVE_NOT_FOUND", "请假记录不存在", "Leave not found"),
+    ("REPLY_NOT_FOUND", "回复不存在", "Reply not found"),
+    ("LOCATION_NOT_FOUND", "院区不存在", "Location not found"),
+    (
+        "DOCTOR_NOT_IN_DEPART",
+        "医生不属于该科室",
+        "The doctor doesn't consult in this department",
+    ),
+    (
+        "WAITLIST_NOT_FOUND",
+        "不在该时间段的候补队列中",
+        "Not on the waitlist of this time slot",
+    ),
+    (
+        "QUEUE_NUMBER_NOT_FOUND",
+        "该时间段没有这个号",
+        "No such queue number in this time slot",
+    ),
+    ("DUPLICATE_ID", "ID 重复", "ID already exists"),
+    (
+        "DUPLICATE_USERNAME",
+        "用户名重复",
+        "Username already exists",
+    ),
+    (
+        "DUPLICATE_DEPART",
+        "科室名称重复",
+        "Department already exists",
+    ),
+    (
+        "COMMENT_EXISTS",
+        "已评价过该次就诊",
+        "This appointment has already been reviewed",
+    ),
+    (
+        "REPORT_EXISTS",
+        "已举报过该评论",
+        "You have already reported this comment",
+    ),
+    (
+        "REPLY_EXISTS",
+        "已回复过该评论",
+        "This comment has already been replied to",
+    ),
+    (
+        "DEPART_CYCLE",
+        "不能移动或合并到自身或其下级科室",
+        "A department can't be moved or merged into itself or its sub-departments",
+    ),
+    (
+        "DEPART_NOT_EMPTY",
+        "科室仍有医生、管理员或下级科室",
+        "Department still has doctors, admins or sub-departments",
+    ),
+    (
+        "MAIN_DEPART",
+        "不能移除医生的主科室",
+        "The main department of a doctor can't be removed",
+    ),
+    (
+        "DUPLICATE_LOCATION",
+        "院区名称重复",
+        "Location already exists",
+    ),
+    (
+        "TIME_CONFLICT",
+        "时间区间与已有的冲突",
+        "Time slot overlaps an existing one",
+    ),
+    ("TIME_FULL", "时间段已满", "Time slot is full"),
+    (
+        "TIME_NOT_FULL",
+        "时间段未满, 请直接预约",
+        "Time slot is not full, book it directly",
+    ),
+    (
+        "TIME_HAS_APPOINTMENTS",
+        "不能在已有用户预约的情况下删除时间",
+        "Cannot delete a time slot that has appointments",
+    ),
+    (
+        "TIME_BOOKED",
+        "不能在已有用户预约的情况下修改时间",
+        "Cannot move a time slot that has appointments",
+    ),
+    (
+        "CAPACITY_BELOW_APPOINTED",
+        "不能修改容量为 {capacity}, 因为已经有 {appointed} 个用户预约了",
+        "Cannot set capacity to {capacity}, {appointed} users have already booked",
+    ),
+    ("APPOINT_EXISTS", "预约已存在", "Appointment already exists"),
+    (
+        "WAITLIST_EXISTS",
+        "已在候补队列中",
+        "Already on the waitlist",
+    ),
+    (
+        "RESCHEDULE_TOO_LATE",
+        "距离就诊不足 {hours} 小时, 不能改约",
+        "Cannot reschedule less than {hours} hours before the appointment",
+    ),
+    (
+        "APPOINT_FINISHED",
+        "预约已完成",
+        "Appointment is already finished",
+    ),
+    (
+        "APPOINT_CANCELED",
+        "预约已取消",
+        "Appointment is already canceled",
+    ),
+    (
+        "INVALID_TRANSITION",
+        "预约不能从 {from} 变为 {to}",
+        "Appointment cannot go from {from} to {to}",
+    ),
+    (
+        "APPOINT_TOO_EARLY",
+        "时间未到, 不能进行该操作",
+        "Too early for this appointment action",
+    ),
+    (
+        "APPOINT_TOO_LATE",
+        "时间已过, 不能进行该操作",
+        "Too late for this appointment action",
+    ),
+    (
+        "TIME_STARTED",
+        "号源已开始",
+        "The time slot has already started",
+    ),
+    (
+        "TIME_UNAVAILABLE",
+        "医生停诊, 该时间段不可预约",
+        "The doctor is away, this time slot can't be booked",
+    ),
+    (
+        "DOCTOR_ON_LEAVE",
+        "时间区间与医生的请假冲突",
+        "The doctor is on leave at this time",
+    ),
+    (
+        "APPOINT_RESCHEDULED",
+        "该预约已改期",
+        "This appointment has already been rescheduled",
+    ),
+    (
+        "BOOKING_TOO_SOON",
+        "需在就诊开始前 {minutes} 分钟预约",
+        "Slots must be booked at least {minutes} minutes before they start",
+    ),
+    (
+        "BOOKING_TOO_FAR_AHEAD",
+        "只能预约 {days} 天内的号源",
+        "Slots can be booked at most {days} days ahead",
+    ),
+    (
+        "ACTIVE_APPOINT_LIMIT",
+        "最多同时持有 {max} 个预约",
+        "At most {max} active appointments are allowed",
+    ),
+    (
+        "DEPART_DAILY_LIMIT",
+        "同一科室每天只能预约一次",
+        "Only one appointment per department per day is allowed",
+    ),
+    (
+        "APPOINT_OVERLAP",
+        "与已有预约时间冲突",
+        "Overlaps with another appointment",
+    ),
+    (
+        "CANCEL_TOO_LATE",
+        "距离就诊不足 {minutes} 分钟, 不能取消",
+        "Cannot cancel less than {minutes} minutes before the appointment",
+    ),
+    ("QUEUE_EMPTY", "没有候诊的患者", "No patient is waiting"),
+    (
+        "QUEUE_NOT_SERVING",
+        "还没有叫号",
+        "No patient has been called yet",
+    ),
+    (
+        "QUEUE_NOT_CALLED",
+        "该号还没有叫过",
+        "This queue number has not been called yet",
+    ),
+    (
+        "USER_ALREADY_BANNED",
+        "用户已被封禁",
+        "User is already banned",
+    ),
+    (
+        "USER_ALREADY_UNBANNED",
+        "用户已被解封",
+        "User is not banned",
+    ),
+    ("NOT_LOGGED_IN", "您还未登录", "Not logged in"),
+    ("LOGIN_EXPIRED", "登录已过期", "Login expired"),
+    ("WRONG_PASSWORD", "密码错误", "Wrong password"),
+    ("FORBIDDEN", "权限不足", "Permission denied"),
+    ("USER_BANNED", "用户被封禁", "User is banned"),
+    (
+        "COMMENT_NOT_ALLOWED",
+        "只能评价已完成的就诊",
+        "Only a finished appointment with the doctor can be reviewed",
+    ),
+    (
+        "BOOKING_BLOCKED",
+        "爽约次数过多, {until} 前不能预约",
+        "Too many no-shows, booking is blocked until {until}",
+    ),
+    ("DATABASE", "数据库错误", "Database error"),
+    ("PASSWORD_HASH", "密码加密错误", "Failed to hash password"),
+    ("INTERNAL", "未知错误", "Internal error"),
+    ("APPOINT_STATUS_UNFINISHED", "未完成", "Unfinished"),
+    ("APPOINT_STATUS_CHECKED_IN", "已签到", "Checked in"),
+    ("APPOINT_STATUS_IN_PROGRESS", "就诊中", "In consultation"),
+    ("APPOINT_STATUS_FINISHED", "已完成", "Finished"),
+    ("APPOINT_STATUS_NO_SHOW", "爽约", "No-show"),
+    ("APPOINT_STATUS_CANCELED", "已取消", "Canceled"),
+    (
+        "APPOINT_STATUS_CLINIC_CANCELED",
+        "已被医院取消",
+        "Canceled by the clinic",
+    ),
+    ("APPOINT_STATUS_ALL", "所有", "All"),
+    ("PERIOD_AM", "上午", "AM"),
+    ("PERIOD_PM", "下午", "PM"),
+    ("NOTIFY_BOOKED_SUBJECT", "预约成功", "Appointment booked"),
+    (
+        "NOTIFY_BOOKED_BODY",
+        "{name}, 您已预约 {depart} {doctor} 医生 {time} 的号, 排队号 {queue_number}。",
+        "{name}, your appointment with Dr. {doctor} ({depart}) at {time} is booked, queue number {queue_number}.",
+    ),
+    ("NOTIFY_CANCELED_SUBJECT", "预约已取消", "Appointment canceled"),
+    (
+        "NOTIFY_CANCELED_BODY",
+        "{name}, 您在 {depart} {doctor} 医生 {time} 的预约已取消。",
+        "{name}, your appointment with Dr. {doctor} ({depart}) at {time} is canceled.",
+    ),
+    ("NOTIFY_CLINIC_CANCELED_SUBJECT", "医生停诊通知", "Appointment canceled by the clinic"),
+    (
+        "NOTIFY_CLINIC_CANCELED_BODY",
+        "{name}, 由于 {depart} {doctor} 医生停诊, 您 {time} 的预约已取消, 可改约该科室的其他时间。",
+        "{name}, Dr. {doctor} ({depart}) is not available, so your appointment at {time} is canceled. You can reschedule it to another slot of the department.",
+    ),
+    ("NOTIFY_RESCHEDULED_SUBJECT", "预约已改期", "Appointment rescheduled"),
+    (
+        "NOTIFY_RESCHEDULED_BODY",
+        "{name}, 您 {from_time} 的预约已改到 {depart} {doctor} 医生 {time}, 排队号 {queue_number}。",
+        "{name}, your appointment at {from_time} is moved to Dr. {doctor} ({depart}) at {time}, queue number {queue_number}.",
+    ),
+    ("NOTIFY_REMINDER_SUBJECT", "就诊提醒", "Appointment reminder"),
+    (
+        "NOTIFY_REMINDER_BODY",
+        "{name}, 提醒您 {time} 在 {depart} {doctor} 医生处就诊, 排队号 {queue_number}。",
+        "{name}, a reminder of your appointment with Dr. {doctor} ({depart}) at {time}, queue number {queue_number}.",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(Locale::negotiate("en-US,en;q=0.9"), Some(Locale::EnUs));
+        assert_eq!(Locale::negotiate("EN"), Some(Locale::EnUs));
+        assert_eq!(Locale::negotiate("zh-CN"), Some(Locale::ZhCn));
+        assert_eq!(
+            Locale::negotiate("fr, zh-TW;q=0.5, en-GB;q=0.8"),
+            Some(Locale::EnUs)
+        );
+        // the first of equally preferred locales wins
+        assert_eq!(Locale::negotiate("zh, en"), Some(Locale::ZhCn));
+        assert_eq!(Locale::negotiate("en;q=0, zh;q=0.1"), Some(Locale::ZhCn));
+        assert_eq!(Locale::negotiate("en;q=0"), None);
+        assert_eq!(Locale::negotiate("fr, de;q=0.9"), None);
+        assert_eq!(Locale::negotiate(""), None);
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(message("APPOINT_STATUS_FINISHED", Locale::EnUs), "Finished");
+        assert_eq!(message("NO_SUCH_KEY", Locale::ZhCn), "NO_SUCH_KEY");
+        assert!(is_label("APPOINT_STATUS_FINISHED", "finished"));
+        assert!(is_label("APPOINT_STATUS_FINISHED", "已完成"));
+        assert!(!is_label("APPOINT_STATUS_FINISHED", "Canceled"));
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{bail, Context};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    booking::{self, Actor},
+    config::Config,
+    depart,
+    error::ApiError,
+    models::{
+        appointments::AppointStatus,
+        doctor_leaves::{DoctorLeave, NewDoctorLeave},
+    },
+    notify,
+    protocol::LeaveItem,
+    schedule::last_insert_id,
+    utils::format_time_str,
+};
+
+// Leaves of doctors. The future slots in a leave are kept, so the canceled appointments
+// still point to them, but can't be booked until the leave is deleted.
+
+/// Whether `[start_time, end_time)` intersects a leave of the doctor.
+pub fn on_leave(
+    conn: &MysqlConnection,
+    did: &str,
+    start_time: &NaiveDateTime,
+    end_time: &NaiveDateTime,
+) -> anyhow::Result<bool> {
+    use crate::schema::doctor_leaves;
+
+    let res = doctor_leaves::table
+        .filter(doctor_leaves::did.eq(did))
+        .filter(doctor_leaves::start_time.lt(end_time))
+        .filter(doctor_leaves::end_time.gt(start_time))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    Ok(res > 0)
+}
+
+pub fn assert_not_on_leave(
+    conn: &MysqlConnection,
+    did: &str,
+    start_time: &NaiveDateTime,
+    end_time: &NaiveDateTime,
+) -> anyhow::Result<()> {
+    if on_leave(conn, did, start_time, end_time)? {
+        bail!(ApiError::DoctorOnLeave);
+    }
+    Ok(())
+}
+
+fn find(conn: &MysqlConnection, lid: u64) -> anyhow::Result<DoctorLeave> {
+    use crate::schema::doctor_leaves;
+
+    doctor_leaves::table
+        .filter(doctor_leaves::lid.eq(lid))
+        .get_result::<DoctorLeave>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::LeaveNotFound)
+}
+
+/// The departments of the doctor on leave, for the permission check of admins.
+pub fn leave_departs(conn: &MysqlConnection, lid: u64) -> anyhow::Result<Vec<String>> {
+    depart::names_of(conn, &find(conn, lid)?.did)
+}
+
+/// The future slots of the doctor intersecting `[start_time, end_time)`.
+fn future_slots(
+    conn: &MysqlConnection,
+    did: &str,
+    start_time: &NaiveDateTime,
+    end_time: &NaiveDateTime,
+) -> anyhow::Result<Vec<u64>> {
+    use crate::schema::times;
+
+    times::table
+        .filter(times::did.eq(did))
+        .filter(times::start_time.lt(end_time))
+        .filter(times::end_time.gt(start_time))
+        .filter(times::start_time.gt(Utc::now().naive_utc()))
+        .select(times::tid)
+        .get_results::<u64>(conn)
+        .context(ApiError::Database)
+}
+
+/// Records the leave, makes the future slots in it unavailable, cancels their appointments
+/// that haven't started and drops their waitlists. Returns the new `lid`, the number of slots and the number of
+/// canceled appointments.
+pub fn create(
+    conn: &MysqlConnection,
+    did: String,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    reason: String,
+    actor: Actor,
+    config: &Config,
+) -> anyhow::Result<(u64, usize, usize)> {
+    use crate::schema::{appointments, doctor_leaves, times, waitlists};
+
+    if start_time >= end_time {
+        bail!(ApiError::InvalidTimeRange);
+    }
+    let data = NewDoctorLeave {
+        did: did.clone(),
+        start_time,
+        end_time,
+        reason,
+        actor_kind: actor.kind().to_string(),
+        actor_id: actor.id(),
+    };
+    diesel::insert_into(doctor_leaves::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    let lid = diesel::select(last_insert_id)
+        .get_result::<u64>(conn)
+        .context(ApiError::Database)?;
+
+    // appointments already in progress or over are left alone
+    let cancelable = AppointStatus::ALL
+        .iter()
+        .filter(|status| status.can_become(AppointStatus::ClinicCanceled))
+        .map(|status| status.as_str())
+        .collect::<Vec<_>>();
+    let tids = future_slots(conn, &did, &start_time, &end_time)?;
+    let mut canceled = 0;
+    for &tid in &tids {
+        let time_data = booking::lock_time(conn, tid)?;
+        if time_data.available {
+            diesel::update(times::table.filter(times::tid.eq(tid)))
+                .set(times::available.eq(false))
+                .execute(conn)
+                .context(ApiError::Database)?;
+            notify::time_changed(conn, &time_data, "unavailable")?;
+        }
+
+        let usernames = appointments::table
+            .filter(appointments::tid.eq(tid))
+            .filter(appointments::status.eq_any(&cancelable))
+            .select(appointments::username)
+            .get_results::<String>(conn)
+            .context(ApiError::Database)?;
+        for username in usernames {
+            booking::transition(
+                conn,
+                &username,
+                tid,
+                AppointStatus::ClinicCanceled,
+                actor,
+                &config.booking,
+                &config.penalty,
+            )?;
+            canceled += 1;
+        }
+        diesel::delete(waitlists::table.filter(waitlists::tid.eq(tid)))
+            .execute(conn)
+            .context(ApiError::Database)?;
+    }
+
+    Ok((lid, tids.len(), canceled))
+}
+
+/// Deletes the leave and opens its future slots again, unless another leave covers them.
+/// Canceled appointments stay canceled.
+pub fn delete(conn: &MysqlConnection, lid: u64) -> anyhow::Result<()> {
+    use crate::schema::{doctor_leaves, times};
+
+    let leave = find(conn, lid)?;
+    diesel::delete(doctor_leaves::table.filter(doctor_leaves::lid.eq(lid)))
+        .execute(conn)
+        .context(ApiError::Database)?;
+
+    for tid in future_slots(conn, &leave.did, &leave.start_time, &leave.end_time)? {
+        let time_data = booking::lock_time(conn, tid)?;
+        if time_data.available
+            || on_leave(conn, &leave.did, &time_data.start_time, &time_data.end_time)?
+        {
+            continue;
+        }
+        diesel::update(times::table.filter(times::tid.eq(tid)))
+            .set(times::available.eq(true))
+            .execute(conn)
+            .context(ApiError::Database)?;
+        notify::time_changed(conn, &time_data, "available")?;
+    }
+    Ok(())
+}
+
+pub fn search(conn: &MysqlConnection, did: &str) -> anyhow::Result<Vec<LeaveItem>> {
+    use crate::schema::doctor_leaves;
+
+    let res = doctor_leaves::table
+        .filter(doctor_leaves::did.eq(did))
+        .order(doctor_leaves::start_time.desc())
+        .get_results::<DoctorLeave>(conn)
+        .context(ApiError::Database)?;
+
+    Ok(res
+        .into_iter()
+        .map(|data| LeaveItem {
+            lid: data.lid,
+            did: data.did,
+            start_time: format_time_str(&data.start_time),
+            end_time: format_time_str(&data.end_time),
+            reason: data.reason,
+            actor_kind: data.actor_kind,
+            actor_id: data.actor_id,
+            time: format_time_str(&data.time),
+        })
+        .collect())
+}
@@ -0,0 +1,97 @@
+use anyhow::{bail, Context};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    error::ApiError,
+    models::locations::{Location, NewLocation, UpdateLocation},
+    protocol::LocationItem,
+    schedule::last_insert_id,
+};
+
+// Campuses of the hospital. Time slots and templates may name the one they take place at.
+
+/// Checks the campus a slot or template takes place at, if any.
+pub fn assert_location(conn: &MysqlConnection, lcid: Option<u64>) -> anyhow::Result<()> {
+    use crate::schema::locations;
+
+    if let Some(lcid) = lcid {
+        let res = locations::table
+            .filter(locations::lcid.eq(lcid))
+            .count()
+            .get_result::<i64>(conn)
+            .context(ApiError::Database)?;
+        if res == 0 {
+            bail!(ApiError::LocationNotFound);
+        }
+    }
+    Ok(())
+}
+
+fn assert_name_free(conn: &MysqlConnection, name: &str) -> anyhow::Result<()> {
+    use crate::schema::locations;
+
+    let res = locations::table
+        .filter(locations::name.eq(name))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    if res > 0 {
+        bail!(ApiError::DuplicateLocation);
+    }
+    Ok(())
+}
+
+pub fn create(conn: &MysqlConnection, name: String, address: String) -> anyhow::Result<u64> {
+    use crate::schema::locations;
+
+    assert_name_free(conn, &name)?;
+    diesel::insert_into(locations::table)
+        .values(NewLocation { name, address })
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::select(last_insert_id)
+        .get_result::<u64>(conn)
+        .context(ApiError::Database)
+}
+
+pub fn modify(conn: &MysqlConnection, lcid: u64, data: UpdateLocation) -> anyhow::Result<()> {
+    use crate::schema::locations;
+
+    assert_location(conn, Some(lcid))?;
+    if let Some(name) = &data.name {
+        let res = locations::table
+            .filter(locations::name.eq(name))
+            .filter(locations::lcid.ne(lcid))
+            .count()
+            .get_result::<i64>(conn)
+            .context(ApiError::Database)?;
+        if res > 0 {
+            bail!(ApiError::DuplicateLocation);
+        }
+    }
+    if data.name.is_some() || data.address.is_some() {
+        diesel::update(locations::table.filter(locations::lcid.eq(lcid)))
+            .set(&data)
+            .execute(conn)
+            .context(ApiError::Database)?;
+    }
+    Ok(())
+}
+
+pub fn search(conn: &MysqlConnection, name_pattern: &str) -> anyhow::Result<Vec<LocationItem>> {
+    use crate::schema::locations;
+
+    let locs = locations::table
+        .filter(locations::name.like(name_pattern))
+        .order(locations::name.asc())
+        .get_results::<Location>(conn)
+        .context(ApiError::Database)?;
+    Ok(locs
+        .into_iter()
+        .map(|data| LocationItem {
+            lcid: data.lcid,
+            name: data.name,
+            address: data.address,
+        })
+        .collect())
+}
@@ -2,17 +2,40 @@
 extern crate diesel;
 
 mod admin;
+mod auth;
+mod booking;
+mod config;
 mod database;
+mod depart;
+mod display;
 mod doctor;
+mod error;
+mod events;
+mod i18n;
+mod leave;
+mod location;
 mod models;
+mod notifier;
+mod notify;
+mod outbox;
+mod password;
+mod penalty;
 mod protocol;
+mod queue;
+mod review;
+mod rules;
+mod schedule;
 mod schema;
+#[cfg(test)]
+mod testing;
 mod user;
 mod utils;
 
+use actix::Actor;
 use actix_files::NamedFile;
 use actix_web::{get, web, App, HttpServer, Responder};
 use diesel::{r2d2::ConnectionManager, MysqlConnection};
+use std::time::Duration;
 
 type DbPool = r2d2::Pool<ConnectionManager<MysqlConnection>>;
 
@@ -20,17 +43,44 @@ type DbPool = r2d2::Pool<ConnectionManager<MysqlConnection>>;
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
 
-    let conn_url = std::env::var("DATABASE_URL").expect("DATABASE_URL not found");
-    let manager = ConnectionManager::<MysqlConnection>::new(conn_url);
+    let config = config::Config::load().expect("Failed to load config");
+
+    let manager = ConnectionManager::<MysqlConnection>::new(&config.database.url);
     let pool = r2d2::Pool::builder()
+        .max_size(config.database.pool_size)
+        .min_idle(config.database.min_idle)
+        .connection_timeout(Duration::from_secs(config.database.connection_timeout_secs))
         .build(manager)
         .expect("Failed to create pool");
 
-    let bind = "127.0.0.1:8080";
+    admin::bootstrap(&pool).expect("Failed to bootstrap administrator");
+
+    let broker = events::Broker::new(&config.events).start();
+
+    actix_rt::spawn(auth::run_session_sweeper(
+        pool.clone(),
+        config.session.clone(),
+    ));
+    actix_rt::spawn(schedule::run_schedule_generator(
+        pool.clone(),
+        config.schedule.clone(),
+    ));
+    actix_rt::spawn(penalty::run_no_show_marker(
+        pool.clone(),
+        broker.clone(),
+        config.booking.clone(),
+        config.penalty.clone(),
+    ));
+    actix_rt::spawn(outbox::run_outbox(pool.clone(), config.notifier.clone()));
 
-    HttpServer::new(move || {
+    let bind = config.server.bind.clone();
+    let workers = config.server.workers;
+
+    let server = HttpServer::new(move || {
         App::new()
             .data(pool.clone())
+            .data(config.clone())
+            .data(broker.clone())
             // user
             .service(
                 web::scope("/user")
@@ -46,8 +96,15 @@ async fn main() -> std::io::Result<()> {
                 web::scope("/admin")
                     .configure(admin::config),
             )
-    })
-    .bind(bind)?
-    .run()
-    .await
+            // waiting room
+            .service(
+                web::scope("/display")
+                    .configure(display::config),
+            )
+    });
+    let server = match workers {
+        Some(workers) => server.workers(workers),
+        None => server,
+    };
+    server.bind(bind)?.run().await
 }
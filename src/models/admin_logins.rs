@@ -7,4 +7,6 @@ pub struct AdminLoginData {
     pub token: String,
     pub aid: String,
     pub login_time: NaiveDateTime,
+    pub last_active: NaiveDateTime,
+    pub refresh_token: String,
 }
@@ -1,8 +1,41 @@
-use crate::schema::administrators;
+use crate::{error::ApiError, schema::administrators};
+use anyhow::bail;
 
 #[derive(Queryable, Insertable)]
 #[table_name = "administrators"]
 pub struct AdminData {
     pub aid: String,
     pub password: String,
+    pub role: String,
+    /// The department of a department admin.
+    pub dpid: Option<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum AdminRole {
+    /// Full access, including creating other administrators.
+    SuperAdmin,
+    /// Manages the doctors, information and comments of a single department.
+    DepartAdmin,
+    /// Read-only access to everything.
+    Auditor,
+}
+
+impl AdminRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AdminRole::SuperAdmin => "super_admin",
+            AdminRole::DepartAdmin => "depart_admin",
+            AdminRole::Auditor => "auditor",
+        }
+    }
+
+    pub fn parse(role: &str) -> anyhow::Result<Self> {
+        match role {
+            "super_admin" => Ok(AdminRole::SuperAdmin),
+            "depart_admin" => Ok(AdminRole::DepartAdmin),
+            "auditor" => Ok(AdminRole::Auditor),
+            _ => bail!(ApiError::InvalidAdminRole),
+        }
+    }
 }
@@ -0,0 +1,27 @@
+use crate::schema::appointment_events;
+use chrono::NaiveDateTime;
+
+/// One status change of an appointment. `from_status` is empty for a new booking.
+#[derive(Queryable)]
+pub struct AppointEvent {
+    pub eid: u64,
+    pub username: String,
+    pub tid: u64,
+    pub from_status: Option<String>,
+    pub to_status: String,
+    /// `user`, `doctor`, `admin` or `system`.
+    pub actor_kind: String,
+    pub actor_id: Option<String>,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "appointment_events"]
+pub struct NewAppointEvent {
+    pub username: String,
+    pub tid: u64,
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub actor_kind: String,
+    pub actor_id: Option<String>,
+}
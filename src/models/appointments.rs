@@ -1,5 +1,11 @@
-use crate::schema::appointments;
+use crate::{
+    error::ApiError,
+    i18n::{self, Locale},
+    schema::appointments,
+};
+use anyhow::Context;
 use chrono::NaiveDateTime;
+use serde::{Serialize, Serializer};
 
 #[derive(Queryable)]
 pub struct Appointment {
@@ -7,6 +13,12 @@ pub struct Appointment {
     pub tid: u64,
     pub status: String,
     pub time: NaiveDateTime,
+    /// Issued in booking order within the slot, starting from 1.
+    pub queue_number: i32,
+    /// When the doctor last called the patient in.
+    pub called_at: Option<NaiveDateTime>,
+    /// The patient didn't answer the call and can be recalled later.
+    pub skipped: bool,
 }
 
 #[derive(Insertable)]
@@ -16,8 +28,182 @@ pub struct NewAppointment {
     pub tid: u64,
     pub status: String,
     pub time: Option<NaiveDateTime>,
+    pub queue_number: i32,
 }
 
-pub const APPOINT_STATUS_UNFINISHED: &str = "未完成";
-pub const APPOINT_STATUS_FINISHED: &str = "已完成";
-pub const APPOINT_STATUS_CANCELED: &str = "已取消";
+/// Stored in `appointments.status` by `as_str()`, rendered in the requested locale.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AppointStatus {
+    /// Booked, the patient hasn't arrived yet.
+    #[default]
+    Unfinished,
+    CheckedIn,
+    InProgress,
+    Finished,
+    NoShow,
+    /// Canceled by the patient.
+    Canceled,
+    /// Canceled by the doctor or an administrator.
+    ClinicCanceled,
+}
+
+impl AppointStatus {
+    pub const ALL: [AppointStatus; 7] = [
+        AppointStatus::Unfinished,
+        AppointStatus::CheckedIn,
+        AppointStatus::InProgress,
+        AppointStatus::Finished,
+        AppointStatus::NoShow,
+        AppointStatus::Canceled,
+        AppointStatus::ClinicCanceled,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AppointStatus::Unfinished => "unfinished",
+            AppointStatus::CheckedIn => "checked_in",
+            AppointStatus::InProgress => "in_progress",
+            AppointStatus::Finished => "finished",
+            AppointStatus::NoShow => "no_show",
+            AppointStatus::Canceled => "canceled",
+            AppointStatus::ClinicCanceled => "clinic_canceled",
+        }
+    }
+
+    fn message_key(self) -> &'static str {
+        match self {
+            AppointStatus::Unfinished => "APPOINT_STATUS_UNFINISHED",
+            AppointStatus::CheckedIn => "APPOINT_STATUS_CHECKED_IN",
+            AppointStatus::InProgress => "APPOINT_STATUS_IN_PROGRESS",
+            AppointStatus::Finished => "APPOINT_STATUS_FINISHED",
+            AppointStatus::NoShow => "APPOINT_STATUS_NO_SHOW",
+            AppointStatus::Canceled => "APPOINT_STATUS_CANCELED",
+            AppointStatus::ClinicCanceled => "APPOINT_STATUS_CLINIC_CANCELED",
+        }
+    }
+
+    pub fn label(self, locale: Locale) -> &'static str {
+        i18n::message(self.message_key(), locale)
+    }
+
+    /// Whether the appointment takes one of the `appointed` seats of its slot.
+    pub fn holds_seat(self) -> bool {
+        !matches!(
+            self,
+            AppointStatus::Canceled | AppointStatus::ClinicCanceled
+        )
+    }
+
+    /// The legal transitions. A canceled appointment comes back by booking the slot again.
+    pub fn can_become(self, to: AppointStatus) -> bool {
+        use AppointStatus::*;
+
+        matches!(
+            (self, to),
+            (Unfinished, CheckedIn)
+                | (Unfinished, Finished)
+                | (Unfinished, NoShow)
+                | (Unfinished, Canceled)
+                | (Unfinished, ClinicCanceled)
+                | (CheckedIn, InProgress)
+                | (CheckedIn, Finished)
+                | (CheckedIn, NoShow)
+                | (CheckedIn, ClinicCanceled)
+                | (InProgress, Finished)
+                | (Canceled, Unfinished)
+        )
+    }
+
+    /// Accepts the stored value as well as its rendering in any locale.
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|status| status.as_str() == s || i18n::is_label(status.message_key(), s))
+            .context(ApiError::StatusFormat)
+    }
+
+    /// Parses a search filter, `None` stands for all statuses.
+    pub fn parse_filter(s: &str) -> anyhow::Result<Option<Self>> {
+        if s.is_empty() {
+            Ok(Some(AppointStatus::Unfinished))
+        } else if s == "all" || i18n::is_label("APPOINT_STATUS_ALL", s) {
+            Ok(None)
+        } else {
+            Self::parse(s).map(Some)
+        }
+    }
+}
+
+impl Serialize for AppointStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.label(Locale::current()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use AppointStatus::*;
+
+    #[test]
+    fn legal_transitions() {
+        for (from, to) in [
+            (Unfinished, CheckedIn),
+            (Unfinished, Finished),
+            (Unfinished, NoShow),
+            (Unfinished, Canceled),
+            (Unfinished, ClinicCanceled),
+            (CheckedIn, InProgress),
+            (CheckedIn, Finished),
+            (CheckedIn, NoShow),
+            (CheckedIn, ClinicCanceled),
+            (InProgress, Finished),
+            (Canceled, Unfinished),
+        ] {
+            assert!(from.can_become(to), "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn illegal_transitions() {
+        for to in AppointStatus::ALL {
+            assert!(!Finished.can_become(to), "Finished -> {:?}", to);
+            assert!(!NoShow.can_become(to), "NoShow -> {:?}", to);
+            assert!(!ClinicCanceled.can_become(to), "ClinicCanceled -> {:?}", to);
+            assert!(!to.can_become(to), "{:?} -> itself", to);
+        }
+        for to in [CheckedIn, InProgress, Finished, NoShow, ClinicCanceled] {
+            assert!(!Canceled.can_become(to), "Canceled -> {:?}", to);
+        }
+        assert!(!Unfinished.can_become(InProgress));
+        assert!(!CheckedIn.can_become(Canceled));
+        assert!(!InProgress.can_become(ClinicCanceled));
+        assert!(!InProgress.can_become(NoShow));
+    }
+
+    #[test]
+    fn parse_stored_values_and_labels() {
+        for status in AppointStatus::ALL {
+            assert_eq!(AppointStatus::parse(status.as_str()).unwrap(), status);
+            for locale in Locale::ALL {
+                assert_eq!(AppointStatus::parse(status.label(locale)).unwrap(), status);
+            }
+        }
+        assert_eq!(AppointStatus::parse("finished").unwrap(), Finished);
+        assert_eq!(AppointStatus::parse("FINISHED").unwrap(), Finished);
+        assert!(AppointStatus::parse("done").is_err());
+    }
+
+    #[test]
+    fn parse_filter() {
+        assert_eq!(AppointStatus::parse_filter("").unwrap(), Some(Unfinished));
+        assert_eq!(AppointStatus::parse_filter("all").unwrap(), None);
+        assert_eq!(AppointStatus::parse_filter("All").unwrap(), None);
+        assert_eq!(
+            AppointStatus::parse_filter("no_show").unwrap(),
+            Some(NoShow)
+        );
+        assert!(AppointStatus::parse_filter("done").is_err());
+    }
+}
@@ -1,4 +1,8 @@
-use crate::schema::comments;
+use crate::{
+    error::ApiError,
+    schema::{comment_replies, comment_reports, comments, doctor_ratings},
+};
+use anyhow::Context;
 use chrono::NaiveDateTime;
 
 #[derive(Queryable)]
@@ -8,6 +12,15 @@ pub struct Comment {
     pub did: String,
     pub comment: String,
     pub time: NaiveDateTime,
+    /// The finished appointment the review is about, unset for comments from before ratings.
+    pub tid: Option<u64>,
+    /// 1 to 5 stars.
+    pub rating: Option<i32>,
+    pub status: String,
+    /// Who set the status last, unset while it is the one the comment was posted with.
+    pub status_actor_kind: Option<String>,
+    pub status_actor_id: Option<String>,
+    pub status_time: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -16,4 +29,93 @@ pub struct NewComment {
     pub username: String,
     pub did: String,
     pub comment: String,
+    pub tid: Option<u64>,
+    pub rating: Option<i32>,
+    pub status: String,
+}
+
+/// Stored in `comments.status` by `as_str()`. Only visible comments are shown to users and
+/// doctors and count towards the rating.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommentStatus {
+    Visible,
+    Hidden,
+    /// Waiting for a moderator.
+    Pending,
+    /// Deleted by the author or a moderator, the text is kept.
+    Deleted,
+}
+
+impl CommentStatus {
+    pub const ALL: [CommentStatus; 4] = [
+        CommentStatus::Visible,
+        CommentStatus::Hidden,
+        CommentStatus::Pending,
+        CommentStatus::Deleted,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CommentStatus::Visible => "visible",
+            CommentStatus::Hidden => "hidden",
+            CommentStatus::Pending => "pending",
+            CommentStatus::Deleted => "deleted",
+        }
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|status| status.as_str() == s)
+            .context(ApiError::StatusFormat)
+    }
+}
+
+/// An open report of a comment, as listed for moderators.
+#[derive(Queryable)]
+pub struct CommentReport {
+    pub rpid: u64,
+    /// `user` or `doctor`.
+    pub reporter_kind: String,
+    pub reporter_id: String,
+    pub reason: String,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "comment_reports"]
+pub struct NewCommentReport {
+    pub cid: u64,
+    pub reporter_kind: String,
+    pub reporter_id: String,
+    pub reason: String,
+}
+
+/// The public answer of the doctor to a comment about them, at most one per comment.
+#[derive(Queryable)]
+pub struct CommentReply {
+    pub cid: u64,
+    pub did: String,
+    pub reply: String,
+    pub time: NaiveDateTime,
+    /// Set once the reply is edited.
+    pub modified_time: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "comment_replies"]
+pub struct NewCommentReply {
+    pub cid: u64,
+    pub did: String,
+    pub reply: String,
+}
+
+/// The sum and number of the ratings of a doctor, kept for sorting doctors by rating.
+#[derive(Queryable, Insertable)]
+#[table_name = "doctor_ratings"]
+pub struct DoctorRating {
+    pub did: String,
+    pub rating_total: i32,
+    pub rating_count: i32,
 }
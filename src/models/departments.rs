@@ -1,8 +1,20 @@
 use crate::schema::departments;
 
-#[derive(Queryable, Insertable)]
-#[table_name = "departments"]
+#[derive(Queryable)]
 pub struct DepartData {
+    pub dpid: u64,
+    pub depart_name: String,
+    pub information: String,
+    /// Unset for top-level departments.
+    pub parent: Option<u64>,
+    /// Retired departments keep their id and name but take no doctors.
+    pub retired: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "departments"]
+pub struct NewDepart {
     pub depart_name: String,
     pub information: String,
+    pub parent: Option<u64>,
 }
@@ -0,0 +1,27 @@
+use crate::schema::doctor_leaves;
+use chrono::NaiveDateTime;
+
+/// A period the doctor is away. Slots in it are made unavailable and no new ones are added.
+#[derive(Queryable)]
+pub struct DoctorLeave {
+    pub lid: u64,
+    pub did: String,
+    pub start_time: NaiveDateTime,
+    pub end_time: NaiveDateTime,
+    pub reason: String,
+    /// `doctor` or `admin`.
+    pub actor_kind: String,
+    pub actor_id: Option<String>,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "doctor_leaves"]
+pub struct NewDoctorLeave {
+    pub did: String,
+    pub start_time: NaiveDateTime,
+    pub end_time: NaiveDateTime,
+    pub reason: String,
+    pub actor_kind: String,
+    pub actor_id: Option<String>,
+}
@@ -7,4 +7,6 @@ pub struct DoctorLoginData {
     pub token: String,
     pub did: String,
     pub login_time: NaiveDateTime,
+    pub last_active: NaiveDateTime,
+    pub refresh_token: String,
 }
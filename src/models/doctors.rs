@@ -1,4 +1,4 @@
-use crate::schema::doctors;
+use crate::schema::{doctor_departments, doctors};
 use chrono::NaiveDate;
 
 #[derive(Queryable, Insertable)]
@@ -9,7 +9,8 @@ pub struct DoctorData {
     pub password: String,
     pub gender: String,
     pub birthday: Option<NaiveDate>,
-    pub department: String,
+    /// The main department.
+    pub dpid: u64,
     #[column_name = "rankk"]
     pub rank: String,
     pub information: String,
@@ -24,5 +25,14 @@ pub struct UpdateDoctor {
     pub information: Option<String>,
     #[column_name = "rankk"]
     pub rank: Option<String>,
-    pub department: Option<String>,
+    pub dpid: Option<u64>,
+}
+
+/// A department the doctor consults in. `DoctorData.dpid` is the main one and is always
+/// among them.
+#[derive(Queryable, Insertable)]
+#[table_name = "doctor_departments"]
+pub struct DoctorDepart {
+    pub did: String,
+    pub dpid: u64,
 }
@@ -0,0 +1,23 @@
+use crate::schema::locations;
+
+/// A campus of the hospital.
+#[derive(Queryable)]
+pub struct Location {
+    pub lcid: u64,
+    pub name: String,
+    pub address: String,
+}
+
+#[derive(Insertable)]
+#[table_name = "locations"]
+pub struct NewLocation {
+    pub name: String,
+    pub address: String,
+}
+
+#[derive(AsChangeset, Default)]
+#[table_name = "locations"]
+pub struct UpdateLocation {
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
@@ -1,10 +1,17 @@
 pub mod administrators;
+pub mod appointment_events;
 pub mod appointments;
 pub mod comments;
 pub mod departments;
+pub mod doctor_leaves;
 pub mod doctors;
+pub mod locations;
+pub mod notifications;
+pub mod reschedules;
+pub mod schedules;
 pub mod times;
 pub mod users;
+pub mod waitlists;
 
 pub mod admin_logins;
 pub mod doctor_logins;
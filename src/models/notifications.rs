@@ -0,0 +1,37 @@
+use crate::schema::{notification_outbox, notification_preferences};
+
+/// What the outbox needs to send a queued message.
+#[derive(Queryable)]
+pub struct OutboxMessage {
+    pub nid: u64,
+    pub channel: String,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub attempts: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "notification_outbox"]
+pub struct NewOutboxMessage {
+    pub username: String,
+    pub tid: Option<u64>,
+    pub kind: String,
+    pub channel: String,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Users are contacted only on the channels they opted in to.
+#[derive(Queryable, Insertable, AsChangeset)]
+#[table_name = "notification_preferences"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct NotifyPreference {
+    pub username: String,
+    pub email: Option<String>,
+    pub email_enabled: bool,
+    pub sms_enabled: bool,
+    pub reminders_enabled: bool,
+    pub locale: String,
+}
@@ -0,0 +1,19 @@
+use crate::schema::reschedules;
+use chrono::NaiveDateTime;
+
+/// Links a canceled appointment at `from_tid` to the one at `to_tid`, as listed for its user.
+#[derive(Queryable)]
+pub struct Reschedule {
+    pub rid: u64,
+    pub from_tid: u64,
+    pub to_tid: u64,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "reschedules"]
+pub struct NewReschedule {
+    pub username: String,
+    pub from_tid: u64,
+    pub to_tid: u64,
+}
@@ -0,0 +1,55 @@
+use crate::schema::{schedule_exceptions, schedules};
+use chrono::{NaiveDate, NaiveTime};
+
+/// A weekly template, materialized into `times` rows by `crate::schedule`.
+#[derive(Queryable)]
+pub struct Schedule {
+    pub sid: u64,
+    pub did: String,
+    /// 1 for Monday, ..., 7 for Sunday.
+    pub weekday: i32,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub capacity: i32,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    /// Copied to the generated slots.
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+#[derive(Insertable)]
+#[table_name = "schedules"]
+pub struct NewSchedule {
+    pub did: String,
+    pub weekday: i32,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub capacity: i32,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "schedules"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct UpdateSchedule {
+    pub weekday: i32,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub capacity: i32,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+/// A date on which the template doesn't generate a slot.
+#[derive(Queryable, Insertable)]
+#[table_name = "schedule_exceptions"]
+pub struct ScheduleException {
+    pub sid: u64,
+    pub date: NaiveDate,
+}
@@ -1,5 +1,11 @@
-use crate::schema::times;
+use crate::{
+    error::ApiError,
+    i18n::{self, Locale},
+    schema::times,
+};
+use anyhow::bail;
 use chrono::NaiveDateTime;
+use serde::{Serialize, Serializer};
 
 #[derive(Queryable)]
 pub struct TimeData {
@@ -9,6 +15,19 @@ pub struct TimeData {
     pub end_time: NaiveDateTime,
     pub capacity: i32,
     pub appointed: i32,
+    /// The schedule template this slot was generated from.
+    pub sid: Option<u64>,
+    /// The last queue number handed out.
+    pub issued_number: i32,
+    /// The queue number the doctor called last.
+    pub serving_number: Option<i32>,
+    pub called_at: Option<NaiveDateTime>,
+    /// Cleared while the doctor is on leave, the slot can't be booked then.
+    pub available: bool,
+    /// The department the doctor consults for in this slot.
+    pub dpid: Option<u64>,
+    /// The campus the slot takes place at, unset when it isn't known.
+    pub lcid: Option<u64>,
 }
 
 #[derive(Insertable)]
@@ -18,6 +37,9 @@ pub struct NewTime {
     pub start_time: NaiveDateTime,
     pub end_time: NaiveDateTime,
     pub capacity: i32,
+    pub sid: Option<u64>,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
 }
 
 #[derive(AsChangeset, Default)]
@@ -26,7 +48,40 @@ pub struct UpdateTime {
     pub start_time: Option<NaiveDateTime>,
     pub end_time: Option<NaiveDateTime>,
     pub capacity: Option<i32>,
+    pub available: Option<bool>,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
 }
 
-pub const TIME_AM: &str = "上午";
-pub const TIME_PM: &str = "下午";
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Period {
+    #[default]
+    Am,
+    Pm,
+}
+
+impl Period {
+    fn message_key(self) -> &'static str {
+        match self {
+            Period::Am => "PERIOD_AM",
+            Period::Pm => "PERIOD_PM",
+        }
+    }
+
+    /// Accepts `am` / `pm` as well as the rendering in any locale.
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        if s == "am" || i18n::is_label(Period::Am.message_key(), s) {
+            Ok(Period::Am)
+        } else if s == "pm" || i18n::is_label(Period::Pm.message_key(), s) {
+            Ok(Period::Pm)
+        } else {
+            bail!(ApiError::TimeFormat)
+        }
+    }
+}
+
+impl Serialize for Period {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(i18n::message(self.message_key(), Locale::current()))
+    }
+}
@@ -7,4 +7,6 @@ pub struct UserLoginData {
     pub token: String,
     pub username: String,
     pub login_time: NaiveDateTime,
+    pub last_active: NaiveDateTime,
+    pub refresh_token: String,
 }
@@ -1,5 +1,5 @@
 use crate::schema::users;
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 
 #[derive(Queryable, Insertable, Identifiable)]
 #[primary_key(username)]
@@ -13,6 +13,8 @@ pub struct UserData {
     pub id_number: String,
     pub telephone: String,
     pub is_banned: bool,
+    /// No-shows before this time don't count towards the booking restriction.
+    pub penalty_cleared_at: Option<NaiveDateTime>,
 }
 
 #[derive(AsChangeset, Default)]
@@ -0,0 +1,18 @@
+use crate::schema::waitlists;
+use chrono::NaiveDateTime;
+
+/// A user waiting for a seat of a full time slot. Entries are served in `wid` order.
+#[derive(Queryable)]
+pub struct WaitlistEntry {
+    pub wid: u64,
+    pub username: String,
+    pub tid: u64,
+    pub time: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "waitlists"]
+pub struct NewWaitlistEntry {
+    pub username: String,
+    pub tid: u64,
+}
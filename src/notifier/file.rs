@@ -0,0 +1,42 @@
+use std::{fs::OpenOptions, io::Write};
+
+use anyhow::Context;
+use chrono::Utc;
+
+use super::{Channel, Message, Notifier};
+
+/// Appends messages to a file instead of sending them, for local testing.
+pub struct FileSink {
+    path: String,
+    channel: Channel,
+}
+
+impl FileSink {
+    pub fn new(path: &str, channel: Channel) -> Self {
+        Self {
+            path: path.to_string(),
+            channel,
+        }
+    }
+}
+
+impl Notifier for FileSink {
+    fn send(&self, message: &Message) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("failed to open {}", self.path))?;
+        write!(
+            file,
+            "[{}] {} to {}: {}\n{}\n\n",
+            Utc::now().naive_utc(),
+            self.channel.as_str(),
+            message.recipient,
+            message.subject,
+            message.body
+        )
+        .with_context(|| format!("failed to write {}", self.path))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+
+use super::{Message, Notifier};
+use crate::config::NotifierConfig;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize)]
+struct SmsRequest<'a> {
+    to: &'a str,
+    text: &'a str,
+}
+
+/// Posts text messages as JSON to an HTTP gateway. The token is only sent over `https://`.
+pub struct SmsGateway {
+    url: String,
+    token: Option<String>,
+}
+
+impl SmsGateway {
+    pub fn new(config: &NotifierConfig) -> Self {
+        Self {
+            url: config.sms_gateway_url.clone(),
+            token: config.sms_gateway_token.clone(),
+        }
+    }
+}
+
+impl Notifier for SmsGateway {
+    fn send(&self, message: &Message) -> anyhow::Result<()> {
+        let mut request = ureq::post(&self.url).timeout(TIMEOUT);
+        if let Some(token) = &self.token {
+            if !self.url.starts_with("https://") {
+                bail!("refusing to send the SMS gateway token without TLS");
+            }
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+
+        match request.send_json(SmsRequest {
+            to: message.recipient,
+            text: message.body,
+        }) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => Err(anyhow!(
+                "SMS gateway answered {} {}",
+                status,
+                response.status_text()
+            )),
+            Err(err) => Err(anyhow!(err).context("failed to reach the SMS gateway")),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+mod file;
+mod gateway;
+mod smtp;
+
+use anyhow::bail;
+
+use crate::config::NotifierConfig;
+
+use self::{file::FileSink, gateway::SmsGateway, smtp::Smtp};
+
+/// One outgoing message, `recipient` is an email address or a telephone number.
+pub struct Message<'a> {
+    pub recipient: &'a str,
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+/// Delivers messages of one channel. Sending blocks, the outbox calls it on the blocking
+/// pool.
+pub trait Notifier: Send + Sync {
+    fn send(&self, message: &Message) -> anyhow::Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    Email,
+    Sms,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 2] = [Channel::Email, Channel::Sms];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Channel::Email => "email",
+            Channel::Sms => "sms",
+        }
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        match Self::ALL.iter().find(|channel| channel.as_str() == s) {
+            Some(&channel) => Ok(channel),
+            None => bail!("unknown notification channel {}", s),
+        }
+    }
+}
+
+/// The configured notifier of each channel, `None` where the channel is turned off.
+pub struct Notifiers {
+    email: Option<Box<dyn Notifier>>,
+    sms: Option<Box<dyn Notifier>>,
+}
+
+impl Notifiers {
+    pub fn from_config(config: &NotifierConfig) -> Self {
+        let email: Option<Box<dyn Notifier>> = match config.email.as_str() {
+            "smtp" => Some(Box::new(Smtp::new(config))),
+            "log" => Some(Box::new(FileSink::new(&config.log_file, Channel::Email))),
+            _ => None,
+        };
+        let sms: Option<Box<dyn Notifier>> = match config.sms.as_str() {
+            "gateway" => Some(Box::new(SmsGateway::new(config))),
+            "log" => Some(Box::new(FileSink::new(&config.log_file, Channel::Sms))),
+            _ => None,
+        };
+        Self { email, sms }
+    }
+
+    /// The channels that are turned on.
+    pub fn enabled(&self) -> Vec<&'static str> {
+        Channel::ALL
+            .iter()
+            .filter(|&&channel| self.get(channel).is_some())
+            .map(|channel| channel.as_str())
+            .collect()
+    }
+
+    pub fn get(&self, channel: Channel) -> Option<&dyn Notifier> {
+        match channel {
+            Channel::Email => self.email.as_deref(),
+            Channel::Sms => self.sms.as_deref(),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::{
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+    },
+    Message as Email, SmtpTransport, Transport,
+};
+
+use super::{Message, Notifier};
+use crate::config::NotifierConfig;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends emails through an SMTP server, over STARTTLS or implicit TLS unless `smtp_tls` is
+/// `none`. Credentials are never sent without TLS.
+pub struct Smtp {
+    host: String,
+    port: u16,
+    tls: String,
+    username: Option<String>,
+    password: Option<String>,
+    from: String,
+}
+
+impl Smtp {
+    pub fn new(config: &NotifierConfig) -> Self {
+        Self {
+            host: config.smtp_host.clone(),
+            port: config.smtp_port,
+            tls: config.smtp_tls.clone(),
+            username: config.smtp_username.clone(),
+            password: config.smtp_password.clone(),
+            from: config.smtp_from.clone(),
+        }
+    }
+
+    fn transport(&self) -> anyhow::Result<SmtpTransport> {
+        let tls = match self.tls.as_str() {
+            "none" => Tls::None,
+            mode => {
+                let params = TlsParameters::new(self.host.clone())
+                    .with_context(|| format!("invalid SMTP host {}", self.host))?;
+                if mode == "tls" {
+                    Tls::Wrapper(params)
+                } else {
+                    Tls::Required(params)
+                }
+            }
+        };
+        let mut builder = SmtpTransport::builder_dangerous(&self.host)
+            .port(self.port)
+            .timeout(Some(TIMEOUT));
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            if matches!(tls, Tls::None) {
+                bail!("refusing to send SMTP credentials without TLS");
+            }
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(builder.tls(tls).build())
+    }
+}
+
+impl Notifier for Smtp {
+    fn send(&self, message: &Message) -> anyhow::Result<()> {
+        let from = self
+            .from
+            .parse::<Mailbox>()
+            .with_context(|| format!("invalid email address {}", self.from))?;
+        let to = message
+            .recipient
+            .parse::<Mailbox>()
+            .with_context(|| format!("invalid email address {}", message.recipient))?;
+        let email = Email::builder()
+            .from(from)
+            .to(to)
+            .subject(message.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.body.to_string())
+            .context("failed to build the email")?;
+
+        self.transport()?
+            .send(&email)
+            .with_context(|| format!("failed to send through {}:{}", self.host, self.port))?;
+        Ok(())
+    }
+}
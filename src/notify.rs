@@ -0,0 +1,174 @@
+use std::cell::RefCell;
+
+use actix::Addr;
+use actix_web::web::Bytes;
+use anyhow::Context;
+use diesel::{prelude::*, MysqlConnection};
+use serde::Serialize;
+
+use crate::{
+    error::ApiError,
+    events::{Broker, Publish},
+    models::{appointments::AppointStatus, times::TimeData},
+    utils::format_time_str,
+};
+
+// Pushes changes to the event streams of the users and doctors they concern. Events are
+// queued while the transaction runs and only sent once it commits, so clients never see a
+// change that was rolled back.
+
+thread_local! {
+    static PENDING: RefCell<Vec<(Vec<String>, Bytes)>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn user_topic(username: &str) -> String {
+    format!("user/{}", username)
+}
+
+pub fn doctor_topic(did: &str) -> String {
+    format!("doctor/{}", did)
+}
+
+/// Sent as an `appointment` event when an appointment is booked or changes its status.
+#[derive(Serialize)]
+pub struct AppointNotice {
+    pub username: String,
+    pub tid: u64,
+    pub did: String,
+    pub from_status: Option<&'static str>,
+    pub status: &'static str,
+    /// `user`, `doctor`, `admin` or `system`.
+    pub actor_kind: &'static str,
+}
+
+/// Sent as a `time` event when a doctor changes or deletes a slot, or it is closed for a leave.
+#[derive(Serialize)]
+pub struct TimeNotice {
+    pub tid: u64,
+    pub did: String,
+    /// `modified`, `deleted`, `unavailable` or `available`.
+    pub change: &'static str,
+    pub start_time: String,
+    pub end_time: String,
+    pub capacity: i32,
+}
+
+fn queue<T: Serialize>(topics: Vec<String>, event: &str, data: &T) {
+    let frame = crate::events::frame(event, data);
+    PENDING.with(|pending| pending.borrow_mut().push((topics, frame)));
+}
+
+/// Runs `f` in a transaction and sends the events it queued to the broker if it commits.
+pub fn transaction<T, F>(conn: &MysqlConnection, broker: &Addr<Broker>, f: F) -> anyhow::Result<T>
+where
+    F: FnOnce() -> anyhow::Result<T>,
+{
+    PENDING.with(|pending| pending.borrow_mut().clear());
+    let res = conn.transaction(f);
+    let pending = PENDING.with(|pending| pending.take());
+    if res.is_ok() {
+        for (topics, frame) in pending {
+            broker.do_send(Publish { topics, frame });
+        }
+    }
+    res
+}
+
+pub fn appointment_changed(
+    username: &str,
+    time_data: &TimeData,
+    from: Option<AppointStatus>,
+    to: AppointStatus,
+    actor_kind: &'static str,
+) {
+    let notice = AppointNotice {
+        username: username.to_string(),
+        tid: time_data.tid,
+        did: time_data.did.clone(),
+        from_status: from.map(AppointStatus::as_str),
+        status: to.as_str(),
+        actor_kind,
+    };
+    queue(
+        vec![user_topic(username), doctor_topic(&time_data.did)],
+        "appointment",
+        &notice,
+    );
+}
+
+/// Tells the doctor and everyone holding a seat of, or waiting for, the slot.
+pub fn time_changed(
+    conn: &MysqlConnection,
+    time_data: &TimeData,
+    change: &'static str,
+) -> anyhow::Result<()> {
+    use crate::schema::{appointments, waitlists};
+
+    let holding = AppointStatus::ALL
+        .iter()
+        .filter(|status| status.holds_seat())
+        .map(|status| status.as_str())
+        .collect::<Vec<_>>();
+    let mut usernames = appointments::table
+        .filter(appointments::tid.eq(time_data.tid))
+        .filter(appointments::status.eq_any(holding))
+        .select(appointments::username)
+        .get_results::<String>(conn)
+        .context(ApiError::Database)?;
+    usernames.extend(
+        waitlists::table
+            .filter(waitlists::tid.eq(time_data.tid))
+            .select(waitlists::username)
+            .get_results::<String>(conn)
+            .context(ApiError::Database)?,
+    );
+
+    let mut topics = vec![doctor_topic(&time_data.did)];
+    topics.extend(usernames.iter().map(|username| user_topic(username)));
+    let notice = TimeNotice {
+        tid: time_data.tid,
+        did: time_data.did.clone(),
+        change,
+        start_time: format_time_str(&time_data.start_time),
+        end_time: format_time_str(&time_data.end_time),
+        capacity: time_data.capacity,
+    };
+    queue(topics, "time", &notice);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::{events, testing};
+
+    #[actix_rt::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn time_changed_is_sent_after_commit_only() {
+        let pool = testing::pool();
+        let broker = testing::broker();
+        let tid = testing::add_time(&pool, "doctor_a");
+        let mut receiver = events::subscribe(&broker, vec![doctor_topic("doctor_a")]);
+
+        let conn = pool.get().unwrap();
+        let time_data = crate::booking::lock_time(&conn, tid).unwrap();
+        let res = transaction(&conn, &broker, || -> anyhow::Result<()> {
+            time_changed(&conn, &time_data, "deleted")?;
+            anyhow::bail!(ApiError::Internal)
+        });
+        assert!(res.is_err());
+        transaction(&conn, &broker, || {
+            time_changed(&conn, &time_data, "modified")
+        })
+        .unwrap();
+
+        // the broker handles messages in order, so a leaked event would come first
+        let frame = receiver.next().await.unwrap();
+        let frame = String::from_utf8_lossy(&frame);
+        assert!(frame.starts_with("event: time\n"));
+        assert!(frame.contains(r#""change":"modified""#));
+        assert!(!frame.contains(r#""change":"deleted""#));
+    }
+}
@@ -0,0 +1,346 @@
+use std::{sync::Arc, time::Duration};
+
+use actix_web::web;
+use anyhow::Context;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    config::NotifierConfig,
+    depart,
+    error::ApiError,
+    i18n::{self, Locale},
+    models::{
+        appointments::AppointStatus,
+        notifications::{NewOutboxMessage, NotifyPreference, OutboxMessage},
+    },
+    notifier::{Channel, Message, Notifiers},
+    DbPool,
+};
+
+// Outgoing emails and text messages. They are written to `notification_outbox` on the
+// transaction of the change they announce, and sent by a background job that retries
+// failures with a growing delay.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Booked,
+    Canceled,
+    /// Canceled by the clinic, the patient may reschedule it.
+    ClinicCanceled,
+    Rescheduled,
+    /// Sent `reminder_hours` before the slot starts.
+    Reminder,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Booked => "booked",
+            Kind::Canceled => "canceled",
+            Kind::ClinicCanceled => "clinic_canceled",
+            Kind::Rescheduled => "rescheduled",
+            Kind::Reminder => "reminder",
+        }
+    }
+
+    fn template_keys(self) -> (&'static str, &'static str) {
+        match self {
+            Kind::Booked => ("NOTIFY_BOOKED_SUBJECT", "NOTIFY_BOOKED_BODY"),
+            Kind::Canceled => ("NOTIFY_CANCELED_SUBJECT", "NOTIFY_CANCELED_BODY"),
+            Kind::ClinicCanceled => (
+                "NOTIFY_CLINIC_CANCELED_SUBJECT",
+                "NOTIFY_CLINIC_CANCELED_BODY",
+            ),
+            Kind::Rescheduled => ("NOTIFY_RESCHEDULED_SUBJECT", "NOTIFY_RESCHEDULED_BODY"),
+            Kind::Reminder => ("NOTIFY_REMINDER_SUBJECT", "NOTIFY_REMINDER_BODY"),
+        }
+    }
+}
+
+fn format_time(time: &NaiveDateTime) -> String {
+    time.format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn find_preference(
+    conn: &MysqlConnection,
+    username: &str,
+) -> anyhow::Result<Option<NotifyPreference>> {
+    use crate::schema::notification_preferences;
+
+    notification_preferences::table
+        .filter(notification_preferences::username.eq(username))
+        .get_result::<NotifyPreference>(conn)
+        .optional()
+        .context(ApiError::Database)
+}
+
+/// The settings of users that never changed them, nothing is sent to them.
+pub fn preference(conn: &MysqlConnection, username: &str) -> anyhow::Result<NotifyPreference> {
+    Ok(
+        find_preference(conn, username)?.unwrap_or_else(|| NotifyPreference {
+            username: username.to_string(),
+            email: None,
+            email_enabled: false,
+            sms_enabled: false,
+            reminders_enabled: true,
+            locale: Locale::default().as_str().to_string(),
+        }),
+    )
+}
+
+pub fn save_preference(conn: &MysqlConnection, data: &NotifyPreference) -> anyhow::Result<()> {
+    use crate::schema::notification_preferences;
+
+    if find_preference(conn, &data.username)?.is_some() {
+        diesel::update(
+            notification_preferences::table
+                .filter(notification_preferences::username.eq(&data.username)),
+        )
+        .set(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    } else {
+        diesel::insert_into(notification_preferences::table)
+            .values(data)
+            .execute(conn)
+            .context(ApiError::Database)?;
+    }
+    Ok(())
+}
+
+/// Queues the message about the appointment of `username` at `tid` on every channel the user
+/// opted in to. `from_tid` is the slot a rescheduled appointment moved away from.
+pub fn enqueue(
+    conn: &MysqlConnection,
+    kind: Kind,
+    username: &str,
+    tid: u64,
+    from_tid: Option<u64>,
+) -> anyhow::Result<()> {
+    use crate::schema::{appointments, departments, doctors, notification_outbox, times, users};
+
+    let pref = match find_preference(conn, username)? {
+        Some(pref) => pref,
+        None => return Ok(()),
+    };
+    if kind == Kind::Reminder && !pref.reminders_enabled {
+        return Ok(());
+    }
+    let mut recipients = vec![];
+    if pref.email_enabled {
+        if let Some(email) = pref.email {
+            recipients.push((Channel::Email, email));
+        }
+    }
+    if pref.sms_enabled {
+        let telephone = users::table
+            .filter(users::username.eq(username))
+            .select(users::telephone)
+            .get_result::<String>(conn)
+            .context(ApiError::Database)?;
+        if !telephone.is_empty() {
+            recipients.push((Channel::Sms, telephone));
+        }
+    }
+    if recipients.is_empty() {
+        return Ok(());
+    }
+
+    let (name, start_time, doctor, depart, queue_number) = appointments::table
+        .inner_join(users::table.on(appointments::username.eq(users::username)))
+        .inner_join(times::table.on(appointments::tid.eq(times::tid)))
+        .inner_join(doctors::table.on(times::did.eq(doctors::did)))
+        .inner_join(
+            departments::table
+                .on(departments::dpid.eq(depart::slot_or_main(times::dpid, doctors::dpid))),
+        )
+        .filter(appointments::username.eq(username))
+        .filter(appointments::tid.eq(tid))
+        .select((
+            users::name,
+            times::start_time,
+            doctors::name,
+            departments::depart_name,
+            appointments::queue_number,
+        ))
+        .get_result::<(String, NaiveDateTime, String, String, i32)>(conn)
+        .context(ApiError::Database)?;
+    let from_time = match from_tid {
+        Some(from_tid) => Some(
+            times::table
+                .filter(times::tid.eq(from_tid))
+                .select(times::start_time)
+                .get_result::<NaiveDateTime>(conn)
+                .context(ApiError::Database)?,
+        ),
+        None => None,
+    };
+
+    let locale = Locale::parse(&pref.locale).unwrap_or_default();
+    let (subject_key, body_key) = kind.template_keys();
+    let subject = i18n::message(subject_key, locale).to_string();
+    let body = i18n::message(body_key, locale)
+        .replace("{name}", &name)
+        .replace("{doctor}", &doctor)
+        .replace("{depart}", &depart)
+        .replace("{time}", &format_time(&start_time))
+        .replace("{queue_number}", &queue_number.to_string())
+        .replace(
+            "{from_time}",
+            &from_time.as_ref().map(format_time).unwrap_or_default(),
+        );
+
+    let data = recipients
+        .into_iter()
+        .map(|(channel, recipient)| NewOutboxMessage {
+            username: username.to_string(),
+            tid: Some(tid),
+            kind: kind.as_str().to_string(),
+            channel: channel.as_str().to_string(),
+            recipient,
+            subject: subject.clone(),
+            body: body.clone(),
+        })
+        .collect::<Vec<_>>();
+    diesel::insert_into(notification_outbox::table)
+        .values(&data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// Queues reminders of the appointments starting within `reminder_hours`.
+fn queue_reminders(pool: &DbPool, config: &NotifierConfig) -> anyhow::Result<()> {
+    use crate::schema::{appointments, notification_outbox, times};
+
+    let conn = pool.get().context(ApiError::Database)?;
+    let now = Utc::now().naive_utc();
+    let due = appointments::table
+        .inner_join(times::table.on(appointments::tid.eq(times::tid)))
+        .filter(appointments::status.eq_any(vec![
+            AppointStatus::Unfinished.as_str(),
+            AppointStatus::CheckedIn.as_str(),
+        ]))
+        .filter(times::start_time.gt(now))
+        .filter(times::start_time.le(now + chrono::Duration::hours(config.reminder_hours)))
+        .select((appointments::username, appointments::tid))
+        .get_results::<(String, u64)>(&conn)
+        .context(ApiError::Database)?;
+
+    for (username, tid) in due {
+        // one appointment that can't be reminded must not hold up the others
+        let res = conn.transaction(|| {
+            let reminded = notification_outbox::table
+                .filter(notification_outbox::username.eq(&username))
+                .filter(notification_outbox::tid.eq(tid))
+                .filter(notification_outbox::kind.eq(Kind::Reminder.as_str()))
+                .count()
+                .get_result::<i64>(&conn)
+                .context(ApiError::Database)?;
+            if reminded > 0 {
+                return Ok(());
+            }
+            enqueue(&conn, Kind::Reminder, &username, tid, None)
+        });
+        if let Err(err) = res {
+            eprintln!(
+                "failed to queue the reminder of {} at {}: {:#}",
+                username, tid, err
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Sends the messages that are due, and schedules retries of the failed ones. Messages of
+/// channels that are turned off wait, without using up their attempts, until the channel is
+/// turned on again.
+fn deliver(pool: &DbPool, notifiers: &Notifiers, config: &NotifierConfig) -> anyhow::Result<()> {
+    use crate::schema::notification_outbox;
+
+    let channels = notifiers.enabled();
+    if channels.is_empty() {
+        return Ok(());
+    }
+    let conn = pool.get().context(ApiError::Database)?;
+    let now = Utc::now().naive_utc();
+    let due = notification_outbox::table
+        .filter(notification_outbox::sent_at.is_null())
+        .filter(notification_outbox::channel.eq_any(channels))
+        .filter(notification_outbox::attempts.lt(config.max_attempts))
+        .filter(notification_outbox::next_attempt_at.le(now))
+        .order(notification_outbox::nid.asc())
+        .limit(100)
+        .select((
+            notification_outbox::nid,
+            notification_outbox::channel,
+            notification_outbox::recipient,
+            notification_outbox::subject,
+            notification_outbox::body,
+            notification_outbox::attempts,
+        ))
+        .get_results::<OutboxMessage>(&conn)
+        .context(ApiError::Database)?;
+
+    for message in due {
+        let res = Channel::parse(&message.channel).and_then(|channel| {
+            let notifier = notifiers
+                .get(channel)
+                .with_context(|| format!("{} notifications are turned off", channel.as_str()))?;
+            notifier.send(&Message {
+                recipient: &message.recipient,
+                subject: &message.subject,
+                body: &message.body,
+            })
+        });
+
+        let target = notification_outbox::table.filter(notification_outbox::nid.eq(message.nid));
+        let attempts = message.attempts + 1;
+        match res {
+            Ok(()) => diesel::update(target)
+                .set((
+                    notification_outbox::attempts.eq(attempts),
+                    notification_outbox::sent_at.eq(Utc::now().naive_utc()),
+                    notification_outbox::last_error.eq(None::<String>),
+                ))
+                .execute(&conn),
+            Err(err) => {
+                let delay = config.retry_base_secs << (attempts - 1).clamp(0, 16);
+                diesel::update(target)
+                    .set((
+                        notification_outbox::attempts.eq(attempts),
+                        notification_outbox::next_attempt_at
+                            .eq(Utc::now().naive_utc() + chrono::Duration::seconds(delay)),
+                        notification_outbox::last_error.eq(format!("{:#}", err)),
+                    ))
+                    .execute(&conn)
+            }
+        }
+        .context(ApiError::Database)?;
+    }
+    Ok(())
+}
+
+/// Periodically queues reminders and delivers the outbox.
+pub async fn run_outbox(pool: DbPool, config: NotifierConfig) {
+    let notifiers = Arc::new(Notifiers::from_config(&config));
+    let mut interval =
+        actix_rt::time::interval(Duration::from_secs(config.deliver_interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let config = config.clone();
+        let notifiers = notifiers.clone();
+        let res = web::block(move || {
+            if let Err(err) = queue_reminders(&pool, &config) {
+                eprintln!("failed to queue reminders: {:#}", err);
+            }
+            deliver(&pool, &notifiers, &config)
+        })
+        .await;
+        if let Err(err) = res {
+            eprintln!("failed to deliver notifications: {}", err);
+        }
+    }
+}
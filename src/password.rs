@@ -0,0 +1,64 @@
+use anyhow::{anyhow, bail};
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params,
+};
+use blake2::{Blake2b, Digest};
+use rand_core::OsRng;
+use std::convert::TryFrom;
+use subtle::ConstantTimeEq;
+
+use crate::error::ApiError;
+
+// Hashes are stored as PHC strings (`$argon2id$v=19$m=...,t=...,p=...$salt$hash`),
+// so the algorithm, parameters and salt travel with the hash itself.
+// Accounts created before that still hold a bare hex Blake2b digest.
+
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| anyhow!(ApiError::PasswordHash))
+}
+
+/// Checks `password` against the stored hash, returns whether the stored hash
+/// should be replaced by a fresh `hash_password` result.
+pub fn verify_password(password: &str, stored: &str) -> anyhow::Result<bool> {
+    if is_legacy_hash(stored) {
+        let hashed = format!("{:x}", Blake2b::digest(password.as_bytes()));
+        if !bool::from(hashed.as_bytes().ct_eq(stored.as_bytes())) {
+            bail!(ApiError::WrongPassword);
+        }
+        return Ok(true);
+    }
+
+    let parsed = PasswordHash::new(stored).map_err(|_| anyhow!(ApiError::WrongPassword))?;
+    if Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_err()
+    {
+        bail!(ApiError::WrongPassword);
+    }
+
+    Ok(is_outdated(&parsed))
+}
+
+fn is_legacy_hash(stored: &str) -> bool {
+    stored.len() == 128 && stored.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_outdated(hash: &PasswordHash) -> bool {
+    if hash.algorithm != Algorithm::Argon2id.ident() {
+        return true;
+    }
+    match Params::try_from(hash) {
+        Ok(params) => {
+            let current = Params::default();
+            params.m_cost() != current.m_cost()
+                || params.t_cost() != current.t_cost()
+                || params.p_cost() != current.p_cost()
+        }
+        Err(_) => true,
+    }
+}
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use actix::Addr;
+use actix_web::web;
+use anyhow::Context;
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    booking::{self, Actor},
+    config::{BookingConfig, PenaltyConfig},
+    error::ApiError,
+    events::Broker,
+    models::appointments::AppointStatus,
+    notify, DbPool,
+};
+
+pub struct Penalty {
+    /// No-shows in the window since the penalty was last cleared.
+    pub no_shows: i64,
+    /// Set while the user can't book, the time the oldest counted no-show leaves the window.
+    pub blocked_until: Option<NaiveDateTime>,
+}
+
+pub fn penalty(
+    conn: &MysqlConnection,
+    username: &str,
+    config: &PenaltyConfig,
+) -> anyhow::Result<Penalty> {
+    use crate::schema::{appointments, times, users};
+
+    let window = chrono::Duration::days(config.window_days);
+    let window_start = Utc::now().naive_utc() - window;
+    let cleared_at = users::table
+        .filter(users::username.eq(username))
+        .select(users::penalty_cleared_at)
+        .get_result::<Option<NaiveDateTime>>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::UserNotFound)?;
+    let since = cleared_at.map_or(window_start, |cleared_at| cleared_at.max(window_start));
+
+    let no_shows = appointments::table
+        .inner_join(times::table.on(appointments::tid.eq(times::tid)))
+        .filter(appointments::username.eq(username))
+        .filter(appointments::status.eq(AppointStatus::NoShow.as_str()))
+        .filter(times::start_time.gt(since))
+        .order(times::start_time.asc())
+        .select(times::start_time)
+        .get_results::<NaiveDateTime>(conn)
+        .context(ApiError::Database)?;
+
+    let count = no_shows.len() as i64;
+    let blocked_until = if count >= config.threshold {
+        Some(no_shows[(count - config.threshold) as usize] + window)
+    } else {
+        None
+    };
+
+    Ok(Penalty {
+        no_shows: count,
+        blocked_until,
+    })
+}
+
+/// Forgives the no-shows so far, they stay recorded on the appointments.
+pub fn clear(conn: &MysqlConnection, username: &str) -> anyhow::Result<()> {
+    use crate::schema::users;
+
+    diesel::update(users::table.filter(users::username.eq(username)))
+        .set(users::penalty_cleared_at.eq(Utc::now().naive_utc()))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// Closes the appointments of past slots: unfinished ones become no-shows, and the ones of
+/// patients who came but were never finished by the doctor become finished, so they don't
+/// count as active any more.
+fn mark_no_shows(
+    pool: &DbPool,
+    broker: &Addr<Broker>,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<()> {
+    use crate::schema::{appointments, times};
+
+    let conn = pool.get().context(ApiError::Database)?;
+    let now = Utc::now().naive_utc();
+    let started_before = now - chrono::Duration::minutes(config.no_show_after_mins);
+    let open = [
+        AppointStatus::Unfinished.as_str(),
+        AppointStatus::CheckedIn.as_str(),
+        AppointStatus::InProgress.as_str(),
+    ];
+    let due = appointments::table
+        .inner_join(times::table.on(appointments::tid.eq(times::tid)))
+        .filter(appointments::status.eq_any(&open[..]))
+        .filter(times::end_time.lt(now))
+        .filter(times::start_time.le(started_before))
+        .select((
+            appointments::username,
+            appointments::tid,
+            appointments::status,
+        ))
+        .get_results::<(String, u64, String)>(&conn)
+        .context(ApiError::Database)?;
+
+    for (username, tid, status) in due {
+        let to = match AppointStatus::parse(&status) {
+            Ok(AppointStatus::Unfinished) => AppointStatus::NoShow,
+            _ => AppointStatus::Finished,
+        };
+        let res = notify::transaction(&conn, broker, || {
+            booking::transition(
+                &conn,
+                &username,
+                tid,
+                to,
+                Actor::System,
+                config,
+                penalty_config,
+            )
+        });
+        // the doctor may have changed the status in the meantime
+        if let Err(err) = res {
+            eprintln!(
+                "failed to mark the appointment of {} at {} as {}: {}",
+                username,
+                tid,
+                to.as_str(),
+                err
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Periodically closes the appointments of past slots.
+pub async fn run_no_show_marker(
+    pool: DbPool,
+    broker: Addr<Broker>,
+    booking: BookingConfig,
+    config: PenaltyConfig,
+) {
+    let mut interval =
+        actix_rt::time::interval(Duration::from_secs(config.mark_interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let broker = broker.clone();
+        let booking = booking.clone();
+        let config = config.clone();
+        if let Err(err) = web::block(move || mark_no_shows(&pool, &broker, &booking, &config)).await
+        {
+            eprintln!("failed to mark no-shows: {}", err);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Default, Serialize)]
 pub struct SimpleResponse {
@@ -15,23 +15,144 @@ impl SimpleResponse {
     }
 }
 
-#[macro_export]
-macro_rules! impl_err_response {
-    ( $( $type:ty),+ $(,)? ) => {
-        $(
-            impl $type {
-                pub fn err<S: ToString>(err: S) -> Self {
-                    Self {
-                        success: false,
-                        err: err.to_string(),
-                        ..Default::default()
-                    }
-                }
-            }
-        )+
-    };
-}
-
-impl_err_response! {
-    SimpleResponse,
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub success: bool,
+    pub err: String,
+    pub code: &'static str,
+}
+
+#[derive(Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct RefreshResponse {
+    pub success: bool,
+    pub err: String,
+    pub login_token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct AddScheduleResponse {
+    pub success: bool,
+    pub err: String,
+    pub sid: u64,
+    /// Number of time slots generated from the template.
+    pub generated: usize,
+}
+
+#[derive(Default, Serialize)]
+pub struct ModifyScheduleResponse {
+    pub success: bool,
+    pub err: String,
+    pub generated: usize,
+}
+
+#[derive(Default, Serialize)]
+pub struct ScheduleItem {
+    pub sid: u64,
+    pub did: String,
+    pub weekday: i32,
+    pub start_time: String,
+    pub end_time: String,
+    pub capacity: i32,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    pub exceptions: Vec<String>,
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchScheduleResponse {
+    pub success: bool,
+    pub err: String,
+    pub schedules: Vec<ScheduleItem>,
+}
+
+#[derive(Default, Serialize)]
+pub struct AddLeaveResponse {
+    pub success: bool,
+    pub err: String,
+    pub lid: u64,
+    /// Number of time slots made unavailable.
+    pub slots: usize,
+    /// Number of appointments canceled by the clinic.
+    pub canceled: usize,
+}
+
+#[derive(Default, Serialize)]
+pub struct LeaveItem {
+    pub lid: u64,
+    pub did: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub reason: String,
+    pub actor_kind: String,
+    pub actor_id: Option<String>,
+    pub time: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchLeaveResponse {
+    pub success: bool,
+    pub err: String,
+    pub leaves: Vec<LeaveItem>,
+}
+
+/// A department with its sub-departments.
+#[derive(Default, Serialize)]
+pub struct DepartItem {
+    pub dpid: u64,
+    pub name: String,
+    pub info: String,
+    pub parent: Option<u64>,
+    pub retired: bool,
+    pub children: Vec<DepartItem>,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchDepartResponse {
+    pub success: bool,
+    pub err: String,
+    pub departments: Vec<DepartItem>,
+}
+
+/// A campus of the hospital.
+#[derive(Default, Serialize)]
+pub struct LocationItem {
+    pub lcid: u64,
+    pub name: String,
+    pub address: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchLocationResponse {
+    pub success: bool,
+    pub err: String,
+    pub locations: Vec<LocationItem>,
+}
+
+/// The reply of the doctor, shown under the comment it answers.
+#[derive(Default, Serialize)]
+pub struct ReplyItem {
+    pub did: String,
+    pub reply: String,
+    pub time: String,
+    pub modified_time: Option<String>,
+}
+
+/// What a waiting-room display shows of one doctor.
+#[derive(Default, Serialize)]
+pub struct QueueItem {
+    pub tid: u64,
+    pub did: String,
+    pub doctor_name: String,
+    pub department: String,
+    pub serving_number: Option<i32>,
+    pub called_at: Option<String>,
+    pub waiting: i64,
 }
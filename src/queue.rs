@@ -0,0 +1,240 @@
+use actix::Addr;
+use anyhow::{bail, Context};
+use chrono::{NaiveDateTime, NaiveTime, Utc};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    booking, depart,
+    error::ApiError,
+    events::{self, Broker},
+    models::{appointments::AppointStatus, times::TimeData},
+    protocol::QueueItem,
+    utils::format_time_str,
+};
+
+// The waiting-room queue of a slot. Patients are called in the order of their queue
+// numbers. These run on the connection of the caller's transaction.
+
+/// Appointments whose patient can still be called in.
+const WAITING: [AppointStatus; 2] = [AppointStatus::Unfinished, AppointStatus::CheckedIn];
+
+pub struct Serving {
+    pub tid: u64,
+    pub did: String,
+    pub doctor_name: String,
+    pub department: String,
+    pub serving_number: Option<i32>,
+    /// The patient holding `serving_number`.
+    pub username: Option<String>,
+    pub called_at: Option<NaiveDateTime>,
+    /// Patients that haven't been called yet.
+    pub waiting: i64,
+}
+
+impl From<&Serving> for QueueItem {
+    fn from(serving: &Serving) -> Self {
+        QueueItem {
+            tid: serving.tid,
+            did: serving.did.clone(),
+            doctor_name: serving.doctor_name.clone(),
+            department: serving.department.clone(),
+            serving_number: serving.serving_number,
+            called_at: serving.called_at.as_ref().map(format_time_str),
+            waiting: serving.waiting,
+        }
+    }
+}
+
+fn waiting() -> Vec<&'static str> {
+    WAITING.iter().map(|status| status.as_str()).collect()
+}
+
+pub fn serving(conn: &MysqlConnection, tid: u64) -> anyhow::Result<Serving> {
+    use crate::schema::{appointments, doctors, times};
+
+    let (time_data, (doctor_name, main)) = times::table
+        .inner_join(doctors::table.on(times::did.eq(doctors::did)))
+        .filter(times::tid.eq(tid))
+        .select((times::all_columns, (doctors::name, doctors::dpid)))
+        .get_result::<(TimeData, (String, u64))>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::TimeNotFound)?;
+    // the department the slot is held for, the doctor's main one for older slots
+    let department = depart::name_of(conn, time_data.dpid.unwrap_or(main))?;
+
+    let username = match time_data.serving_number {
+        Some(number) => appointments::table
+            .filter(appointments::tid.eq(tid))
+            .filter(appointments::queue_number.eq(number))
+            .select(appointments::username)
+            .get_result::<String>(conn)
+            .optional()
+            .context(ApiError::Database)?,
+        None => None,
+    };
+    let waiting = appointments::table
+        .filter(appointments::tid.eq(tid))
+        .filter(appointments::status.eq_any(waiting()))
+        .filter(appointments::called_at.is_null())
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+
+    Ok(Serving {
+        tid,
+        did: time_data.did,
+        doctor_name,
+        department,
+        serving_number: time_data.serving_number,
+        username,
+        called_at: time_data.called_at,
+        waiting,
+    })
+}
+
+/// Calls `number` in, or clears the display for `None`.
+fn call(conn: &MysqlConnection, tid: u64, number: Option<i32>) -> anyhow::Result<Serving> {
+    use crate::schema::{appointments, times};
+
+    let now = Utc::now().naive_utc();
+    if let Some(number) = number {
+        diesel::update(
+            appointments::table
+                .filter(appointments::tid.eq(tid))
+                .filter(appointments::queue_number.eq(number)),
+        )
+        .set((
+            appointments::called_at.eq(now),
+            appointments::skipped.eq(false),
+        ))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    }
+    diesel::update(times::table.filter(times::tid.eq(tid)))
+        .set((times::serving_number.eq(number), times::called_at.eq(now)))
+        .execute(conn)
+        .context(ApiError::Database)?;
+
+    serving(conn, tid)
+}
+
+fn next_number(conn: &MysqlConnection, tid: u64) -> anyhow::Result<Option<i32>> {
+    use crate::schema::appointments;
+
+    appointments::table
+        .filter(appointments::tid.eq(tid))
+        .filter(appointments::status.eq_any(waiting()))
+        .filter(appointments::called_at.is_null())
+        .order(appointments::queue_number.asc())
+        .select(appointments::queue_number)
+        .first::<i32>(conn)
+        .optional()
+        .context(ApiError::Database)
+}
+
+/// Calls in the waiting patient with the smallest queue number.
+pub fn call_next(conn: &MysqlConnection, tid: u64) -> anyhow::Result<Serving> {
+    booking::lock_time(conn, tid)?;
+    match next_number(conn, tid)? {
+        Some(number) => call(conn, tid, Some(number)),
+        None => bail!(ApiError::QueueEmpty),
+    }
+}
+
+/// Sets the patient being called aside and calls in the next one, if any.
+pub fn skip(conn: &MysqlConnection, tid: u64) -> anyhow::Result<Serving> {
+    use crate::schema::appointments;
+
+    let time_data = booking::lock_time(conn, tid)?;
+    let number = time_data
+        .serving_number
+        .context(ApiError::QueueNotServing)?;
+    diesel::update(
+        appointments::table
+            .filter(appointments::tid.eq(tid))
+            .filter(appointments::queue_number.eq(number)),
+    )
+    .set(appointments::skipped.eq(true))
+    .execute(conn)
+    .context(ApiError::Database)?;
+
+    let next = next_number(conn, tid)?;
+    call(conn, tid, next)
+}
+
+/// Calls a patient in again, the one being called for `None`.
+pub fn recall(conn: &MysqlConnection, tid: u64, number: Option<i32>) -> anyhow::Result<Serving> {
+    use crate::schema::appointments;
+
+    let time_data = booking::lock_time(conn, tid)?;
+    let number = match number {
+        Some(number) => number,
+        None => time_data
+            .serving_number
+            .context(ApiError::QueueNotServing)?,
+    };
+    let called_at = appointments::table
+        .filter(appointments::tid.eq(tid))
+        .filter(appointments::queue_number.eq(number))
+        .select(appointments::called_at)
+        .get_result::<Option<NaiveDateTime>>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::QueueNumberNotFound)?;
+    if called_at.is_none() {
+        bail!(ApiError::QueueNotCalled);
+    }
+
+    call(conn, tid, Some(number))
+}
+
+/// The slot each doctor called a patient from last today.
+pub fn today(
+    conn: &MysqlConnection,
+    did: Option<String>,
+    department: Option<String>,
+) -> anyhow::Result<Vec<Serving>> {
+    use crate::schema::times;
+
+    let today = Utc::now().naive_utc().date().and_time(NaiveTime::MIN);
+    let mut query = times::table
+        .filter(times::called_at.ge(today))
+        .filter(times::called_at.lt(today + chrono::Duration::days(1)))
+        .order((times::did.asc(), times::called_at.desc()))
+        .select((times::tid, times::did))
+        .into_boxed();
+    if let Some(did) = did {
+        query = query.filter(times::did.eq(did));
+    }
+    if let Some(department) = department {
+        query = query.filter(times::dpid.eq(depart::id_of(conn, &department)?));
+    }
+    let mut slots = query
+        .get_results::<(u64, String)>(conn)
+        .context(ApiError::Database)?;
+    slots.dedup_by(|a, b| a.1 == b.1);
+
+    slots
+        .into_iter()
+        .map(|(tid, _)| serving(conn, tid))
+        .collect()
+}
+
+pub fn topics(did: &str, department: &str) -> Vec<String> {
+    vec![
+        "queue".to_string(),
+        format!("queue/doctor/{}", did),
+        format!("queue/depart/{}", department),
+    ]
+}
+
+/// Pushes the change to the waiting-room displays.
+pub fn publish(broker: &Addr<Broker>, serving: &Serving) {
+    events::publish(
+        broker,
+        topics(&serving.did, &serving.department),
+        "queue",
+        &QueueItem::from(serving),
+    );
+}
@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use chrono::Utc;
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    booking::Actor,
+    config::ModerationConfig,
+    depart,
+    error::ApiError,
+    models::{
+        appointments::AppointStatus,
+        comments::{
+            Comment, CommentReply, CommentReport, CommentStatus, DoctorRating, NewComment,
+            NewCommentReply, NewCommentReport,
+        },
+    },
+    protocol::ReplyItem,
+};
+
+// Reviews are comments with a rating, one per finished appointment. The ratings of the
+// visible reviews of each doctor are summed up in `doctor_ratings` whenever a review is
+// posted or changes its status.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoctorSort {
+    Name,
+    /// Best rated first, doctors without reviews last.
+    Rating,
+}
+
+impl DoctorSort {
+    /// Accepts `name` or `rating`, sorting by name when unset.
+    pub fn parse(s: Option<&str>) -> anyhow::Result<Self> {
+        match s {
+            None | Some("name") => Ok(DoctorSort::Name),
+            Some("rating") => Ok(DoctorSort::Rating),
+            Some(_) => bail!(ApiError::SortFormat),
+        }
+    }
+}
+
+/// The average rating of a doctor, `None` until the first review.
+pub fn average(rating: Option<(i32, i32)>) -> Option<f64> {
+    match rating {
+        Some((total, count)) if count > 0 => Some(total as f64 / count as f64),
+        _ => None,
+    }
+}
+
+/// Posts the review of `username` on the finished appointment at `tid` with doctor `did`.
+/// Returns whether it is shown right away or waits for a moderator.
+pub fn post(
+    conn: &MysqlConnection,
+    username: &str,
+    did: &str,
+    tid: u64,
+    rating: i32,
+    comment: String,
+    config: &ModerationConfig,
+) -> anyhow::Result<CommentStatus> {
+    use crate::schema::{appointments, comments, times};
+
+    if !(1..=5).contains(&rating) {
+        bail!(ApiError::RatingFormat);
+    }
+
+    let finished = appointments::table
+        .inner_join(times::table.on(appointments::tid.eq(times::tid)))
+        .filter(appointments::username.eq(username))
+        .filter(appointments::tid.eq(tid))
+        .filter(times::did.eq(did))
+        .filter(appointments::status.eq(AppointStatus::Finished.as_str()))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    if finished == 0 {
+        bail!(ApiError::CommentNotAllowed);
+    }
+
+    let reviewed = comments::table
+        .filter(comments::username.eq(username))
+        .filter(comments::tid.eq(tid))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    if reviewed > 0 {
+        bail!(ApiError::CommentExists);
+    }
+
+    let status = if config.holds(&comment) {
+        CommentStatus::Pending
+    } else {
+        CommentStatus::Visible
+    };
+    let data = NewComment {
+        username: username.to_string(),
+        did: did.to_string(),
+        comment,
+        tid: Some(tid),
+        rating: Some(rating),
+        status: status.as_str().to_string(),
+    };
+    diesel::insert_into(comments::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    refresh(conn, did)?;
+    Ok(status)
+}
+
+/// The departments of the doctor the comment is about, for the permission check of admins.
+pub fn comment_departs(conn: &MysqlConnection, cid: u64) -> anyhow::Result<Vec<String>> {
+    depart::names_of(conn, &find(conn, cid)?.did)
+}
+
+fn find(conn: &MysqlConnection, cid: u64) -> anyhow::Result<Comment> {
+    use crate::schema::comments;
+
+    comments::table
+        .filter(comments::cid.eq(cid))
+        .get_result::<Comment>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::CommentNotFound)
+}
+
+/// Sets the status of the comment and closes its reports.
+pub fn set_status(
+    conn: &MysqlConnection,
+    cid: u64,
+    status: CommentStatus,
+    actor: Actor,
+) -> anyhow::Result<()> {
+    use crate::schema::{comment_reports, comments};
+
+    let comment = find(conn, cid)?;
+    diesel::update(comments::table.filter(comments::cid.eq(cid)))
+        .set((
+            comments::status.eq(status.as_str()),
+            comments::status_actor_kind.eq(actor.kind()),
+            comments::status_actor_id.eq(actor.id()),
+            comments::status_time.eq(Utc::now().naive_utc()),
+        ))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::update(comment_reports::table.filter(comment_reports::cid.eq(cid)))
+        .set(comment_reports::resolved.eq(true))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    refresh(conn, &comment.did)
+}
+
+/// Deletes the comment, keeping its text, and takes its rating out of the doctor's average.
+pub fn delete(conn: &MysqlConnection, cid: u64, actor: Actor) -> anyhow::Result<()> {
+    if find(conn, cid)?.status == CommentStatus::Deleted.as_str() {
+        bail!(ApiError::CommentNotFound);
+    }
+    set_status(conn, cid, CommentStatus::Deleted, actor)
+}
+
+/// Reports a visible comment to the moderators, once per user or doctor.
+pub fn report(
+    conn: &MysqlConnection,
+    cid: u64,
+    actor: Actor,
+    reason: String,
+) -> anyhow::Result<()> {
+    use crate::schema::comment_reports;
+
+    if find(conn, cid)?.status != CommentStatus::Visible.as_str() {
+        bail!(ApiError::CommentNotFound);
+    }
+    let reporter_id = actor.id().unwrap_or_default();
+    let reported = comment_reports::table
+        .filter(comment_reports::cid.eq(cid))
+        .filter(comment_reports::reporter_kind.eq(actor.kind()))
+        .filter(comment_reports::reporter_id.eq(&reporter_id))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    if reported > 0 {
+        bail!(ApiError::ReportExists);
+    }
+
+    let data = NewCommentReport {
+        cid,
+        reporter_kind: actor.kind().to_string(),
+        reporter_id,
+        reason,
+    };
+    diesel::insert_into(comment_reports::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// Posts the reply of doctor `did` to a visible comment about them.
+pub fn reply(conn: &MysqlConnection, cid: u64, did: &str, reply: String) -> anyhow::Result<()> {
+    use crate::schema::comment_replies;
+
+    let comment = find(conn, cid)?;
+    if comment.status != CommentStatus::Visible.as_str() {
+        bail!(ApiError::CommentNotFound);
+    }
+    if comment.did != did {
+        bail!(ApiError::Forbidden);
+    }
+    let replied = comment_replies::table
+        .filter(comment_replies::cid.eq(cid))
+        .count()
+        .get_result::<i64>(conn)
+        .context(ApiError::Database)?;
+    if replied > 0 {
+        bail!(ApiError::ReplyExists);
+    }
+
+    let data = NewCommentReply {
+        cid,
+        did: did.to_string(),
+        reply,
+    };
+    diesel::insert_into(comment_replies::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+/// Replaces the text of the reply of doctor `did`.
+pub fn modify_reply(
+    conn: &MysqlConnection,
+    cid: u64,
+    did: &str,
+    reply: String,
+) -> anyhow::Result<()> {
+    use crate::schema::comment_replies;
+
+    let updated = diesel::update(
+        comment_replies::table
+            .filter(comment_replies::cid.eq(cid))
+            .filter(comment_replies::did.eq(did)),
+    )
+    .set((
+        comment_replies::reply.eq(reply),
+        comment_replies::modified_time.eq(Utc::now().naive_utc()),
+    ))
+    .execute(conn)
+    .context(ApiError::Database)?;
+    if updated == 0 {
+        bail!(ApiError::ReplyNotFound);
+    }
+    Ok(())
+}
+
+/// Withdraws the reply of doctor `did`, after which they may reply again.
+pub fn delete_reply(conn: &MysqlConnection, cid: u64, did: &str) -> anyhow::Result<()> {
+    use crate::schema::comment_replies;
+
+    let deleted = diesel::delete(
+        comment_replies::table
+            .filter(comment_replies::cid.eq(cid))
+            .filter(comment_replies::did.eq(did)),
+    )
+    .execute(conn)
+    .context(ApiError::Database)?;
+    if deleted == 0 {
+        bail!(ApiError::ReplyNotFound);
+    }
+    Ok(())
+}
+
+/// The replies to the comments, by `cid`.
+pub fn replies(conn: &MysqlConnection, cids: &[u64]) -> anyhow::Result<HashMap<u64, ReplyItem>> {
+    use crate::schema::comment_replies;
+
+    Ok(comment_replies::table
+        .filter(comment_replies::cid.eq_any(cids))
+        .get_results::<CommentReply>(conn)
+        .context(ApiError::Database)?
+        .into_iter()
+        .map(|data| {
+            let item = ReplyItem {
+                did: data.did,
+                reply: data.reply,
+                time: crate::utils::format_time_str(&data.time),
+                modified_time: data
+                    .modified_time
+                    .as_ref()
+                    .map(crate::utils::format_time_str),
+            };
+            (data.cid, item)
+        })
+        .collect())
+}
+
+/// The comments waiting for a moderator, that is pending ones and ones with open reports,
+/// oldest first, with their open reports. `depart` limits them to one department.
+pub fn queue(
+    conn: &MysqlConnection,
+    depart: Option<&str>,
+    first_index: i64,
+    limit: i64,
+) -> anyhow::Result<Vec<(Comment, Vec<CommentReport>)>> {
+    use crate::schema::{comment_reports, comments, departments, doctor_departments};
+
+    let reported = comment_reports::table
+        .filter(comment_reports::resolved.eq(false))
+        .select(comment_reports::cid);
+    let mut query = comments::table
+        .filter(
+            comments::status
+                .eq(CommentStatus::Pending.as_str())
+                .or(comments::cid.eq_any(reported)),
+        )
+        .select(comments::all_columns)
+        .into_boxed();
+    if let Some(depart) = depart {
+        let members = doctor_departments::table
+            .inner_join(departments::table.on(departments::dpid.eq(doctor_departments::dpid)))
+            .filter(departments::depart_name.eq(depart))
+            .select(doctor_departments::did);
+        query = query.filter(comments::did.eq_any(members));
+    }
+    let cmts = query
+        .order(comments::time.asc())
+        .offset(first_index)
+        .limit(limit)
+        .get_results::<Comment>(conn)
+        .context(ApiError::Database)?;
+
+    cmts.into_iter()
+        .map(|comment| {
+            let reports = comment_reports::table
+                .filter(comment_reports::cid.eq(comment.cid))
+                .filter(comment_reports::resolved.eq(false))
+                .order(comment_reports::time.asc())
+                .select((
+                    comment_reports::rpid,
+                    comment_reports::reporter_kind,
+                    comment_reports::reporter_id,
+                    comment_reports::reason,
+                    comment_reports::time,
+                ))
+                .get_results::<CommentReport>(conn)
+                .context(ApiError::Database)?;
+            Ok((comment, reports))
+        })
+        .collect()
+}
+
+/// Sums up the ratings of the visible reviews of the doctor again. The doctor's row in
+/// `doctor_ratings` is locked first, so concurrent refreshes run one after the other and the
+/// last one sees every committed review.
+fn refresh(conn: &MysqlConnection, did: &str) -> anyhow::Result<()> {
+    use crate::schema::{comments, doctor_ratings};
+
+    diesel::insert_or_ignore_into(doctor_ratings::table)
+        .values(DoctorRating {
+            did: did.to_string(),
+            rating_total: 0,
+            rating_count: 0,
+        })
+        .execute(conn)
+        .context(ApiError::Database)?;
+    doctor_ratings::table
+        .filter(doctor_ratings::did.eq(did))
+        .for_update()
+        .get_result::<DoctorRating>(conn)
+        .context(ApiError::Database)?;
+
+    let ratings = comments::table
+        .filter(comments::did.eq(did))
+        .filter(comments::status.eq(CommentStatus::Visible.as_str()))
+        .filter(comments::rating.is_not_null())
+        .select(comments::rating)
+        .for_update()
+        .get_results::<Option<i32>>(conn)
+        .context(ApiError::Database)?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    diesel::update(doctor_ratings::table.filter(doctor_ratings::did.eq(did)))
+        .set((
+            doctor_ratings::rating_total.eq(ratings.iter().sum::<i32>()),
+            doctor_ratings::rating_count.eq(ratings.len() as i32),
+        ))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn average_rating() {
+        assert_eq!(average(None), None);
+        assert_eq!(average(Some((0, 0))), None);
+        assert_eq!(average(Some((9, 2))), Some(4.5));
+        assert_eq!(average(Some((5, 1))), Some(5.0));
+    }
+
+    #[test]
+    fn parse_sort() {
+        assert!(matches!(DoctorSort::parse(None), Ok(DoctorSort::Name)));
+        assert!(matches!(
+            DoctorSort::parse(Some("name")),
+            Ok(DoctorSort::Name)
+        ));
+        assert!(matches!(
+            DoctorSort::parse(Some("rating")),
+            Ok(DoctorSort::Rating)
+        ));
+        assert!(DoctorSort::parse(Some("Rating")).is_err());
+        assert!(DoctorSort::parse(Some("")).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{bail, Context};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    config::{BookingConfig, PenaltyConfig},
+    error::ApiError,
+    models::appointments::AppointStatus,
+    models::times::TimeData,
+    penalty,
+};
+
+// The booking policies a user has to satisfy to take a seat. They run on the connection of
+// the caller's transaction, after the slot is locked.
+
+/// Appointments that still lie ahead of the patient.
+const ACTIVE: [AppointStatus; 3] = [
+    AppointStatus::Unfinished,
+    AppointStatus::CheckedIn,
+    AppointStatus::InProgress,
+];
+
+struct Booked {
+    tid: u64,
+    status: AppointStatus,
+    start_time: NaiveDateTime,
+    end_time: NaiveDateTime,
+    /// The department the slot is held for.
+    dpid: Option<u64>,
+}
+
+fn booked(conn: &MysqlConnection, username: &str) -> anyhow::Result<Vec<Booked>> {
+    use crate::schema::{appointments, times};
+
+    let holding = AppointStatus::ALL
+        .iter()
+        .filter(|status| status.holds_seat())
+        .map(|status| status.as_str())
+        .collect::<Vec<_>>();
+    let res = appointments::table
+        .inner_join(times::table.on(appointments::tid.eq(times::tid)))
+        .filter(appointments::username.eq(username))
+        .filter(appointments::status.eq_any(holding))
+        .select((
+            appointments::tid,
+            appointments::status,
+            times::start_time,
+            times::end_time,
+            times::dpid,
+        ))
+        .get_results::<(u64, String, NaiveDateTime, NaiveDateTime, Option<u64>)>(conn)
+        .context(ApiError::Database)?;
+
+    res.into_iter()
+        .map(|(tid, status, start_time, end_time, dpid)| {
+            Ok(Booked {
+                tid,
+                status: AppointStatus::parse(&status)?,
+                start_time,
+                end_time,
+                dpid,
+            })
+        })
+        .collect()
+}
+
+/// The first policy booking `time_data` would break. The appointment at `except` is left out,
+/// it is given back by the same request.
+pub fn violation(
+    conn: &MysqlConnection,
+    username: &str,
+    time_data: &TimeData,
+    except: Option<u64>,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<Option<ApiError>> {
+    let now = Utc::now().naive_utc();
+    if !time_data.available {
+        return Ok(Some(ApiError::TimeUnavailable));
+    }
+    if time_data.start_time <= now {
+        return Ok(Some(ApiError::TimeStarted));
+    }
+    if time_data.start_time - chrono::Duration::minutes(config.min_lead_mins) < now {
+        return Ok(Some(ApiError::BookingTooSoon {
+            minutes: config.min_lead_mins,
+        }));
+    }
+    if config.max_advance_days > 0
+        && time_data.start_time > now + chrono::Duration::days(config.max_advance_days)
+    {
+        return Ok(Some(ApiError::BookingTooFarAhead {
+            days: config.max_advance_days,
+        }));
+    }
+    if let Some(until) = penalty::penalty(conn, username, penalty_config)?.blocked_until {
+        return Ok(Some(ApiError::BookingBlocked { until }));
+    }
+
+    let booked = booked(conn, username)?
+        .into_iter()
+        .filter(|booked| Some(booked.tid) != except && booked.tid != time_data.tid)
+        .collect::<Vec<_>>();
+    let active = booked
+        .iter()
+        .filter(|booked| ACTIVE.contains(&booked.status))
+        .collect::<Vec<_>>();
+
+    if !config.allow_overlap
+        && active.iter().any(|booked| {
+            booked.start_time < time_data.end_time && booked.end_time > time_data.start_time
+        })
+    {
+        return Ok(Some(ApiError::AppointOverlap));
+    }
+    if config.max_active_appoints > 0 && active.len() as i64 >= config.max_active_appoints {
+        return Ok(Some(ApiError::ActiveAppointLimit {
+            max: config.max_active_appoints,
+        }));
+    }
+
+    if config.one_per_depart_per_day && time_data.dpid.is_some() {
+        let date = time_data.start_time.date();
+        if booked
+            .iter()
+            .any(|booked| booked.dpid == time_data.dpid && booked.start_time.date() == date)
+        {
+            return Ok(Some(ApiError::DepartDailyLimit));
+        }
+    }
+
+    Ok(None)
+}
+
+pub fn check(
+    conn: &MysqlConnection,
+    username: &str,
+    time_data: &TimeData,
+    except: Option<u64>,
+    config: &BookingConfig,
+    penalty_config: &PenaltyConfig,
+) -> anyhow::Result<()> {
+    if let Some(err) = violation(conn, username, time_data, except, config, penalty_config)? {
+        bail!(err);
+    }
+    Ok(())
+}
@@ -0,0 +1,436 @@
+use std::time::Duration;
+
+use actix_web::web;
+use anyhow::{bail, Context};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use diesel::{prelude::*, MysqlConnection};
+
+use crate::{
+    config::ScheduleConfig,
+    database, depart,
+    error::ApiError,
+    leave, location,
+    models::{
+        schedules::{NewSchedule, Schedule, ScheduleException, UpdateSchedule},
+        times::NewTime,
+    },
+    protocol::ScheduleItem,
+    DbPool,
+};
+
+no_arg_sql_function!(
+    last_insert_id,
+    diesel::sql_types::Unsigned<diesel::sql_types::Bigint>
+);
+
+/// A weekly template as sent by a doctor or an administrator, already parsed.
+pub struct ScheduleInput {
+    pub weekday: i32,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub capacity: i32,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    pub exceptions: Vec<NaiveDate>,
+    /// The department of the slots, the doctor's main one when unset.
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
+}
+
+impl ScheduleInput {
+    /// Times are `HH:MM[:SS]` in UTC, dates are `YYYY-MM-DD`.
+    pub fn parse(
+        weekday: i32,
+        start_time: &str,
+        end_time: &str,
+        capacity: i32,
+        start_date: &str,
+        end_date: Option<&str>,
+        exceptions: &[String],
+    ) -> anyhow::Result<Self> {
+        if !(1..=7).contains(&weekday) {
+            bail!(ApiError::WeekdayFormat);
+        }
+        let start_time = parse_clock_str(start_time).context(ApiError::StartTimeFormat)?;
+        let end_time = parse_clock_str(end_time).context(ApiError::EndTimeFormat)?;
+        if start_time >= end_time {
+            bail!(ApiError::InvalidTimeRange);
+        }
+        let start_date = parse_date_str(start_date)?;
+        let end_date = end_date.map(parse_date_str).transpose()?;
+        if matches!(end_date, Some(end_date) if end_date < start_date) {
+            bail!(ApiError::InvalidDateRange);
+        }
+        let exceptions = exceptions
+            .iter()
+            .map(parse_date_str)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            weekday,
+            start_time,
+            end_time,
+            capacity,
+            start_date,
+            end_date,
+            exceptions,
+            dpid: None,
+            lcid: None,
+        })
+    }
+
+    /// Sets the department and campus the slots take place at.
+    pub fn at(self, dpid: Option<u64>, lcid: Option<u64>) -> Self {
+        Self { dpid, lcid, ..self }
+    }
+}
+
+fn parse_clock_str(s: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+        .context(ApiError::TimeFormat)
+}
+
+fn parse_date_str<S: AsRef<str>>(s: S) -> anyhow::Result<NaiveDate> {
+    NaiveDate::parse_from_str(s.as_ref(), "%Y-%m-%d").context(ApiError::DateFormat)
+}
+
+fn schedule_doctor(conn: &MysqlConnection, sid: u64) -> anyhow::Result<String> {
+    use crate::schema::schedules;
+
+    schedules::table
+        .filter(schedules::sid.eq(sid))
+        .select(schedules::did)
+        .get_result::<String>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::ScheduleNotFound)
+}
+
+/// The department of the template, for the permission check of admins.
+pub fn schedule_depart(conn: &MysqlConnection, sid: u64) -> anyhow::Result<String> {
+    use crate::schema::schedules;
+
+    let (did, dpid) = schedules::table
+        .filter(schedules::sid.eq(sid))
+        .select((schedules::did, schedules::dpid))
+        .get_result::<(String, Option<u64>)>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::ScheduleNotFound)?;
+    let dpid = match dpid {
+        Some(dpid) => dpid,
+        None => depart::main_of(conn, &did)?,
+    };
+    depart::name_of(conn, dpid)
+}
+
+/// The department the slot is held for, for the permission check of admins.
+pub fn time_depart(conn: &MysqlConnection, tid: u64) -> anyhow::Result<String> {
+    use crate::schema::times;
+
+    let (did, dpid) = times::table
+        .filter(times::tid.eq(tid))
+        .select((times::did, times::dpid))
+        .get_result::<(String, Option<u64>)>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::TimeNotFound)?;
+    let dpid = match dpid {
+        Some(dpid) => dpid,
+        None => depart::main_of(conn, &did)?,
+    };
+    depart::name_of(conn, dpid)
+}
+
+/// Returns the new `sid` and the number of generated slots.
+pub fn create(
+    conn: &MysqlConnection,
+    did: String,
+    input: ScheduleInput,
+    config: &ScheduleConfig,
+) -> anyhow::Result<(u64, usize)> {
+    use crate::schema::schedules;
+
+    let dpid = depart::slot_depart(conn, &did, input.dpid)?;
+    location::assert_location(conn, input.lcid)?;
+    let data = NewSchedule {
+        did,
+        weekday: input.weekday,
+        start_time: input.start_time,
+        end_time: input.end_time,
+        capacity: input.capacity,
+        start_date: input.start_date,
+        end_date: input.end_date,
+        dpid: Some(dpid),
+        lcid: input.lcid,
+    };
+    diesel::insert_into(schedules::table)
+        .values(data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+    let sid = diesel::select(last_insert_id)
+        .get_result::<u64>(conn)
+        .context(ApiError::Database)?;
+
+    replace_exceptions(conn, sid, input.exceptions)?;
+    let generated = regenerate(conn, sid, config)?;
+    Ok((sid, generated))
+}
+
+/// Replaces the template and regenerates its future slots. Slots that already have
+/// appointments are kept as they are. Returns the number of generated slots.
+pub fn update(
+    conn: &MysqlConnection,
+    sid: u64,
+    input: ScheduleInput,
+    config: &ScheduleConfig,
+) -> anyhow::Result<usize> {
+    use crate::schema::schedules;
+
+    let did = schedule_doctor(conn, sid)?;
+    let dpid = depart::slot_depart(conn, &did, input.dpid)?;
+    location::assert_location(conn, input.lcid)?;
+    let data = UpdateSchedule {
+        weekday: input.weekday,
+        start_time: input.start_time,
+        end_time: input.end_time,
+        capacity: input.capacity,
+        start_date: input.start_date,
+        end_date: input.end_date,
+        dpid: Some(dpid),
+        lcid: input.lcid,
+    };
+    diesel::update(schedules::table.filter(schedules::sid.eq(sid)))
+        .set(&data)
+        .execute(conn)
+        .context(ApiError::Database)?;
+
+    replace_exceptions(conn, sid, input.exceptions)?;
+    regenerate(conn, sid, config)
+}
+
+/// Deletes the template with its future unbooked slots. Booked slots become ordinary slots.
+pub fn delete(conn: &MysqlConnection, sid: u64) -> anyhow::Result<()> {
+    use crate::schema::{schedule_exceptions, schedules, times};
+
+    delete_future_slots(conn, sid)?;
+    diesel::update(times::table.filter(times::sid.eq(sid)))
+        .set(times::sid.eq(None::<u64>))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::delete(schedule_exceptions::table.filter(schedule_exceptions::sid.eq(sid)))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::delete(schedules::table.filter(schedules::sid.eq(sid)))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+pub fn search(conn: &MysqlConnection, did: &str) -> anyhow::Result<Vec<ScheduleItem>> {
+    use crate::schema::{schedule_exceptions, schedules};
+
+    let schedules = schedules::table
+        .filter(schedules::did.eq(did))
+        .order(schedules::weekday.asc())
+        .then_order_by(schedules::start_time.asc())
+        .get_results::<Schedule>(conn)
+        .context(ApiError::Database)?;
+
+    schedules
+        .into_iter()
+        .map(|data| {
+            let exceptions = schedule_exceptions::table
+                .filter(schedule_exceptions::sid.eq(data.sid))
+                .order(schedule_exceptions::date.asc())
+                .select(schedule_exceptions::date)
+                .get_results::<NaiveDate>(conn)
+                .context(ApiError::Database)?;
+            Ok(ScheduleItem {
+                sid: data.sid,
+                did: data.did,
+                weekday: data.weekday,
+                start_time: data.start_time.format("%H:%M:%S").to_string(),
+                end_time: data.end_time.format("%H:%M:%S").to_string(),
+                capacity: data.capacity,
+                start_date: data.start_date.format("%Y-%m-%d").to_string(),
+                end_date: data
+                    .end_date
+                    .map(|date| date.format("%Y-%m-%d").to_string()),
+                exceptions: exceptions
+                    .iter()
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+                    .collect(),
+                dpid: data.dpid,
+                lcid: data.lcid,
+            })
+        })
+        .collect()
+}
+
+/// Keeps the template from generating the slot of `date` again, after the doctor
+/// deleted it.
+pub fn skip_date(conn: &MysqlConnection, sid: u64, date: NaiveDate) -> anyhow::Result<()> {
+    use crate::schema::schedule_exceptions;
+
+    diesel::insert_or_ignore_into(schedule_exceptions::table)
+        .values(ScheduleException { sid, date })
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+fn replace_exceptions(
+    conn: &MysqlConnection,
+    sid: u64,
+    mut dates: Vec<NaiveDate>,
+) -> anyhow::Result<()> {
+    use crate::schema::schedule_exceptions;
+
+    diesel::delete(schedule_exceptions::table.filter(schedule_exceptions::sid.eq(sid)))
+        .execute(conn)
+        .context(ApiError::Database)?;
+
+    dates.sort();
+    dates.dedup();
+    let data = dates
+        .into_iter()
+        .map(|date| ScheduleException { sid, date })
+        .collect::<Vec<_>>();
+    if !data.is_empty() {
+        diesel::insert_into(schedule_exceptions::table)
+            .values(&data)
+            .execute(conn)
+            .context(ApiError::Database)?;
+    }
+    Ok(())
+}
+
+fn delete_future_slots(conn: &MysqlConnection, sid: u64) -> anyhow::Result<()> {
+    use crate::schema::{times, waitlists};
+
+    let now = Utc::now().naive_utc();
+    let tids = times::table
+        .filter(times::sid.eq(sid))
+        .filter(times::appointed.eq(0))
+        .filter(times::start_time.gt(now))
+        .select(times::tid)
+        .get_results::<u64>(conn)
+        .context(ApiError::Database)?;
+    diesel::delete(waitlists::table.filter(waitlists::tid.eq_any(&tids)))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    diesel::delete(times::table.filter(times::tid.eq_any(&tids)))
+        .execute(conn)
+        .context(ApiError::Database)?;
+    Ok(())
+}
+
+fn regenerate(conn: &MysqlConnection, sid: u64, config: &ScheduleConfig) -> anyhow::Result<usize> {
+    delete_future_slots(conn, sid)?;
+    generate(conn, sid, config)
+}
+
+/// Inserts the missing slots of the template up to the horizon. Dates that already have a
+/// slot of the template (possibly moved by the doctor), or where the slot would overlap
+/// another one, are skipped.
+fn generate(conn: &MysqlConnection, sid: u64, config: &ScheduleConfig) -> anyhow::Result<usize> {
+    use crate::schema::{schedule_exceptions, schedules, times};
+
+    let schedule = schedules::table
+        .filter(schedules::sid.eq(sid))
+        .get_result::<Schedule>(conn)
+        .optional()
+        .context(ApiError::Database)?
+        .context(ApiError::ScheduleNotFound)?;
+    let exceptions = schedule_exceptions::table
+        .filter(schedule_exceptions::sid.eq(sid))
+        .select(schedule_exceptions::date)
+        .get_results::<NaiveDate>(conn)
+        .context(ApiError::Database)?;
+
+    let now = Utc::now().naive_utc();
+    let existing = times::table
+        .filter(times::sid.eq(sid))
+        .filter(times::start_time.gt(now))
+        .select(times::start_time)
+        .get_results::<NaiveDateTime>(conn)
+        .context(ApiError::Database)?
+        .into_iter()
+        .map(|time| time.date())
+        .collect::<Vec<_>>();
+
+    let first_date = schedule.start_date.max(now.date());
+    let mut last_date = now.date() + chrono::Duration::days(config.horizon_days);
+    if let Some(end_date) = schedule.end_date {
+        last_date = last_date.min(end_date);
+    }
+
+    let mut generated = 0;
+    let mut date = first_date;
+    while date <= last_date {
+        let start_time = date.and_time(schedule.start_time);
+        let end_time = date.and_time(schedule.end_time);
+        if date.weekday().number_from_monday() as i32 == schedule.weekday
+            && !exceptions.contains(&date)
+            && start_time > now
+            && !existing.contains(&date)
+            && !database::times::has_overlap(conn, &schedule.did, &start_time, &end_time, None)?
+            && !leave::on_leave(conn, &schedule.did, &start_time, &end_time)?
+        {
+            let data = NewTime {
+                did: schedule.did.clone(),
+                start_time,
+                end_time,
+                capacity: schedule.capacity,
+                sid: Some(sid),
+                dpid: schedule.dpid,
+                lcid: schedule.lcid,
+            };
+            diesel::insert_into(times::table)
+                .values(data)
+                .execute(conn)
+                .context(ApiError::Database)?;
+            generated += 1;
+        }
+        date += chrono::Duration::days(1);
+    }
+
+    Ok(generated)
+}
+
+fn generate_all(pool: &DbPool, config: &ScheduleConfig) -> anyhow::Result<()> {
+    use crate::schema::schedules;
+
+    let conn = pool.get().context(ApiError::Database)?;
+    let today = Utc::now().naive_utc().date();
+    let sids = schedules::table
+        .filter(
+            schedules::end_date
+                .is_null()
+                .or(schedules::end_date.ge(today)),
+        )
+        .select(schedules::sid)
+        .get_results::<u64>(&conn)
+        .context(ApiError::Database)?;
+
+    for sid in sids {
+        conn.transaction(|| generate(&conn, sid, config))?;
+    }
+    Ok(())
+}
+
+/// Periodically rolls the generated slots of every template forward to the horizon.
+pub async fn run_schedule_generator(pool: DbPool, config: ScheduleConfig) {
+    let mut interval =
+        actix_rt::time::interval(Duration::from_secs(config.generate_interval_secs.max(1)));
+    loop {
+        interval.tick().await;
+        let pool = pool.clone();
+        let config = config.clone();
+        if let Err(err) = web::block(move || generate_all(&pool, &config)).await {
+            eprintln!("failed to generate scheduled time slots: {}", err);
+        }
+    }
+}
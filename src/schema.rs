@@ -3,13 +3,30 @@ table! {
         token -> Char,
         aid -> Char,
         login_time -> Datetime,
+        last_active -> Datetime,
+        refresh_token -> Char,
     }
 }
 
 table! {
     administrators (aid) {
         aid -> Char,
-        password -> Char,
+        password -> Varchar,
+        role -> Char,
+        dpid -> Nullable<Unsigned<Bigint>>,
+    }
+}
+
+table! {
+    appointment_events (eid) {
+        eid -> Unsigned<Bigint>,
+        username -> Char,
+        tid -> Unsigned<Bigint>,
+        from_status -> Nullable<Char>,
+        to_status -> Char,
+        actor_kind -> Char,
+        actor_id -> Nullable<Char>,
+        time -> Datetime,
     }
 }
 
@@ -19,6 +36,31 @@ table! {
         tid -> Unsigned<Bigint>,
         status -> Char,
         time -> Datetime,
+        queue_number -> Integer,
+        called_at -> Nullable<Datetime>,
+        skipped -> Bool,
+    }
+}
+
+table! {
+    comment_replies (cid) {
+        cid -> Unsigned<Bigint>,
+        did -> Char,
+        reply -> Varchar,
+        time -> Datetime,
+        modified_time -> Nullable<Datetime>,
+    }
+}
+
+table! {
+    comment_reports (rpid) {
+        rpid -> Unsigned<Bigint>,
+        cid -> Unsigned<Bigint>,
+        reporter_kind -> Char,
+        reporter_id -> Char,
+        reason -> Varchar,
+        time -> Datetime,
+        resolved -> Bool,
     }
 }
 
@@ -29,13 +71,42 @@ table! {
         did -> Char,
         comment -> Varchar,
         time -> Datetime,
+        tid -> Nullable<Unsigned<Bigint>>,
+        rating -> Nullable<Integer>,
+        status -> Char,
+        status_actor_kind -> Nullable<Char>,
+        status_actor_id -> Nullable<Char>,
+        status_time -> Nullable<Datetime>,
     }
 }
 
 table! {
-    departments (depart_name) {
+    departments (dpid) {
+        dpid -> Unsigned<Bigint>,
         depart_name -> Char,
         information -> Varchar,
+        parent -> Nullable<Unsigned<Bigint>>,
+        retired -> Bool,
+    }
+}
+
+table! {
+    doctor_departments (did, dpid) {
+        did -> Char,
+        dpid -> Unsigned<Bigint>,
+    }
+}
+
+table! {
+    doctor_leaves (lid) {
+        lid -> Unsigned<Bigint>,
+        did -> Char,
+        start_time -> Datetime,
+        end_time -> Datetime,
+        reason -> Varchar,
+        actor_kind -> Char,
+        actor_id -> Nullable<Char>,
+        time -> Datetime,
     }
 }
 
@@ -44,6 +115,16 @@ table! {
         token -> Char,
         did -> Char,
         login_time -> Datetime,
+        last_active -> Datetime,
+        refresh_token -> Char,
+    }
+}
+
+table! {
+    doctor_ratings (did) {
+        did -> Char,
+        rating_total -> Integer,
+        rating_count -> Integer,
     }
 }
 
@@ -51,15 +132,84 @@ table! {
     doctors (did) {
         did -> Char,
         name -> Char,
-        password -> Char,
+        password -> Varchar,
         gender -> Char,
         birthday -> Nullable<Date>,
-        department -> Char,
+        dpid -> Unsigned<Bigint>,
         rankk -> Char,
         information -> Varchar,
     }
 }
 
+table! {
+    locations (lcid) {
+        lcid -> Unsigned<Bigint>,
+        name -> Char,
+        address -> Varchar,
+    }
+}
+
+table! {
+    notification_outbox (nid) {
+        nid -> Unsigned<Bigint>,
+        username -> Char,
+        tid -> Nullable<Unsigned<Bigint>>,
+        kind -> Char,
+        channel -> Char,
+        recipient -> Varchar,
+        subject -> Varchar,
+        body -> Text,
+        attempts -> Integer,
+        next_attempt_at -> Datetime,
+        sent_at -> Nullable<Datetime>,
+        last_error -> Nullable<Text>,
+        created_at -> Datetime,
+    }
+}
+
+table! {
+    notification_preferences (username) {
+        username -> Char,
+        email -> Nullable<Varchar>,
+        email_enabled -> Bool,
+        sms_enabled -> Bool,
+        reminders_enabled -> Bool,
+        locale -> Char,
+    }
+}
+
+table! {
+    reschedules (rid) {
+        rid -> Unsigned<Bigint>,
+        username -> Char,
+        from_tid -> Unsigned<Bigint>,
+        to_tid -> Unsigned<Bigint>,
+        time -> Datetime,
+    }
+}
+
+table! {
+    schedule_exceptions (sid, date) {
+        sid -> Unsigned<Bigint>,
+        date -> Date,
+    }
+}
+
+table! {
+    schedules (sid) {
+        sid -> Unsigned<Bigint>,
+        did -> Char,
+        weekday -> Integer,
+        start_time -> Time,
+        end_time -> Time,
+        capacity -> Integer,
+        start_date -> Date,
+        end_date -> Nullable<Date>,
+        dpid -> Nullable<Unsigned<Bigint>>,
+        lcid -> Nullable<Unsigned<Bigint>>,
+    }
+}
+
 table! {
     times (tid) {
         tid -> Unsigned<Bigint>,
@@ -68,6 +218,13 @@ table! {
         end_time -> Datetime,
         capacity -> Integer,
         appointed -> Integer,
+        sid -> Nullable<Unsigned<Bigint>>,
+        issued_number -> Integer,
+        serving_number -> Nullable<Integer>,
+        called_at -> Nullable<Datetime>,
+        available -> Bool,
+        dpid -> Nullable<Unsigned<Bigint>>,
+        lcid -> Nullable<Unsigned<Bigint>>,
     }
 }
 
@@ -76,31 +233,56 @@ table! {
         token -> Char,
         username -> Char,
         login_time -> Datetime,
+        last_active -> Datetime,
+        refresh_token -> Char,
     }
 }
 
 table! {
     users (username) {
         username -> Char,
-        password -> Char,
+        password -> Varchar,
         name -> Char,
         gender -> Char,
         birthday -> Nullable<Date>,
         id_number -> Char,
         telephone -> Char,
         is_banned -> Bool,
+        penalty_cleared_at -> Nullable<Datetime>,
+    }
+}
+
+table! {
+    waitlists (wid) {
+        wid -> Unsigned<Bigint>,
+        username -> Char,
+        tid -> Unsigned<Bigint>,
+        time -> Datetime,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
     admin_logins,
     administrators,
+    appointment_events,
     appointments,
+    comment_replies,
+    comment_reports,
     comments,
     departments,
+    doctor_departments,
+    doctor_leaves,
     doctor_logins,
+    doctor_ratings,
     doctors,
+    locations,
+    notification_outbox,
+    notification_preferences,
+    reschedules,
+    schedule_exceptions,
+    schedules,
     times,
     user_logins,
     users,
+    waitlists,
 );
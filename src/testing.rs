@@ -0,0 +1,121 @@
+use actix::{Actor, Addr};
+use actix_web::web;
+use chrono::{Duration, Utc};
+use diesel::{
+    prelude::*,
+    r2d2::{ConnectionManager, CustomizeConnection},
+    MysqlConnection,
+};
+
+use crate::{
+    config::{Config, EventsConfig},
+    events::Broker,
+    models::{appointments::AppointStatus, comments::CommentStatus, times::NewTime},
+    schedule::last_insert_id,
+    DbPool,
+};
+
+// Helpers for the handler tests. They need a migrated database in `TEST_DATABASE_URL`, so
+// they are ignored by default and run with `cargo test -- --ignored`. Everything runs in a
+// single connection inside a transaction that is never committed, so the handlers' own
+// transactions become savepoints and nothing is left behind.
+
+#[derive(Debug)]
+struct TestTransaction;
+
+impl CustomizeConnection<MysqlConnection, diesel::r2d2::Error> for TestTransaction {
+    fn on_acquire(&self, conn: &mut MysqlConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.begin_test_transaction()
+            .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+pub fn pool() -> web::Data<DbPool> {
+    dotenv::dotenv().ok();
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(TestTransaction))
+        .build(ConnectionManager::<MysqlConnection>::new(url))
+        .expect("Failed to create pool");
+    web::Data::new(pool)
+}
+
+pub fn config() -> web::Data<Config> {
+    web::Data::new(Config::default())
+}
+
+/// A broker of its own, so tests don't see each other's events.
+pub fn broker() -> web::Data<Addr<Broker>> {
+    web::Data::new(Broker::new(&EventsConfig::default()).start())
+}
+
+/// Adds a slot of `did` tomorrow and returns its id.
+pub fn add_time(pool: &DbPool, did: &str) -> u64 {
+    use crate::schema::times;
+
+    let conn = pool.get().unwrap();
+    let start_time = (Utc::now() + Duration::days(1)).naive_local();
+    diesel::insert_into(times::table)
+        .values(&NewTime {
+            did: did.to_string(),
+            start_time,
+            end_time: start_time + Duration::hours(1),
+            capacity: 5,
+            sid: None,
+            dpid: None,
+            lcid: None,
+        })
+        .execute(&conn)
+        .unwrap();
+    diesel::select(last_insert_id)
+        .get_result::<u64>(&conn)
+        .unwrap()
+}
+
+/// Books `tid` for `username`.
+pub fn add_appoint(pool: &DbPool, username: &str, tid: u64) {
+    use crate::schema::{appointments, times};
+
+    let conn = pool.get().unwrap();
+    diesel::insert_into(appointments::table)
+        .values((
+            appointments::username.eq(username),
+            appointments::tid.eq(tid),
+            appointments::status.eq(AppointStatus::Unfinished.as_str()),
+            appointments::queue_number.eq(1),
+        ))
+        .execute(&conn)
+        .unwrap();
+    diesel::update(times::table.filter(times::tid.eq(tid)))
+        .set(times::appointed.eq(times::appointed + 1))
+        .execute(&conn)
+        .unwrap();
+}
+
+/// Adds a visible comment of `username` about `did` and returns its id.
+pub fn add_comment(pool: &DbPool, username: &str, did: &str) -> u64 {
+    use crate::schema::comments;
+
+    let conn = pool.get().unwrap();
+    diesel::insert_into(comments::table)
+        .values((
+            comments::username.eq(username),
+            comments::did.eq(did),
+            comments::comment.eq("test"),
+            comments::status.eq(CommentStatus::Visible.as_str()),
+        ))
+        .execute(&conn)
+        .unwrap();
+    diesel::select(last_insert_id)
+        .get_result::<u64>(&conn)
+        .unwrap()
+}
+
+/// The `ApiError` an impl function failed with.
+pub fn api_error<T>(res: anyhow::Result<T>) -> crate::error::ApiError {
+    match res {
+        Ok(_) => panic!("expected an error"),
+        Err(err) => err.into(),
+    }
+}
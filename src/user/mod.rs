@@ -1,71 +1,119 @@
 mod requests;
 mod responses;
-mod utils;
 
 use crate::{
-    database::{assert, get_db_conn},
+    auth::AuthedUser,
+    booking::{self, Actor},
+    config::Config,
+    database::{assert, get_db_conn, owner},
+    depart,
+    error::ApiError,
+    events::{self, Broker},
+    i18n::Locale,
+    location,
     models::users::UserData,
     models::{
-        appointments::{
-            Appointment, NewAppointment, APPOINT_STATUS_CANCELED, APPOINT_STATUS_FINISHED,
-            APPOINT_STATUS_UNFINISHED,
-        },
-        comments::{Comment, NewComment},
+        appointments::{AppointStatus, Appointment},
+        comments::{Comment, CommentStatus},
         departments::DepartData,
         doctors::DoctorData,
+        reschedules::Reschedule,
         times::TimeData,
-        user_logins::UserLoginData,
         users::UpdateUser,
+        waitlists::WaitlistEntry,
     },
-    protocol::SimpleResponse,
+    notify, outbox,
+    protocol::{SearchDepartResponse, SearchLocationResponse, SimpleResponse},
+    review::{self, DoctorSort},
     DbPool,
 };
-use actix_web::{post, web, HttpResponse, Responder};
+use actix::Addr;
+use actix_web::{post, web, HttpRequest, HttpResponse};
 use anyhow::{self, bail, Context};
-use blake2::{Blake2b, Digest};
 use chrono::{Datelike, NaiveDate, Utc};
 use diesel::prelude::*;
 
-use self::{requests::*, responses::*, utils::get_username_from_token};
+use self::{requests::*, responses::*};
 
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(register)
         .service(login)
         .service(logout)
+        .service(logout_all)
+        .route(
+            "/refresh",
+            web::post().to(crate::auth::refresh::<AuthedUser>),
+        )
+        .route("/events", web::get().to(event_stream))
         .service(view_info)
         .service(modify_password)
         .service(modify_info)
+        .service(view_notify_pref)
+        .service(modify_notify_pref)
         .service(appoint)
         .service(cancel_appoint)
+        .service(reschedule_appoint)
+        .service(search_reschedule)
         .service(comment)
         .service(delete_comment)
+        .service(report_comment)
         .service(search_depart)
         .service(search_doctor)
         .service(search_comment)
         .service(search_time)
-        .service(search_appoint);
+        .service(search_location)
+        .service(search_appoint)
+        .service(join_waitlist)
+        .service(leave_waitlist)
+        .service(search_waitlist);
 }
 
 crate::post_funcs! {
     (register, "/register", RegisterRequest, SimpleResponse),
     (login, "/login", LoginRequest, LoginResponse),
-    (logout, "/logout", LogoutRequest, SimpleResponse),
-    (view_info, "/view_info", ViewInfoRequest, ViewInfoResponse),
-    (modify_password, "/modify_password", ModifyPasswordRequest, SimpleResponse),
-    (modify_info, "/modify_info", ModifyInfoRequest, SimpleResponse),
-    (appoint, "/appoint", AppointRequest, SimpleResponse),
-    (cancel_appoint, "/cancel_appoint", CancelAppointRequest, SimpleResponse),
-    (comment, "/comment", CommentRequest, SimpleResponse),
-    (delete_comment, "/delete_comment", DeleteCommentRequest, SimpleResponse),
+    (logout, "/logout", LogoutRequest, SimpleResponse, AuthedUser),
+    (logout_all, "/logout_all", LogoutAllRequest, SimpleResponse, AuthedUser),
+    (view_info, "/view_info", ViewInfoRequest, ViewInfoResponse, AuthedUser),
+    (modify_password, "/modify_password", ModifyPasswordRequest, SimpleResponse, AuthedUser),
+    (modify_info, "/modify_info", ModifyInfoRequest, SimpleResponse, AuthedUser),
+    (view_notify_pref, "/view_notify_pref", ViewNotifyPrefRequest, ViewNotifyPrefResponse, AuthedUser),
+    (modify_notify_pref, "/modify_notify_pref", ModifyNotifyPrefRequest, SimpleResponse, AuthedUser),
+    (appoint, "/appoint", AppointRequest, SimpleResponse, AuthedUser, events),
+    (cancel_appoint, "/cancel_appoint", CancelAppointRequest, SimpleResponse, AuthedUser, events),
+    (reschedule_appoint, "/reschedule_appoint", RescheduleAppointRequest, SimpleResponse, AuthedUser, events),
+    (search_reschedule, "/search_reschedule", SearchRescheduleRequest, SearchRescheduleResponse, AuthedUser),
+    (comment, "/comment", CommentRequest, CommentResponse, AuthedUser),
+    (delete_comment, "/delete_comment", DeleteCommentRequest, SimpleResponse, AuthedUser),
+    (report_comment, "/report_comment", ReportCommentRequest, SimpleResponse, AuthedUser),
     (search_depart, "/search_depart", SearchDepartRequest, SearchDepartResponse),
     (search_doctor, "/search_doctor", SearchDoctorRequest, SearchDoctorResponse),
     (search_comment, "/search_comment", SearchCommentRequest, SearchCommentResponse),
     (search_time, "/search_time", SearchTimeRequest, SearchTimeResponse),
-    (search_appoint, "/search_appoint", SearchAppointRequest, SearchAppointResponse),
+    (search_location, "/search_location", SearchLocationRequest, SearchLocationResponse),
+    (search_appoint, "/search_appoint", SearchAppointRequest, SearchAppointResponse, AuthedUser),
+    (join_waitlist, "/join_waitlist", JoinWaitlistRequest, JoinWaitlistResponse, AuthedUser),
+    (leave_waitlist, "/leave_waitlist", LeaveWaitlistRequest, SimpleResponse, AuthedUser),
+    (search_waitlist, "/search_waitlist", SearchWaitlistRequest, SearchWaitlistResponse, AuthedUser),
+}
+
+/// Streams `appointment` and `time` events of the user's appointments. Browsers can't set
+/// headers on an `EventSource`, so the login token may also be passed in the query.
+async fn event_stream(
+    pool: web::Data<DbPool>,
+    broker: web::Data<Addr<Broker>>,
+    req: HttpRequest,
+    info: web::Query<EventsRequest>,
+) -> HttpResponse {
+    let token = info.into_inner().login_token;
+    match crate::auth::authenticate::<AuthedUser>(&req, &pool, token).await {
+        Ok(user) => events::stream(&broker, vec![notify::user_topic(&user.username)], vec![]),
+        Err(err) => crate::i18n::localized_response::<SimpleResponse>(&req, Err(err)),
+    }
 }
 
 async fn register_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
     info: web::Json<RegisterRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::users;
@@ -79,9 +127,9 @@ async fn register_impl(
                 .filter(users::username.eq(&info.username))
                 .count()
                 .get_result::<i64>(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
             if res > 0 {
-                bail!("用户名重复");
+                bail!(ApiError::DuplicateUsername);
             }
 
             crate::utils::assert_gender_str(&info.gender)?;
@@ -91,7 +139,7 @@ async fn register_impl(
                 Err(_) => None,
             };
 
-            let hashed_password = format!("{:x}", Blake2b::digest(info.password.as_bytes()));
+            let hashed_password = crate::password::hash_password(&info.password)?;
             let data = UserData {
                 username: info.username,
                 password: hashed_password,
@@ -101,12 +149,13 @@ async fn register_impl(
                 birthday,
                 telephone: info.telephone,
                 is_banned: false,
+                penalty_cleared_at: None,
             };
 
             diesel::insert_into(users::table)
                 .values(data)
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
 
             Ok(())
         })
@@ -118,40 +167,34 @@ async fn register_impl(
 
 async fn login_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
     info: web::Json<LoginRequest>,
 ) -> anyhow::Result<LoginResponse> {
-    use crate::schema::{user_logins, users};
+    use crate::schema::users;
 
     let info = info.into_inner();
     assert::assert_user(&pool, info.username.clone(), true).await?;
 
     let conn = get_db_conn(&pool)?;
-    let login_token = web::block(move || {
-        conn.transaction(|| {
-            let hashed_password = format!("{:x}", Blake2b::digest(info.password.as_bytes()));
-            let res = users::table
+    let (login_token, refresh_token) = web::block(move || {
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let stored_password = users::table
                 .filter(users::username.eq(&info.username))
-                .filter(users::password.eq(&hashed_password))
                 .filter(users::is_banned.eq(false))
-                .count()
-                .get_result::<i64>(&conn)
-                .context("数据库错误")?;
-            if res != 1 {
-                bail!("密码错误")
+                .select(users::password)
+                .get_result::<String>(&conn)
+                .optional()
+                .context(ApiError::Database)?
+                .context(ApiError::WrongPassword)?;
+            if crate::password::verify_password(&info.password, &stored_password)? {
+                let rehashed_password = crate::password::hash_password(&info.password)?;
+                diesel::update(users::table.filter(users::username.eq(&info.username)))
+                    .set(users::password.eq(&rehashed_password))
+                    .execute(&conn)
+                    .context(ApiError::Database)?;
             }
 
-            let login_token = crate::utils::generate_login_token(&info.username, "user");
-            let token_data = UserLoginData {
-                token: login_token.clone(),
-                username: info.username,
-                login_time: Utc::now().naive_utc(),
-            };
-            diesel::insert_into(user_logins::table)
-                .values(token_data)
-                .execute(&conn)
-                .context("数据库错误")?;
-
-            Ok(login_token)
+            crate::auth::create_session::<AuthedUser>(&conn, &info.username)
         })
     })
     .await?;
@@ -160,42 +203,62 @@ async fn login_impl(
         success: true,
         err: "".to_string(),
         login_token,
+        refresh_token,
     })
 }
 
 async fn logout_impl(
     pool: web::Data<DbPool>,
-    info: web::Json<LogoutRequest>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
+    _info: web::Json<LogoutRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::user_logins;
 
-    let info = info.into_inner();
     let conn = get_db_conn(&pool)?;
     web::block(move || {
-        diesel::delete(user_logins::table.filter(user_logins::token.eq(info.login_token)))
+        diesel::delete(user_logins::table.filter(user_logins::token.eq(user.token_hash)))
             .execute(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn logout_all_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
+    _info: web::Json<LogoutAllRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    use crate::schema::user_logins;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || {
+        diesel::delete(user_logins::table.filter(user_logins::username.eq(user.username)))
+            .execute(&conn)
+    })
+    .await
+    .context(ApiError::Database)?;
 
     Ok(SimpleResponse::ok())
 }
 
 async fn view_info_impl(
     pool: web::Data<DbPool>,
-    info: web::Json<ViewInfoRequest>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
+    _info: web::Json<ViewInfoRequest>,
 ) -> anyhow::Result<ViewInfoResponse> {
     use crate::schema::users;
 
-    let info = info.into_inner();
-    let username = get_username_from_token(info.login_token, &pool).await?;
-    assert::assert_user(&pool, username.clone(), true).await?;
-
+    let username = user.username;
     let conn = get_db_conn(&pool)?;
     let res = users::table
         .filter(users::username.eq(&username))
         .get_result::<UserData>(&conn)
-        .context("数据库错误")?;
+        .context(ApiError::Database)?;
 
     let data = ViewInfoResponse {
         success: true,
@@ -215,35 +278,34 @@ async fn view_info_impl(
 
 async fn modify_password_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
     info: web::Json<ModifyPasswordRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::users;
+    use crate::schema::{user_logins, users};
 
     let info = info.into_inner();
-    let username = get_username_from_token(info.login_token.clone(), &pool).await?;
-    assert::assert_user(&pool, username.clone(), true).await?;
+    let username = user.username;
 
     let conn = get_db_conn(&pool)?;
     web::block(move || {
-        conn.transaction(|| {
-            let hashed_password_old =
-                format!("{:x}", Blake2b::digest(info.password_old.as_bytes()));
-            let res = users::table
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            let stored_password = users::table
                 .filter(users::username.eq(&username))
-                .filter(users::password.eq(&hashed_password_old))
-                .count()
-                .get_result::<i64>(&conn)
-                .context("数据库错误")?;
-            if res != 1 {
-                bail!("密码错误");
-            }
+                .select(users::password)
+                .get_result::<String>(&conn)
+                .context(ApiError::Database)?;
+            crate::password::verify_password(&info.password_old, &stored_password)?;
 
-            let hashed_password_new =
-                format!("{:x}", Blake2b::digest(info.password_new.as_bytes()));
+            let hashed_password_new = crate::password::hash_password(&info.password_new)?;
             diesel::update(users::table.filter(users::username.eq(&username)))
                 .set(users::password.eq(&hashed_password_new))
                 .execute(&conn)
-                .context("数据库错误")?;
+                .context(ApiError::Database)?;
+
+            diesel::delete(user_logins::table.filter(user_logins::username.eq(&username)))
+                .execute(&conn)
+                .context(ApiError::Database)?;
 
             Ok(())
         })
@@ -255,13 +317,14 @@ async fn modify_password_impl(
 
 async fn modify_info_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
     info: web::Json<ModifyInfoRequest>,
 ) -> anyhow::Result<SimpleResponse> {
     use crate::schema::users;
 
     let info = info.into_inner();
-    let username = get_username_from_token(info.login_token, &pool).await?;
-    assert::assert_user(&pool, username.clone(), true).await?;
+    let username = user.username;
 
     let mut data = UpdateUser {
         name: info.name,
@@ -271,7 +334,8 @@ async fn modify_info_impl(
         ..Default::default()
     };
     if let Some(birthday) = info.birthday {
-        let birthday = NaiveDate::parse_from_str(&birthday, "%Y-%m-%d").context("生日格式错误")?;
+        let birthday =
+            NaiveDate::parse_from_str(&birthday, "%Y-%m-%d").context(ApiError::BirthdayFormat)?;
         data.birthday = Some(birthday);
     }
 
@@ -282,76 +346,102 @@ async fn modify_info_impl(
             .execute(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     Ok(SimpleResponse::ok())
 }
 
-async fn appoint_impl(
+async fn view_notify_pref_impl(
     pool: web::Data<DbPool>,
-    info: web::Json<AppointRequest>,
-) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::{appointments, times};
+    _config: web::Data<Config>,
+    user: AuthedUser,
+    _info: web::Json<ViewNotifyPrefRequest>,
+) -> anyhow::Result<ViewNotifyPrefResponse> {
+    let username = user.username;
+    let conn = get_db_conn(&pool)?;
+    let pref = web::block(move || outbox::preference(&conn, &username)).await?;
 
-    let info = info.into_inner();
-    let username = get_username_from_token(info.login_token.clone(), &pool).await?;
-    assert::assert_user(&pool, username.clone(), true).await?;
+    Ok(ViewNotifyPrefResponse {
+        success: true,
+        err: "".to_string(),
+        email: pref.email,
+        email_enabled: pref.email_enabled,
+        sms_enabled: pref.sms_enabled,
+        reminders_enabled: pref.reminders_enabled,
+        locale: pref.locale,
+    })
+}
 
-    let tid = info.tid;
-    assert::assert_time(&pool, tid).await?;
+async fn modify_notify_pref_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
+    info: web::Json<ModifyNotifyPrefRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let username = user.username;
+
+    if let Some(email) = &info.email {
+        if !email.is_empty()
+            && (!email.contains('@')
+                || email.contains(|c: char| c.is_whitespace() || c == '<' || c == '>'))
+        {
+            bail!(ApiError::EmailFormat);
+        }
+    }
+    let locale = match &info.locale {
+        Some(locale) => Some(Locale::parse(locale).context(ApiError::LocaleFormat)?),
+        None => None,
+    };
 
-    // check appo
     let conn = get_db_conn(&pool)?;
     web::block(move || {
         conn.transaction(|| {
-            let res = appointments::table
-                .filter(appointments::username.eq(&username))
-                .filter(appointments::tid.eq(tid))
-                .get_results::<Appointment>(&conn)
-                .context("数据库错误")?;
-            if res.len() > 0 && res[0].status != APPOINT_STATUS_CANCELED {
-                bail!("预约已存在");
+            let mut pref = outbox::preference(&conn, &username)?;
+            if let Some(email) = info.email {
+                pref.email = if email.is_empty() { None } else { Some(email) };
             }
-
-            // check time
-            let appo_time = times::table
-                .filter(times::tid.eq(tid))
-                .get_result::<TimeData>(&conn)
-                .context("数据库错误")?;
-            if appo_time.capacity <= appo_time.appointed {
-                bail!("时间段已满");
+            if let Some(email_enabled) = info.email_enabled {
+                pref.email_enabled = email_enabled;
             }
-
-            // insert/update appo
-            if res.is_empty() {
-                let data = NewAppointment {
-                    username,
-                    tid,
-                    status: APPOINT_STATUS_UNFINISHED.to_string(),
-                    time: None,
-                };
-                diesel::insert_into(appointments::table)
-                    .values(data)
-                    .execute(&conn)
-                    .context("数据库错误")?;
-            } else {
-                diesel::update(
-                    appointments::table
-                        .filter(appointments::username.eq(&username))
-                        .filter(appointments::tid.eq(tid)),
-                )
-                .set(appointments::status.eq(APPOINT_STATUS_UNFINISHED))
-                .execute(&conn)
-                .context("数据库错误")?;
+            if let Some(sms_enabled) = info.sms_enabled {
+                pref.sms_enabled = sms_enabled;
+            }
+            if let Some(reminders_enabled) = info.reminders_enabled {
+                pref.reminders_enabled = reminders_enabled;
+            }
+            if let Some(locale) = locale {
+                pref.locale = locale.as_str().to_string();
+            }
+            if pref.email_enabled && pref.email.is_none() {
+                bail!(ApiError::EmailRequired);
             }
+            outbox::save_preference(&conn, &pref)
+        })
+    })
+    .await?;
 
-            // update time
-            diesel::update(times::table.filter(times::tid.eq(tid)))
-                .set(times::appointed.eq(times::appointed + 1))
-                .execute(&conn)
-                .context("数据库错误")?;
+    Ok(SimpleResponse::ok())
+}
 
-            Ok(())
+async fn appoint_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    user: AuthedUser,
+    info: web::Json<AppointRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let username = user.username;
+
+    let tid = info.tid;
+    assert::assert_time(&pool, tid).await?;
+
+    let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
+    web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            booking::book(&conn, &username, tid, &config.booking, &config.penalty)
         })
     })
     .await?;
@@ -361,50 +451,53 @@ async fn appoint_impl(
 
 async fn cancel_appoint_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    user: AuthedUser,
     info: web::Json<CancelAppointRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::{appointments, times};
-
     let info = info.into_inner();
-    let username = get_username_from_token(info.login_token.clone(), &pool).await?;
-    assert::assert_user(&pool, username.clone(), true).await?;
+    let username = user.username;
 
     let tid = info.tid;
     assert::assert_time(&pool, tid).await?;
 
     let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
     web::block(move || {
-        conn.transaction(|| {
-            let res = appointments::table
-                .filter(appointments::username.eq(&username))
-                .filter(appointments::tid.eq(&tid))
-                .get_results::<Appointment>(&conn)
-                .context("数据库错误")?;
-            if res.len() == 0 {
-                bail!("预约不存在");
-            }
-            match res[0].status.as_str() {
-                APPOINT_STATUS_FINISHED => bail!("预约已完成"),
-                APPOINT_STATUS_CANCELED => bail!("预约已取消"),
-                _ => {}
-            }
+        notify::transaction(&conn, &broker, || {
+            booking::cancel(&conn, &username, tid, &config.booking, &config.penalty)
+        })
+    })
+    .await?;
 
-            diesel::update(
-                appointments::table
-                    .filter(appointments::username.eq(&username))
-                    .filter(appointments::tid.eq(tid)),
-            )
-            .set(appointments::status.eq(APPOINT_STATUS_CANCELED))
-            .execute(&conn)
-            .context("数据库错误")?;
+    Ok(SimpleResponse::ok())
+}
 
-            // update times
-            diesel::update(times::table.filter(times::tid.eq(tid)))
-                .set(times::appointed.eq(times::appointed - 1))
-                .execute(&conn)
-                .context("数据库错误")?;
+async fn reschedule_appoint_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    broker: web::Data<Addr<Broker>>,
+    user: AuthedUser,
+    info: web::Json<RescheduleAppointRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let username = user.username;
+    assert::assert_time(&pool, info.from_tid).await?;
+    assert::assert_time(&pool, info.to_tid).await?;
 
-            Ok(())
+    let conn = get_db_conn(&pool)?;
+    let broker = broker.get_ref().clone();
+    web::block(move || {
+        notify::transaction(&conn, &broker, || {
+            booking::reschedule(
+                &conn,
+                &username,
+                info.from_tid,
+                info.to_tid,
+                &config.booking,
+                &config.penalty,
+            )
         })
     })
     .await?;
@@ -412,64 +505,133 @@ async fn cancel_appoint_impl(
     Ok(SimpleResponse::ok())
 }
 
-async fn comment_impl(
+async fn search_reschedule_impl(
     pool: web::Data<DbPool>,
-    info: web::Json<CommentRequest>,
-) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::comments;
+    config: web::Data<Config>,
+    user: AuthedUser,
+    info: web::Json<SearchRescheduleRequest>,
+) -> anyhow::Result<SearchRescheduleResponse> {
+    use crate::schema::reschedules;
 
     let info = info.into_inner();
-    let username = get_username_from_token(info.login_token, &pool).await?;
+    let username = user.username;
+
+    let conn = get_db_conn(&pool)?;
+    let first_index = info.first_index.unwrap_or(0).max(0);
+    let limit = config.pagination.limit(info.limit);
+    let res = web::block(move || {
+        reschedules::table
+            .filter(reschedules::username.eq(&username))
+            .order(reschedules::rid.desc())
+            .offset(first_index)
+            .limit(limit)
+            .select((
+                reschedules::rid,
+                reschedules::from_tid,
+                reschedules::to_tid,
+                reschedules::time,
+            ))
+            .get_results::<Reschedule>(&conn)
+    })
+    .await
+    .context(ApiError::Database)?;
+
+    let res = res
+        .into_iter()
+        .map(|data| SearchRescheduleItem {
+            rid: data.rid,
+            from_tid: data.from_tid,
+            to_tid: data.to_tid,
+            time: crate::utils::format_time_str(&data.time),
+        })
+        .collect();
+
+    Ok(SearchRescheduleResponse {
+        success: true,
+        err: "".to_string(),
+        reschedules: res,
+    })
+}
 
-    assert::assert_user(&pool, username.clone(), true).await?;
+async fn comment_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    user: AuthedUser,
+    info: web::Json<CommentRequest>,
+) -> anyhow::Result<CommentResponse> {
+    let info = info.into_inner();
+    let username = user.username;
     assert::assert_doctor(&pool, info.did.clone()).await?;
 
     let conn = get_db_conn(&pool)?;
-    let data = NewComment {
-        username,
-        did: info.did,
-        comment: info.comment,
-    };
-    web::block(move || {
-        diesel::insert_into(comments::table)
-            .values(data)
-            .execute(&conn)
+    let status = web::block(move || {
+        conn.transaction(|| {
+            review::post(
+                &conn,
+                &username,
+                &info.did,
+                info.tid,
+                info.rating,
+                info.comment,
+                &config.moderation,
+            )
+        })
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
 
-    Ok(SimpleResponse::ok())
+    Ok(CommentResponse {
+        success: true,
+        err: "".to_string(),
+        status: status.as_str().to_string(),
+    })
 }
 
 async fn delete_comment_impl(
     pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
     info: web::Json<DeleteCommentRequest>,
 ) -> anyhow::Result<SimpleResponse> {
-    use crate::schema::comments;
-
     let info = info.into_inner();
-    let username = get_username_from_token(info.login_token, &pool).await?;
-
-    assert::assert_user(&pool, username.clone(), true).await?;
+    let username = user.username;
     assert::assert_comment(&pool, info.cid).await?;
 
     let conn = get_db_conn(&pool)?;
     let cid = info.cid;
     web::block(move || {
-        diesel::delete(comments::table.filter(comments::cid.eq(cid))).execute(&conn)
+        conn.transaction::<_, anyhow::Error, _>(|| {
+            owner::assert_comment_owner(&conn, cid, &username)?;
+            review::delete(&conn, cid, Actor::User(&username))
+        })
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn report_comment_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
+    info: web::Json<ReportCommentRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let info = info.into_inner();
+    let username = user.username;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || {
+        conn.transaction(|| review::report(&conn, info.cid, Actor::User(&username), info.reason))
+    })
+    .await?;
 
     Ok(SimpleResponse::ok())
 }
 
 async fn search_depart_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
     info: web::Json<SearchDepartRequest>,
 ) -> anyhow::Result<SearchDepartResponse> {
-    use crate::schema::departments;
-
     let info = info.into_inner();
     // let username = get_username_from_token(info.login_token, &pool).await?;
     // assert::assert_user(&pool, username, true).await?;
@@ -477,25 +639,9 @@ async fn search_depart_impl(
     let conn = get_db_conn(&pool)?;
     let name_pattern = crate::utils::get_str_pattern_opt(info.depart_name);
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(30).max(0);
-    let departs = web::block(move || {
-        departments::table
-            .filter(departments::depart_name.like(name_pattern))
-            .order(departments::depart_name.asc())
-            .offset(first_index)
-            .limit(limit)
-            .get_results::<DepartData>(&conn)
-    })
-    .await
-    .context("数据库错误")?;
-
-    let departs = departs
-        .into_iter()
-        .map(|data| SearchDepartItem {
-            name: data.depart_name,
-            info: data.information,
-        })
-        .collect();
+    let limit = config.pagination.limit(info.limit);
+    let departs =
+        web::block(move || depart::tree(&conn, &name_pattern, false, first_index, limit)).await?;
 
     Ok(SearchDepartResponse {
         success: true,
@@ -506,45 +652,95 @@ async fn search_depart_impl(
 
 async fn search_doctor_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
     info: web::Json<SearchDoctorRequest>,
 ) -> anyhow::Result<SearchDoctorResponse> {
-    use crate::schema::doctors;
+    use crate::schema::{departments, doctor_departments, doctor_ratings, doctors, times};
 
     let info = info.into_inner();
     // let username = get_username_from_token(info.login_token, &pool).await?;
     // assert::assert_user(&pool, username, true).await?;
+    let sort = DoctorSort::parse(info.sort.as_deref())?;
 
     let conn = get_db_conn(&pool)?;
-    let depart_name_pattern = crate::utils::get_str_pattern_opt(info.depart_name);
+    let depart_name_pattern = info.depart_name.map(crate::utils::get_str_pattern);
     let doctor_name_pattern = crate::utils::get_str_pattern_opt(info.doctor_name);
     let rank = crate::utils::get_str_pattern_opt(info.rank);
+    let (dpid, lcid) = (info.dpid, info.lcid);
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(30).max(0);
-    let docs = web::block(move || {
-        doctors::table
-            .filter(doctors::department.like(depart_name_pattern))
+    let limit = config.pagination.limit(info.limit);
+    let (docs, mut departs) = web::block(move || -> anyhow::Result<_> {
+        let mut query = doctors::table
+            .left_join(doctor_ratings::table.on(doctor_ratings::did.eq(doctors::did)))
             .filter(doctors::name.like(doctor_name_pattern))
             .filter(doctors::rankk.like(rank))
-            .order(doctors::name.asc())
+            .select((
+                doctors::all_columns,
+                (doctor_ratings::rating_total, doctor_ratings::rating_count).nullable(),
+            ))
+            .into_boxed();
+        // any of the departments of the doctor, sub-departments included for `dpid`
+        if let Some(pattern) = depart_name_pattern {
+            let members = doctor_departments::table
+                .inner_join(departments::table.on(departments::dpid.eq(doctor_departments::dpid)))
+                .filter(departments::depart_name.like(pattern))
+                .select(doctor_departments::did);
+            query = query.filter(doctors::did.eq_any(members));
+        }
+        if let Some(dpid) = dpid {
+            let members = doctor_departments::table
+                .filter(doctor_departments::dpid.eq_any(depart::subtree(&conn, dpid)?))
+                .select(doctor_departments::did);
+            query = query.filter(doctors::did.eq_any(members));
+        }
+        // consulting at the campus from now on
+        if let Some(lcid) = lcid {
+            let consulting = times::table
+                .filter(times::lcid.eq(lcid))
+                .filter(times::start_time.gt(Utc::now().naive_utc()))
+                .select(times::did);
+            query = query.filter(doctors::did.eq_any(consulting));
+        }
+        let query = match sort {
+            DoctorSort::Name => query.order(doctors::name.asc()),
+            DoctorSort::Rating => query.order((
+                (doctor_ratings::rating_total / doctor_ratings::rating_count).desc(),
+                doctors::name.asc(),
+            )),
+        };
+        let docs = query
             .offset(first_index)
             .limit(limit)
-            .get_results::<DoctorData>(&conn)
+            .get_results::<(DoctorData, Option<(i32, i32)>)>(&conn)
+            .context(ApiError::Database)?;
+        let dids = docs
+            .iter()
+            .map(|(data, _)| data.did.clone())
+            .collect::<Vec<_>>();
+        let departs = depart::doctor_departs(&conn, &dids)?;
+        Ok((docs, departs))
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
 
     let docs = docs
         .into_iter()
-        .map(|data| SearchDoctorItem {
-            did: data.did,
-            name: data.name,
-            depart: data.department,
-            rank: data.rank,
-            gender: data.gender,
-            age: data
-                .birthday
-                .map_or(-1, |birth| Utc::today().year() - birth.year()),
-            info: data.information,
+        .map(|(data, rating)| {
+            let departments = departs.remove(&data.did).unwrap_or_default();
+            SearchDoctorItem {
+                did: data.did,
+                name: data.name,
+                // the main department comes first
+                depart: departments.first().cloned().unwrap_or_default(),
+                departments,
+                rank: data.rank,
+                gender: data.gender,
+                age: data
+                    .birthday
+                    .map_or(-1, |birth| Utc::today().year() - birth.year()),
+                info: data.information,
+                rating: review::average(rating),
+                review_count: rating.map_or(0, |(_, count)| count),
+            }
         })
         .collect();
 
@@ -557,6 +753,7 @@ async fn search_doctor_impl(
 
 async fn search_comment_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
     info: web::Json<SearchCommentRequest>,
 ) -> anyhow::Result<SearchCommentResponse> {
     use crate::schema::comments;
@@ -571,18 +768,22 @@ async fn search_comment_impl(
 
     let conn = get_db_conn(&pool)?;
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(30).max(0);
-    let cmts = web::block(move || {
-        comments::table
+    let limit = config.pagination.limit(info.limit);
+    let (cmts, mut replies) = web::block(move || -> anyhow::Result<_> {
+        let cmts = comments::table
             .filter(comments::did.eq(did))
+            .filter(comments::status.eq(CommentStatus::Visible.as_str()))
             .filter(comments::time.between(start_time, end_time))
             .order(comments::time.desc())
             .offset(first_index)
             .limit(limit)
             .get_results::<Comment>(&conn)
+            .context(ApiError::Database)?;
+        let cids = cmts.iter().map(|data| data.cid).collect::<Vec<_>>();
+        let replies = review::replies(&conn, &cids)?;
+        Ok((cmts, replies))
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
 
     let cmts = cmts
         .into_iter()
@@ -591,6 +792,9 @@ async fn search_comment_impl(
             username: data.username,
             comment: data.comment,
             time: crate::utils::format_time_str(&data.time),
+            tid: data.tid,
+            rating: data.rating,
+            reply: replies.remove(&data.cid),
         })
         .collect();
 
@@ -603,9 +807,10 @@ async fn search_comment_impl(
 
 async fn search_time_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
     info: web::Json<SearchTimeRequest>,
 ) -> anyhow::Result<SearchTimeResponse> {
-    use crate::schema::{doctors, times};
+    use crate::schema::{departments, doctors, times};
 
     let info = info.into_inner();
     // let username = get_username_from_token(info.login_token, &pool).await?;
@@ -614,35 +819,57 @@ async fn search_time_impl(
     let (start_time, end_time) = crate::utils::get_time_pair_from_date_opt(info.date)?;
 
     let doctor_name_pattern = crate::utils::get_str_pattern_opt(info.doctor_name);
+    let (dpid, lcid) = (info.dpid, info.lcid);
 
     let conn = get_db_conn(&pool)?;
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(30).max(0);
-    let tms = web::block(move || {
-        times::table
+    let limit = config.pagination.limit(info.limit);
+    let tms = web::block(move || -> anyhow::Result<_> {
+        let mut query = times::table
             .filter(times::start_time.ge(&start_time))
             .filter(times::end_time.le(&end_time))
+            .filter(times::available.eq(true))
             .inner_join(doctors::table.on(times::did.eq(doctors::did)))
+            .inner_join(
+                departments::table
+                    .on(departments::dpid.eq(depart::slot_or_main(times::dpid, doctors::dpid))),
+            )
             .filter(doctors::name.like(doctor_name_pattern))
+            .into_boxed();
+        if let Some(dpid) = dpid {
+            query = query.filter(times::dpid.eq_any(depart::subtree(&conn, dpid)?));
+        }
+        if let Some(lcid) = lcid {
+            query = query.filter(times::lcid.eq(lcid));
+        }
+        query
             .order(times::start_time.asc())
             .offset(first_index)
             .limit(limit)
-            .get_results::<(TimeData, DoctorData)>(&conn)
+            .get_results::<(TimeData, DoctorData, DepartData)>(&conn)
+            .context(ApiError::Database)
     })
-    .await
-    .context("数据库错误")?;
+    .await?;
 
     let tms = tms
         .into_iter()
-        .map(|(time_data, doctor_data)| SearchTimeItem {
+        .map(|(time_data, doctor_data, depart_data)| SearchTimeItem {
             tid: time_data.tid,
             date: time_data.start_time.date().format("%Y-%m-%d").to_string(),
-            time: crate::utils::get_time_str(&time_data.start_time, &time_data.end_time).to_owned(),
+            time: crate::utils::get_period(
+                &time_data.start_time,
+                &time_data.end_time,
+                &config.clinic,
+            ),
+            start_time: crate::utils::format_time_str(&time_data.start_time),
+            end_time: crate::utils::format_time_str(&time_data.end_time),
             did: doctor_data.did,
             doctor_name: doctor_data.name,
-            doctor_depart: doctor_data.department,
+            doctor_depart: depart_data.depart_name,
             capacity: time_data.capacity,
             rest: time_data.capacity - time_data.appointed,
+            dpid: time_data.dpid,
+            lcid: time_data.lcid,
         })
         .collect();
 
@@ -653,55 +880,85 @@ async fn search_time_impl(
     })
 }
 
+async fn search_location_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    info: web::Json<SearchLocationRequest>,
+) -> anyhow::Result<SearchLocationResponse> {
+    let info = info.into_inner();
+
+    let conn = get_db_conn(&pool)?;
+    let name_pattern = crate::utils::get_str_pattern_opt(info.name);
+    let locs = web::block(move || location::search(&conn, &name_pattern)).await?;
+
+    Ok(SearchLocationResponse {
+        success: true,
+        err: "".to_string(),
+        locations: locs,
+    })
+}
+
 async fn search_appoint_impl(
     pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    user: AuthedUser,
     info: web::Json<SearchAppointRequest>,
 ) -> anyhow::Result<SearchAppointResponse> {
-    use crate::schema::{appointments, doctors, times};
+    use crate::schema::{appointments, departments, doctors, times};
 
     let info = info.into_inner();
-    let username = get_username_from_token(info.login_token, &pool).await?;
-    assert::assert_user(&pool, username.clone(), true).await?;
+    let username = user.username;
 
     let (start_time, end_time) =
         crate::utils::parse_time_pair_str_opt(info.start_time, info.end_time)?;
 
     let conn = get_db_conn(&pool)?;
     let first_index = info.first_index.unwrap_or(0).max(0);
-    let limit = info.limit.unwrap_or(30).max(0);
-    let status = if info.status.is_empty() {
-        APPOINT_STATUS_UNFINISHED.to_string()
-    } else {
-        info.status
-    };
+    let limit = config.pagination.limit(info.limit);
+    let status = AppointStatus::parse_filter(&info.status)?;
+    let all_status = status.is_none();
+    let status = status.unwrap_or_default().as_str();
     let appos = web::block(move || {
         appointments::table
             .filter(appointments::username.eq(&username))
-            .filter((appointments::status.eq(&status)).or(&status == "所有"))
+            .filter(appointments::status.eq(status).or(all_status))
             .inner_join(times::table.on(appointments::tid.eq(times::tid)))
             .filter(times::start_time.ge(&start_time))
             .filter(times::end_time.le(&end_time))
             .inner_join(doctors::table.on(times::did.eq(doctors::did)))
+            .inner_join(
+                departments::table
+                    .on(departments::dpid.eq(depart::slot_or_main(times::dpid, doctors::dpid))),
+            )
             .order(times::start_time.desc())
             .offset(first_index)
             .limit(limit)
-            .get_results::<(Appointment, TimeData, DoctorData)>(&conn)
+            .get_results::<(Appointment, TimeData, DoctorData, DepartData)>(&conn)
     })
     .await
-    .context("数据库错误")?;
+    .context(ApiError::Database)?;
 
     let appos = appos
         .into_iter()
-        .map(|(appo_data, time_data, doctor_data)| SearchAppointItem {
-            did: doctor_data.did,
-            doctor_name: doctor_data.name,
-            doctor_depart: doctor_data.department,
-            tid: time_data.tid,
-            date: time_data.start_time.date().format("%Y-%m-%d").to_string(),
-            time: crate::utils::get_time_str(&time_data.start_time, &time_data.end_time).to_owned(),
-            status: appo_data.status,
-            appo_time: crate::utils::format_time_str(&appo_data.time),
-        })
+        .map(
+            |(appo_data, time_data, doctor_data, depart_data)| SearchAppointItem {
+                did: doctor_data.did,
+                doctor_name: doctor_data.name,
+                doctor_depart: depart_data.depart_name,
+                tid: time_data.tid,
+                date: time_data.start_time.date().format("%Y-%m-%d").to_string(),
+                time: crate::utils::get_period(
+                    &time_data.start_time,
+                    &time_data.end_time,
+                    &config.clinic,
+                ),
+                start_time: crate::utils::format_time_str(&time_data.start_time),
+                end_time: crate::utils::format_time_str(&time_data.end_time),
+                status: AppointStatus::parse(&appo_data.status).unwrap_or_default(),
+                appo_time: crate::utils::format_time_str(&appo_data.time),
+                queue_number: appo_data.queue_number,
+            },
+        )
         .collect();
 
     Ok(SearchAppointResponse {
@@ -710,3 +967,132 @@ async fn search_appoint_impl(
         appointments: appos,
     })
 }
+
+async fn join_waitlist_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    user: AuthedUser,
+    info: web::Json<JoinWaitlistRequest>,
+) -> anyhow::Result<JoinWaitlistResponse> {
+    let username = user.username;
+    let tid = info.tid;
+    assert::assert_time(&pool, tid).await?;
+
+    let conn = get_db_conn(&pool)?;
+    let position = web::block(move || {
+        conn.transaction(|| {
+            booking::join_waitlist(&conn, &username, tid, &config.booking, &config.penalty)
+        })
+    })
+    .await?;
+
+    Ok(JoinWaitlistResponse {
+        success: true,
+        err: "".to_string(),
+        position,
+    })
+}
+
+async fn leave_waitlist_impl(
+    pool: web::Data<DbPool>,
+    _config: web::Data<Config>,
+    user: AuthedUser,
+    info: web::Json<LeaveWaitlistRequest>,
+) -> anyhow::Result<SimpleResponse> {
+    let username = user.username;
+    let tid = info.tid;
+
+    let conn = get_db_conn(&pool)?;
+    web::block(move || booking::leave_waitlist(&conn, &username, tid)).await?;
+
+    Ok(SimpleResponse::ok())
+}
+
+async fn search_waitlist_impl(
+    pool: web::Data<DbPool>,
+    config: web::Data<Config>,
+    user: AuthedUser,
+    _info: web::Json<SearchWaitlistRequest>,
+) -> anyhow::Result<SearchWaitlistResponse> {
+    use crate::schema::{departments, doctors, times, waitlists};
+
+    let username = user.username;
+
+    let conn = get_db_conn(&pool)?;
+    let entries = web::block(move || {
+        waitlists::table
+            .filter(waitlists::username.eq(&username))
+            .inner_join(times::table.on(waitlists::tid.eq(times::tid)))
+            .inner_join(doctors::table.on(times::did.eq(doctors::did)))
+            .inner_join(
+                departments::table
+                    .on(departments::dpid.eq(depart::slot_or_main(times::dpid, doctors::dpid))),
+            )
+            .order(times::start_time.asc())
+            .get_results::<(WaitlistEntry, TimeData, DoctorData, DepartData)>(&conn)
+            .context(ApiError::Database)?
+            .into_iter()
+            .map(|(entry, time_data, doctor_data, depart_data)| {
+                let position = booking::position(&conn, &username, entry.tid)?;
+                Ok((entry, position, time_data, doctor_data, depart_data))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()
+    })
+    .await?;
+
+    let entries = entries
+        .into_iter()
+        .map(
+            |(entry, position, time_data, doctor_data, depart_data)| SearchWaitlistItem {
+                tid: time_data.tid,
+                did: doctor_data.did,
+                doctor_name: doctor_data.name,
+                doctor_depart: depart_data.depart_name,
+                date: time_data.start_time.date().format("%Y-%m-%d").to_string(),
+                time: crate::utils::get_period(
+                    &time_data.start_time,
+                    &time_data.end_time,
+                    &config.clinic,
+                ),
+                start_time: crate::utils::format_time_str(&time_data.start_time),
+                end_time: crate::utils::format_time_str(&time_data.end_time),
+                position,
+                join_time: crate::utils::format_time_str(&entry.time),
+            },
+        )
+        .collect();
+
+    Ok(SearchWaitlistResponse {
+        success: true,
+        err: "".to_string(),
+        waitlist: entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[actix_rt::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn delete_comment_of_other_user() {
+        let pool = testing::pool();
+        let cid = testing::add_comment(&pool, "user_b", "doctor_a");
+
+        let res = delete_comment_impl(
+            pool,
+            testing::config(),
+            AuthedUser {
+                username: "user_a".to_string(),
+                token_hash: String::new(),
+            },
+            web::Json(DeleteCommentRequest {
+                login_token: None,
+                cid,
+            }),
+        )
+        .await;
+        assert_eq!(testing::api_error(res), ApiError::Forbidden);
+    }
+}
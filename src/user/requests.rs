@@ -22,24 +22,34 @@ pub struct LoginRequest {
 
 #[derive(Deserialize)]
 pub struct LogoutRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LogoutAllRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct ViewInfoRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct ModifyPasswordRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub password_old: String,
     pub password_new: String,
 }
 
 #[derive(Deserialize)]
 pub struct ModifyInfoRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub name: Option<String>,
     pub gender: Option<String>,
     pub id_number: Option<String>,
@@ -49,26 +59,78 @@ pub struct ModifyInfoRequest {
 
 #[derive(Deserialize)]
 pub struct AppointRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub tid: u64,
 }
 
 #[derive(Deserialize)]
 pub struct CancelAppointRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub tid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct RescheduleAppointRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub from_tid: u64,
+    pub to_tid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct SearchRescheduleRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub first_index: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct JoinWaitlistRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub tid: u64,
+}
+
+#[derive(Deserialize)]
+pub struct LeaveWaitlistRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub tid: u64,
 }
 
+#[derive(Deserialize)]
+pub struct SearchWaitlistRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct CommentRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub did: String,
+    /// The finished appointment being reviewed.
+    pub tid: u64,
+    /// 1 to 5 stars.
+    pub rating: i32,
     pub comment: String,
 }
 
+#[derive(Deserialize)]
+pub struct ReportCommentRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    pub cid: u64,
+    pub reason: String,
+}
+
 #[derive(Deserialize)]
 pub struct DeleteCommentRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub cid: u64,
 }
 
@@ -84,8 +146,14 @@ pub struct SearchDepartRequest {
 pub struct SearchDoctorRequest {
     // pub login_token: String,
     pub depart_name: Option<String>,
+    /// Doctors consulting in the department or one of its sub-departments.
+    pub dpid: Option<u64>,
+    /// Doctors with upcoming slots at the campus.
+    pub lcid: Option<u64>,
     pub doctor_name: Option<String>,
     pub rank: Option<String>,
+    /// `name` or `rating`.
+    pub sort: Option<String>,
     pub first_index: Option<i64>,
     pub limit: Option<i64>,
 }
@@ -105,13 +173,23 @@ pub struct SearchTimeRequest {
     // pub login_token: String,
     pub doctor_name: Option<String>,
     pub date: Option<String>,
+    /// Slots of the department or one of its sub-departments.
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
     pub first_index: Option<i64>,
     pub limit: Option<i64>,
 }
 
+#[derive(Deserialize)]
+pub struct SearchLocationRequest {
+    // pub login_token: String,
+    pub name: Option<String>,
+}
+
 #[derive(Deserialize)]
 pub struct SearchAppointRequest {
-    pub login_token: String,
+    #[serde(default)]
+    pub login_token: Option<String>,
     pub start_time: Option<String>,
     pub end_time: Option<String>,
     #[serde(default = "search_appoint_request_status_default")]
@@ -121,5 +199,31 @@ pub struct SearchAppointRequest {
 }
 
 fn search_appoint_request_status_default() -> String {
-    crate::models::appointments::APPOINT_STATUS_UNFINISHED.to_string()
+    crate::models::appointments::AppointStatus::Unfinished
+        .as_str()
+        .to_string()
+}
+
+#[derive(Deserialize)]
+pub struct EventsRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ViewNotifyPrefRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ModifyNotifyPrefRequest {
+    #[serde(default)]
+    pub login_token: Option<String>,
+    /// An empty string removes the address.
+    pub email: Option<String>,
+    pub email_enabled: Option<bool>,
+    pub sms_enabled: Option<bool>,
+    pub reminders_enabled: Option<bool>,
+    pub locale: Option<String>,
 }
@@ -1,3 +1,5 @@
+use crate::models::{appointments::AppointStatus, times::Period};
+use crate::protocol::ReplyItem;
 use serde::Serialize;
 
 #[derive(Default, Serialize)]
@@ -5,6 +7,7 @@ pub struct LoginResponse {
     pub success: bool,
     pub err: String,
     pub login_token: String,
+    pub refresh_token: String,
 }
 
 #[derive(Default, Serialize)]
@@ -19,28 +22,20 @@ pub struct ViewInfoResponse {
     pub telephone: String,
 }
 
-#[derive(Default, Serialize)]
-pub struct SearchDepartItem {
-    pub name: String,
-    pub info: String,
-}
-
-#[derive(Default, Serialize)]
-pub struct SearchDepartResponse {
-    pub success: bool,
-    pub err: String,
-    pub departments: Vec<SearchDepartItem>,
-}
-
 #[derive(Default, Serialize)]
 pub struct SearchDoctorItem {
     pub did: String,
     pub name: String,
     pub depart: String,
+    /// All departments of the doctor, the main one first.
+    pub departments: Vec<String>,
     pub rank: String,
     pub gender: String,
     pub age: i32,
     pub info: String,
+    /// Average of the ratings, unset before the first review.
+    pub rating: Option<f64>,
+    pub review_count: i32,
 }
 
 #[derive(Default, Serialize)]
@@ -56,6 +51,9 @@ pub struct SearchCommentItem {
     pub username: String,
     pub comment: String,
     pub time: String,
+    pub tid: Option<u64>,
+    pub rating: Option<i32>,
+    pub reply: Option<ReplyItem>,
 }
 
 #[derive(Default, Serialize)]
@@ -69,12 +67,17 @@ pub struct SearchCommentResponse {
 pub struct SearchTimeItem {
     pub tid: u64,
     pub date: String,
-    pub time: String,
+    pub time: Period,
+    pub start_time: String,
+    pub end_time: String,
     pub did: String,
     pub doctor_name: String,
     pub doctor_depart: String,
     pub capacity: i32,
     pub rest: i32,
+    /// The department and campus of the slot.
+    pub dpid: Option<u64>,
+    pub lcid: Option<u64>,
 }
 
 #[derive(Default, Serialize)]
@@ -91,9 +94,12 @@ pub struct SearchAppointItem {
     pub doctor_depart: String,
     pub tid: u64,
     pub date: String,
-    pub time: String,
-    pub status: String,
+    pub time: Period,
+    pub start_time: String,
+    pub end_time: String,
+    pub status: AppointStatus,
     pub appo_time: String,
+    pub queue_number: i32,
 }
 
 #[derive(Default, Serialize)]
@@ -103,12 +109,65 @@ pub struct SearchAppointResponse {
     pub appointments: Vec<SearchAppointItem>,
 }
 
-crate::impl_err_response! {
-    LoginResponse,
-    ViewInfoResponse,
-    SearchDepartResponse,
-    SearchDoctorResponse,
-    SearchCommentResponse,
-    SearchTimeResponse,
-    SearchAppointResponse,
+#[derive(Default, Serialize)]
+pub struct SearchRescheduleItem {
+    pub rid: u64,
+    pub from_tid: u64,
+    pub to_tid: u64,
+    pub time: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchRescheduleResponse {
+    pub success: bool,
+    pub err: String,
+    pub reschedules: Vec<SearchRescheduleItem>,
+}
+
+#[derive(Default, Serialize)]
+pub struct JoinWaitlistResponse {
+    pub success: bool,
+    pub err: String,
+    /// 1 for the head of the waitlist.
+    pub position: i64,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchWaitlistItem {
+    pub tid: u64,
+    pub did: String,
+    pub doctor_name: String,
+    pub doctor_depart: String,
+    pub date: String,
+    pub time: Period,
+    pub start_time: String,
+    pub end_time: String,
+    pub position: i64,
+    pub join_time: String,
+}
+
+#[derive(Default, Serialize)]
+pub struct SearchWaitlistResponse {
+    pub success: bool,
+    pub err: String,
+    pub waitlist: Vec<SearchWaitlistItem>,
+}
+
+#[derive(Default, Serialize)]
+pub struct ViewNotifyPrefResponse {
+    pub success: bool,
+    pub err: String,
+    pub email: Option<String>,
+    pub email_enabled: bool,
+    pub sms_enabled: bool,
+    pub reminders_enabled: bool,
+    pub locale: String,
+}
+
+/// `status` is `visible`, or `pending` while the comment waits for a moderator.
+#[derive(Default, Serialize)]
+pub struct CommentResponse {
+    pub success: bool,
+    pub err: String,
+    pub status: String,
 }
@@ -1,38 +1,91 @@
 #[macro_export]
 macro_rules! post_funcs {
-    ( $( ( $func_name:ident, $url:expr, $request:ty, $response:ty ) ),+ $(,)? ) => {
-        $(
-            paste::paste! {
-                #[post($url)]
-                async fn $func_name(
-                    pool: web::Data<DbPool>,
-                    info: web::Json<$request>
-                ) -> impl Responder {
-                    let response = match [<$func_name _impl>](pool, info).await {
-                        Ok(response) => response,
-                        Err(err) => $response::err(err.to_string()),
+    (@handler $func_name:ident, $url:expr, $request:ty, $response:ty) => {
+        paste::paste! {
+            #[post($url)]
+            async fn $func_name(
+                pool: web::Data<DbPool>,
+                config: web::Data<$crate::config::Config>,
+                req: HttpRequest,
+                info: web::Json<$request>
+            ) -> HttpResponse {
+                let result: anyhow::Result<$response> =
+                    [<$func_name _impl>](pool, config, info).await;
+                $crate::i18n::localized_response(&req, result)
+            }
+        }
+    };
+    (@handler $func_name:ident, $url:expr, $request:ty, $response:ty, $auth:ty) => {
+        paste::paste! {
+            #[post($url)]
+            async fn $func_name(
+                pool: web::Data<DbPool>,
+                config: web::Data<$crate::config::Config>,
+                req: HttpRequest,
+                mut info: web::Json<$request>
+            ) -> HttpResponse {
+                let body_token = info.login_token.take();
+                let result: anyhow::Result<$response> =
+                    match $crate::auth::authenticate::<$auth>(&req, &pool, body_token).await {
+                        Ok(principal) => [<$func_name _impl>](pool, config, principal, info).await,
+                        Err(err) => Err(err),
+                    };
+                $crate::i18n::localized_response(&req, result)
+            }
+        }
+    };
+    // `events` handlers also get the event broker, to publish their changes.
+    (@handler $func_name:ident, $url:expr, $request:ty, $response:ty, $auth:ty, events) => {
+        paste::paste! {
+            #[post($url)]
+            async fn $func_name(
+                pool: web::Data<DbPool>,
+                config: web::Data<$crate::config::Config>,
+                broker: web::Data<actix::Addr<$crate::events::Broker>>,
+                req: HttpRequest,
+                mut info: web::Json<$request>
+            ) -> HttpResponse {
+                let body_token = info.login_token.take();
+                let result: anyhow::Result<$response> =
+                    match $crate::auth::authenticate::<$auth>(&req, &pool, body_token).await {
+                        Ok(principal) => {
+                            [<$func_name _impl>](pool, config, broker, principal, info).await
+                        }
+                        Err(err) => Err(err),
                     };
-                    HttpResponse::Ok().json(response)
-                }
+                $crate::i18n::localized_response(&req, result)
             }
+        }
+    };
+    ( $( ( $func_name:ident, $url:expr, $request:ty, $response:ty $(, $auth:ty $(, $events:ident)?)? ) ),+ $(,)? ) => {
+        $(
+            $crate::post_funcs!(@handler $func_name, $url, $request, $response $(, $auth $(, $events)?)?);
         )+
     };
 }
 
 use anyhow::{bail, Context};
 use blake2::{Blake2b, Digest};
-use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc};
+use chrono::{DateTime, NaiveDateTime};
+use rand_core::{OsRng, RngCore};
+
+use crate::{config::ClinicConfig, error::ApiError, models::times::Period};
 
 pub fn assert_gender_str(gender: &str) -> anyhow::Result<()> {
     if gender != "男" && gender != "女" {
-        bail!("性别格式错误")
+        bail!(ApiError::GenderFormat)
     }
     Ok(())
 }
 
-pub fn generate_login_token(id: &str, tag: &str) -> String {
-    let temp_str = format!("{}@{}@{}", id, tag, Utc::now());
-    format!("{:x}", Blake2b::digest(temp_str.as_bytes()))
+pub fn generate_login_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn hash_login_token(token: &str) -> String {
+    format!("{:x}", Blake2b::digest(token.as_bytes()))
 }
 
 pub fn parse_time_str<S: AsRef<str>>(s: S) -> anyhow::Result<NaiveDateTime> {
@@ -41,10 +94,10 @@ pub fn parse_time_str<S: AsRef<str>>(s: S) -> anyhow::Result<NaiveDateTime> {
 
     let s = s.as_ref();
     if let Some('Z') = s.chars().last() {
-        NaiveDateTime::parse_from_str(s.as_ref(), TIME_FMT_SPECIAL).context("未知错误")
+        NaiveDateTime::parse_from_str(s.as_ref(), TIME_FMT_SPECIAL).context(ApiError::TimeFormat)
     } else {
         DateTime::parse_from_str(s.as_ref(), TIME_FMT)
-            .context("未知错误")
+            .context(ApiError::TimeFormat)
             .map(|t| t.naive_utc())
     }
 }
@@ -56,10 +109,10 @@ pub fn parse_time_pair_str_opt<S1: AsRef<str>, S2: AsRef<str>>(
     let time_min = parse_time_str("1901-01-01T00:00:00.0000Z")?;
     let time_max = parse_time_str("2901-01-01T00:00:00.0000Z")?;
     let start_time = start_time.map_or(Ok(time_min.clone()), |t| {
-        crate::utils::parse_time_str(t).context("起始时间格式错误")
+        crate::utils::parse_time_str(t).context(ApiError::StartTimeFormat)
     })?;
     let end_time = end_time.map_or(Ok(time_max.clone()), |t| {
-        crate::utils::parse_time_str(t).context("结束时间格式错误")
+        crate::utils::parse_time_str(t).context(ApiError::EndTimeFormat)
     })?;
     Ok((start_time, end_time))
 }
@@ -68,8 +121,8 @@ pub fn parse_time_pair_str<S1: AsRef<str>, S2: AsRef<str>>(
     start_time: S1,
     end_time: S2,
 ) -> anyhow::Result<(NaiveDateTime, NaiveDateTime)> {
-    let start_time = parse_time_str(start_time).context("起始时间格式错误")?;
-    let end_time = parse_time_str(end_time).context("结束时间格式错误")?;
+    let start_time = parse_time_str(start_time).context(ApiError::StartTimeFormat)?;
+    let end_time = parse_time_str(end_time).context(ApiError::EndTimeFormat)?;
     Ok((start_time, end_time))
 }
 
@@ -79,7 +132,8 @@ pub fn get_time_pair_from_date_opt<S: AsRef<str>>(
     if let Some(date) = date {
         let start_time_str = format!("{}T00:00:00+00:00", date.as_ref());
         let end_time_str = format!("{}T23:59:59+00:00", date.as_ref());
-        crate::utils::parse_time_pair_str(start_time_str, end_time_str).context("日期格式错误")
+        crate::utils::parse_time_pair_str(start_time_str, end_time_str)
+            .context(ApiError::DateFormat)
     } else {
         crate::utils::parse_time_pair_str_opt::<String, String>(None, None)
     }
@@ -102,26 +156,28 @@ pub fn get_str_pattern_opt<S: AsRef<str>>(s: Option<S>) -> String {
     }
 }
 
-pub fn get_time_from_str(date: &str, time: &str) -> anyhow::Result<(NaiveDateTime, NaiveDateTime)> {
-    match time {
-        crate::models::times::TIME_AM => {
-            let start_time_str = format!("{}T09:00:00+00:00", date);
-            let end_time_str = format!("{}T11:00:00+00:00", date);
-            crate::utils::parse_time_pair_str(start_time_str, end_time_str).context("日期格式错误")
-        }
-        crate::models::times::TIME_PM => {
-            let start_time_str = format!("{}T15:00:00+00:00", date);
-            let end_time_str = format!("{}T17:00:00+00:00", date);
-            crate::utils::parse_time_pair_str(start_time_str, end_time_str).context("日期格式错误")
-        }
-        _ => Err(anyhow::anyhow!("时间格式错误")),
-    }
+pub fn get_time_from_str(
+    date: &str,
+    time: &str,
+    clinic: &ClinicConfig,
+) -> anyhow::Result<(NaiveDateTime, NaiveDateTime)> {
+    let (start, end) = match Period::parse(time)? {
+        Period::Am => (clinic.am_start, clinic.am_end),
+        Period::Pm => (clinic.pm_start, clinic.pm_end),
+    };
+    let start_time_str = format!("{}T{}+00:00", date, start.format("%H:%M:%S"));
+    let end_time_str = format!("{}T{}+00:00", date, end.format("%H:%M:%S"));
+    crate::utils::parse_time_pair_str(start_time_str, end_time_str).context(ApiError::DateFormat)
 }
 
-pub fn get_time_str(_start_time: &NaiveDateTime, end_time: &NaiveDateTime) -> &'static str {
-    if end_time.time() >= NaiveTime::from_hms(12, 0, 0) {
-        crate::models::times::TIME_PM
+pub fn get_period(
+    start_time: &NaiveDateTime,
+    _end_time: &NaiveDateTime,
+    clinic: &ClinicConfig,
+) -> Period {
+    if start_time.time() >= clinic.am_end {
+        Period::Pm
     } else {
-        crate::models::times::TIME_AM
+        Period::Am
     }
 }